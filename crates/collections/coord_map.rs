@@ -0,0 +1,413 @@
+use alloc::vec::Vec;
+use core::hash::{BuildHasher, BuildHasherDefault, Hasher};
+
+use hashbrown::{HashMap, hash_map::Entry};
+
+/// Signed 3D coordinate usable as a [`CoordMap`] key.
+pub trait Coord3: Copy {
+    fn to_xyz(self) -> [i32; 3];
+
+    fn from_xyz(xyz: [i32; 3]) -> Self;
+}
+
+impl Coord3 for [i32; 3] {
+    #[inline]
+    fn to_xyz(self) -> [i32; 3] {
+        self
+    }
+
+    #[inline]
+    fn from_xyz(xyz: [i32; 3]) -> Self {
+        xyz
+    }
+}
+
+impl Coord3 for (i32, i32, i32) {
+    #[inline]
+    fn to_xyz(self) -> [i32; 3] {
+        [self.0, self.1, self.2]
+    }
+
+    #[inline]
+    fn from_xyz([x, y, z]: [i32; 3]) -> Self {
+        (x, y, z)
+    }
+}
+
+/// Offsets of the 26 cells surrounding a coordinate, in Y-Z-X order.
+pub const NEIGHBORS_26: [[i32; 3]; 26] = {
+    let mut offsets = [[0; 3]; 26];
+    let mut i = 0;
+    let mut y = -1;
+    while y <= 1 {
+        let mut z = -1;
+        while z <= 1 {
+            let mut x = -1;
+            while x <= 1 {
+                if x != 0 || y != 0 || z != 0 {
+                    offsets[i] = [x, y, z];
+                    i += 1;
+                }
+                x += 1;
+            }
+            z += 1;
+        }
+        y += 1;
+    }
+    offsets
+};
+
+/// Hasher for small integer coordinates; mixes each component with a large odd multiplier.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CoordHasher {
+    hash: u64,
+}
+
+impl CoordHasher {
+    const SEED: u64 = 0x51_7c_c1_b7_27_22_0a_95;
+}
+
+impl Hasher for CoordHasher {
+    #[inline]
+    fn finish(&self) -> u64 {
+        // Fold high bits down since hash tables index with the low bits.
+        self.hash ^ (self.hash >> 32)
+    }
+
+    #[inline]
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.write_u8(*byte);
+        }
+    }
+
+    #[inline]
+    fn write_u8(&mut self, i: u8) {
+        self.write_u64(i as u64);
+    }
+
+    #[inline]
+    fn write_u32(&mut self, i: u32) {
+        self.write_u64(i as u64);
+    }
+
+    #[inline]
+    fn write_i32(&mut self, i: i32) {
+        self.write_u64(i as u32 as u64);
+    }
+
+    #[inline]
+    fn write_u64(&mut self, i: u64) {
+        self.hash = (self.hash.rotate_left(5) ^ i).wrapping_mul(Self::SEED);
+    }
+
+    #[inline]
+    fn write_usize(&mut self, i: usize) {
+        self.write_u64(i as u64);
+    }
+}
+
+pub type BuildCoordHasher = BuildHasherDefault<CoordHasher>;
+
+// Tuples hash per component, unlike arrays which hash as bytes.
+type MapKey = (i32, i32, i32);
+
+#[inline]
+fn map_key<K: Coord3>(key: K) -> MapKey {
+    let [x, y, z] = key.to_xyz();
+    (x, y, z)
+}
+
+/// Hash map keyed by signed 3D coordinates.
+///
+/// Entries are kept in a dense list, so iteration order only depends on the sequence of
+/// insertions and removals. Removal moves the last entry into the freed slot.
+#[derive(Clone, Debug)]
+pub struct CoordMap<K, V, S = BuildCoordHasher> {
+    map: HashMap<MapKey, usize, S>,
+    entries: Vec<(K, V)>,
+}
+
+impl<K, V, S> CoordMap<K, V, S> {
+    pub const fn with_hasher(hash_builder: S) -> Self {
+        Self {
+            map: HashMap::with_hasher(hash_builder),
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.map.clear();
+        self.entries.clear();
+    }
+
+    pub fn iter(&self) -> impl ExactSizeIterator<Item = (K, &V)> + Clone
+    where
+        K: Copy,
+    {
+        self.entries.iter().map(|(k, v)| (*k, v))
+    }
+
+    pub fn iter_mut(&mut self) -> impl ExactSizeIterator<Item = (K, &mut V)>
+    where
+        K: Copy,
+    {
+        self.entries.iter_mut().map(|(k, v)| (*k, v))
+    }
+
+    pub fn keys(&self) -> impl ExactSizeIterator<Item = K> + Clone
+    where
+        K: Copy,
+    {
+        self.entries.iter().map(|(k, _)| *k)
+    }
+
+    pub fn values(&self) -> impl ExactSizeIterator<Item = &V> + Clone {
+        self.entries.iter().map(|(_, v)| v)
+    }
+}
+
+impl<K, V, S> Default for CoordMap<K, V, S>
+where
+    S: Default,
+{
+    fn default() -> Self {
+        Self::with_hasher(S::default())
+    }
+}
+
+impl<K, V> CoordMap<K, V> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            map: HashMap::with_capacity_and_hasher(capacity, Default::default()),
+            entries: Vec::with_capacity(capacity),
+        }
+    }
+}
+
+impl<K, V, S> CoordMap<K, V, S>
+where
+    K: Coord3,
+    S: BuildHasher,
+{
+    #[inline]
+    pub fn index_of(&self, key: K) -> Option<usize> {
+        self.map.get(&map_key(key)).copied()
+    }
+
+    #[inline]
+    pub fn contains_key(&self, key: K) -> bool {
+        self.map.contains_key(&map_key(key))
+    }
+
+    #[inline]
+    pub fn get(&self, key: K) -> Option<&V> {
+        let index = self.index_of(key)?;
+        Some(&self.entries[index].1)
+    }
+
+    #[inline]
+    pub fn get_mut(&mut self, key: K) -> Option<&mut V> {
+        let index = self.index_of(key)?;
+        Some(&mut self.entries[index].1)
+    }
+
+    /// Inserts a value, returning the previous value at `key`.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        match self.map.entry(map_key(key)) {
+            Entry::Occupied(occupied) => {
+                let slot = &mut self.entries[*occupied.get()].1;
+                Some(core::mem::replace(slot, value))
+            }
+            Entry::Vacant(vacant) => {
+                vacant.insert(self.entries.len());
+                self.entries.push((key, value));
+                None
+            }
+        }
+    }
+
+    pub fn get_or_insert_with(&mut self, key: K, f: impl FnOnce() -> V) -> &mut V {
+        let index = match self.map.entry(map_key(key)) {
+            Entry::Occupied(occupied) => *occupied.get(),
+            Entry::Vacant(vacant) => {
+                let index = self.entries.len();
+                vacant.insert(index);
+                self.entries.push((key, f()));
+                index
+            }
+        };
+        &mut self.entries[index].1
+    }
+
+    /// Removes the value at `key`; the last entry takes its place in iteration order.
+    pub fn remove(&mut self, key: K) -> Option<V> {
+        let index = self.map.remove(&map_key(key))?;
+        let (_, value) = self.entries.swap_remove(index);
+        if let Some((moved, _)) = self.entries.get(index) {
+            *self.map.get_mut(&map_key(*moved)).unwrap() = index;
+        }
+        Some(value)
+    }
+
+    pub fn retain(&mut self, mut f: impl FnMut(K, &mut V) -> bool) {
+        let mut index = 0;
+        while index < self.entries.len() {
+            let (key, value) = &mut self.entries[index];
+            if f(*key, value) {
+                index += 1;
+            } else {
+                let key = *key;
+                self.remove(key);
+            }
+        }
+    }
+
+    /// Gets the 26 surrounding values, ordered like [`NEIGHBORS_26`].
+    pub fn get_neighbors_26(&self, key: K) -> [Option<&V>; 26] {
+        let [x, y, z] = key.to_xyz();
+        NEIGHBORS_26.map(|[dx, dy, dz]| {
+            let neighbor = (x.wrapping_add(dx), y.wrapping_add(dy), z.wrapping_add(dz));
+            let index = self.map.get(&neighbor)?;
+            Some(&self.entries[*index].1)
+        })
+    }
+
+    /// Iterates over entries within the inclusive box `min..=max`, in map order.
+    pub fn iter_box(&self, min: K, max: K) -> impl Iterator<Item = (K, &V)> {
+        let min = min.to_xyz();
+        let max = max.to_xyz();
+        let volume = (0..3)
+            .map(|i| (max[i] as i64 - min[i] as i64 + 1).max(0) as u64)
+            .fold(1u64, |acc, len| acc.saturating_mul(len));
+
+        // Probe each cell when the box is smaller than the map.
+        let probed = if volume < self.len() as u64 {
+            let mut indices = Vec::new();
+            for y in min[1]..=max[1] {
+                for z in min[2]..=max[2] {
+                    for x in min[0]..=max[0] {
+                        if let Some(index) = self.map.get(&(x, y, z)) {
+                            indices.push(*index);
+                        }
+                    }
+                }
+            }
+            indices.sort_unstable();
+            Some(indices)
+        } else {
+            None
+        };
+
+        let scanned = match probed {
+            Some(_) => None,
+            None => Some(self.entries.iter().filter(move |(key, _)| {
+                let xyz = key.to_xyz();
+                (0..3).all(|i| min[i] <= xyz[i] && xyz[i] <= max[i])
+            })),
+        };
+
+        let probed = probed
+            .into_iter()
+            .flatten()
+            .map(|index| &self.entries[index]);
+
+        probed
+            .chain(scanned.into_iter().flatten())
+            .map(|(k, v)| (*k, v))
+    }
+
+    /// Iterates over entries within euclidean distance `radius` of `center`, in map order.
+    pub fn iter_radius(&self, center: K, radius: u32) -> impl Iterator<Item = (K, &V)> {
+        let [cx, cy, cz] = center.to_xyz();
+        let r = radius.min(i32::MAX as u32) as i32;
+        let min = K::from_xyz([cx.saturating_sub(r), cy.saturating_sub(r), cz.saturating_sub(r)]);
+        let max = K::from_xyz([cx.saturating_add(r), cy.saturating_add(r), cz.saturating_add(r)]);
+
+        let radius_sq = (radius as i64) * (radius as i64);
+        self.iter_box(min, max).filter(move |(key, _)| {
+            let [x, y, z] = key.to_xyz();
+            let dx = x as i64 - cx as i64;
+            let dy = y as i64 - cy as i64;
+            let dz = z as i64 - cz as i64;
+            dx * dx + dy * dy + dz * dz <= radius_sq
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn insert_remove() {
+        let mut map = CoordMap::new();
+        assert_eq!(map.insert([0, 0, 0], 'a'), None);
+        assert_eq!(map.insert([-1, 2, -3], 'b'), None);
+        assert_eq!(map.insert([5, 5, 5], 'c'), None);
+        assert_eq!(map.insert([-1, 2, -3], 'B'), Some('b'));
+        assert_eq!(map.len(), 3);
+
+        assert_eq!(map.remove([0, 0, 0]), Some('a'));
+        assert_eq!(map.remove([0, 0, 0]), None);
+        assert_eq!(map.get([5, 5, 5]), Some(&'c'));
+        assert_eq!(map.get([-1, 2, -3]), Some(&'B'));
+
+        let order: Vec<_> = map.values().copied().collect();
+        assert_eq!(order, ['c', 'B']);
+    }
+
+    #[test]
+    fn neighbors() {
+        let mut map = CoordMap::new();
+        for offset in NEIGHBORS_26 {
+            map.insert(offset, offset);
+        }
+        map.insert([0, 0, 0], [0, 0, 0]);
+
+        let neighbors = map.get_neighbors_26([0, 0, 0]);
+        for (i, offset) in NEIGHBORS_26.iter().enumerate() {
+            assert_eq!(neighbors[i], Some(offset));
+        }
+
+        let corner = map.get_neighbors_26([1, 1, 1]);
+        assert_eq!(corner.iter().filter(|n| n.is_some()).count(), 7);
+    }
+
+    #[test]
+    fn boxes() {
+        let mut map = CoordMap::new();
+        for x in -8..8 {
+            for y in -8..8 {
+                for z in -8..8 {
+                    map.insert((x, y, z), ());
+                }
+            }
+        }
+
+        // Small box is probed, large box is scanned.
+        assert_eq!(map.iter_box((-1, -1, -1), (1, 1, 1)).count(), 27);
+        assert_eq!(map.iter_box((-100, 0, 0), (100, 0, 0)).count(), 16);
+        assert_eq!(map.iter_box((1, 0, 0), (0, 0, 0)).count(), 0);
+
+        let keys: Vec<_> = map.iter_box((0, 0, 0), (1, 1, 1)).map(|(k, _)| k).collect();
+        let mut sorted = keys.clone();
+        sorted.sort_by_key(|k| map.index_of(*k));
+        assert_eq!(keys, sorted);
+
+        assert_eq!(map.iter_radius((0, 0, 0), 1).count(), 7);
+        assert_eq!(map.iter_radius((0, 0, 0), 0).count(), 1);
+    }
+}
@@ -1,11 +1,13 @@
-use std::collections::hash_map::Entry;
-use std::hash::{BuildHasher, Hash, RandomState};
+use alloc::vec::Vec;
+use core::hash::{BuildHasher, Hash};
 
+use hashbrown::{HashMap, hash_map::Entry};
 use num_traits::NumCast;
-use std::collections::HashMap;
 
-#[derive(Debug)]
-pub struct IndexMap<V, I, S = RandomState> {
+use crate::DefaultHashBuilder;
+
+#[derive(Clone, Debug)]
+pub struct IndexMap<V, I, S = DefaultHashBuilder> {
     pub map: HashMap<V, I, S>,
     pub list: Vec<V>,
 }
@@ -50,20 +52,26 @@ where
         self.list.get(index)
     }
 
-    pub fn entry(&mut self, key: V) -> Entry<'_, V, I> {
+    pub fn entry(&mut self, key: V) -> Entry<'_, V, I, S> {
         self.map.entry(key)
     }
 
-    pub fn index_or_add(&mut self, key: V) -> (&I, bool) {
+    pub fn index_or_add(&mut self, key: V) -> (&I, bool)
+    where
+        V: Clone,
+    {
         let next_index = self.get_next_index();
         match self.map.entry(key) {
             Entry::Occupied(occupied) => (occupied.into_mut(), false),
-            Entry::Vacant(vacant) => (vacant.insert(next_index), true),
+            Entry::Vacant(vacant) => {
+                self.list.push(vacant.key().clone());
+                (vacant.insert(next_index), true)
+            }
         }
     }
 
     pub fn get_next_index(&self) -> I {
         // TODO: replace panic with Result+error
-        I::from(self.len() - 1).expect("index overflow")
+        I::from(self.len()).expect("index overflow")
     }
 }
@@ -1,8 +1,22 @@
 #![feature(associated_type_defaults)]
 #![feature(new_range_api)]
+#![cfg_attr(not(feature = "std"), no_std)]
 
+extern crate alloc;
+
+mod coord_map;
 mod index_map;
 mod subslice;
 
+pub use coord_map::*;
 pub use index_map::*;
 pub use subslice::*;
+
+pub use hashbrown::hash_map;
+
+/// Hasher used by default for [`IndexMap`]: std's randomly seeded SipHash
+/// when available, hashbrown's default hasher otherwise.
+#[cfg(feature = "std")]
+pub type DefaultHashBuilder = std::hash::RandomState;
+#[cfg(not(feature = "std"))]
+pub type DefaultHashBuilder = hashbrown::DefaultHashBuilder;
@@ -1,4 +1,4 @@
-use std::{ops, range};
+use core::{ops, range};
 
 pub trait OwnedCut<I>: Sized {
     type Output = Self;
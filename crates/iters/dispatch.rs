@@ -0,0 +1,235 @@
+use core::sync::atomic::{AtomicU8, Ordering};
+
+/// Instruction set tiers used to pick SIMD lane counts at runtime.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq, PartialOrd, Ord)]
+pub enum SimdLevel {
+    Scalar = 1,
+    Sse2,
+    Neon,
+    Avx2,
+    Avx512,
+}
+
+const UNDETECTED: u8 = 0;
+
+static CURRENT: AtomicU8 = AtomicU8::new(UNDETECTED);
+
+impl SimdLevel {
+    pub const ALL: [SimdLevel; 5] = [
+        SimdLevel::Scalar,
+        SimdLevel::Sse2,
+        SimdLevel::Neon,
+        SimdLevel::Avx2,
+        SimdLevel::Avx512,
+    ];
+
+    /// Queries the CPU for the best supported level.
+    ///
+    /// Without `std` the CPU can't be queried, so only the features enabled
+    /// at compile time are used.
+    pub fn detect() -> Self {
+        #[cfg(all(feature = "std", any(target_arch = "x86", target_arch = "x86_64")))]
+        {
+            if is_x86_feature_detected!("avx512f") && is_x86_feature_detected!("avx512bw") {
+                return SimdLevel::Avx512;
+            }
+            if is_x86_feature_detected!("avx2") {
+                return SimdLevel::Avx2;
+            }
+            if is_x86_feature_detected!("sse2") {
+                return SimdLevel::Sse2;
+            }
+        }
+
+        #[cfg(all(not(feature = "std"), any(target_arch = "x86", target_arch = "x86_64")))]
+        {
+            if cfg!(all(target_feature = "avx512f", target_feature = "avx512bw")) {
+                return SimdLevel::Avx512;
+            }
+            if cfg!(target_feature = "avx2") {
+                return SimdLevel::Avx2;
+            }
+            if cfg!(target_feature = "sse2") {
+                return SimdLevel::Sse2;
+            }
+        }
+
+        #[cfg(target_arch = "aarch64")]
+        {
+            return SimdLevel::Neon;
+        }
+
+        #[allow(unreachable_code)]
+        SimdLevel::Scalar
+    }
+
+    /// The level used by [`dispatch`], detected on first use.
+    #[inline]
+    pub fn current() -> Self {
+        match Self::from_u8(CURRENT.load(Ordering::Relaxed)) {
+            Some(level) => level,
+            None => {
+                core::hint::cold_path();
+                let level = Self::detect();
+                CURRENT.store(level as u8, Ordering::Relaxed);
+                level
+            }
+        }
+    }
+
+    /// Overrides the level used by [`dispatch`], or restores detection with `None`.
+    ///
+    /// The level is clamped to what the CPU supports; returns the level now in effect.
+    pub fn force(level: Option<SimdLevel>) -> Self {
+        let detected = Self::detect();
+        let level = match level {
+            Some(level) => level.min(detected),
+            None => detected,
+        };
+        CURRENT.store(level as u8, Ordering::Relaxed);
+        level
+    }
+
+    /// Width of a native vector register in bytes.
+    pub const fn vector_bytes(self) -> usize {
+        match self {
+            SimdLevel::Scalar => 0,
+            SimdLevel::Sse2 | SimdLevel::Neon => 16,
+            SimdLevel::Avx2 => 32,
+            SimdLevel::Avx512 => 64,
+        }
+    }
+
+    /// Lane count picked for elements of type `T`.
+    pub const fn lanes<T>(self) -> usize {
+        let lanes = self.vector_bytes() / size_of::<T>();
+        if lanes == 0 { 1 } else { lanes }
+    }
+
+    const fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            1 => Some(SimdLevel::Scalar),
+            2 => Some(SimdLevel::Sse2),
+            3 => Some(SimdLevel::Neon),
+            4 => Some(SimdLevel::Avx2),
+            5 => Some(SimdLevel::Avx512),
+            _ => None,
+        }
+    }
+}
+
+/// A computation that is generic over the SIMD lane count.
+///
+/// `run` should be `#[inline]` so it is compiled with the target features of the caller,
+/// as should everything it calls into for the SIMD work. LLVM may ignore the hint for
+/// large bodies, which then fall back to baseline code; use `#[inline(always)]` there.
+pub trait LaneKernel {
+    /// Element type whose size decides the lane count.
+    type Elem;
+
+    type Output;
+
+    fn run<const N: usize>(self) -> Self::Output;
+}
+
+/// Runs `kernel` with the lane count best suited for [`SimdLevel::current`].
+#[inline]
+pub fn dispatch<K: LaneKernel>(kernel: K) -> K::Output {
+    // SAFETY: levels above the detected one are never stored.
+    unsafe { dispatch_at(SimdLevel::current(), kernel) }
+}
+
+/// Runs `kernel` with the lane count best suited for `level`, ignoring [`SimdLevel::current`].
+///
+/// # Safety
+///
+/// `level` must not be above [`SimdLevel::detect`].
+#[inline]
+pub unsafe fn dispatch_at<K: LaneKernel>(level: SimdLevel, kernel: K) -> K::Output {
+    match level {
+        SimdLevel::Scalar => kernel.run::<1>(),
+
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        // SAFETY: the caller guarantees the level is supported.
+        SimdLevel::Sse2 => unsafe { run_sse2(kernel) },
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        // SAFETY: the caller guarantees the level is supported.
+        SimdLevel::Avx2 => unsafe { run_avx2(kernel) },
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        // SAFETY: the caller guarantees the level is supported.
+        SimdLevel::Avx512 => unsafe { run_avx512(kernel) },
+
+        #[allow(unreachable_patterns)]
+        _ => run_128(kernel),
+    }
+}
+
+macro_rules! impl_run_width {
+    ($name:ident, $n8:literal, $n16:literal, $n32:literal, $n64:literal) => {
+        #[inline(always)]
+        fn $name<K: LaneKernel>(kernel: K) -> K::Output {
+            match size_of::<K::Elem>() {
+                1 => kernel.run::<$n8>(),
+                2 => kernel.run::<$n16>(),
+                4 => kernel.run::<$n32>(),
+                8 => kernel.run::<$n64>(),
+                _ => kernel.run::<1>(),
+            }
+        }
+    };
+}
+
+impl_run_width!(run_128, 16, 8, 4, 2);
+impl_run_width!(run_256, 32, 16, 8, 4);
+impl_run_width!(run_512, 64, 32, 16, 8);
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "sse2")]
+fn run_sse2<K: LaneKernel>(kernel: K) -> K::Output {
+    run_128(kernel)
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "avx2")]
+fn run_avx2<K: LaneKernel>(kernel: K) -> K::Output {
+    run_256(kernel)
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "avx512f,avx512bw")]
+fn run_avx512<K: LaneKernel>(kernel: K) -> K::Output {
+    run_512(kernel)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Lanes<T>(std::marker::PhantomData<T>);
+
+    impl<T> LaneKernel for Lanes<T> {
+        type Elem = T;
+        type Output = usize;
+
+        fn run<const N: usize>(self) -> usize {
+            N
+        }
+    }
+
+    fn lanes<T>(level: SimdLevel) -> usize {
+        // SAFETY: callers clamp the level to the detected one.
+        unsafe { dispatch_at(level, Lanes::<T>(std::marker::PhantomData)) }
+    }
+
+    // Leaves `CURRENT` alone so tests running in parallel don't see a forced level.
+    #[test]
+    fn levels() {
+        let detected = SimdLevel::detect();
+        for level in SimdLevel::ALL.map(|level| level.min(detected)) {
+            assert_eq!(lanes::<u8>(level), level.lanes::<u8>());
+            assert_eq!(lanes::<u32>(level), level.lanes::<u32>());
+            assert_eq!(lanes::<u64>(level), level.lanes::<u64>());
+        }
+    }
+}
@@ -1,4 +1,6 @@
 #![feature(portable_simd)]
-#![feature(test)]
+#![feature(cold_path)]
+#![cfg_attr(not(feature = "std"), no_std)]
 
+pub mod dispatch;
 pub mod search;
@@ -1,23 +1,93 @@
-use std::simd::{LaneCount, Mask, Simd, SimdElement, SupportedLaneCount, cmp::SimdPartialEq};
+use core::{
+    iter::FusedIterator,
+    simd::{Mask, Simd, SimdElement, cmp::SimdPartialEq},
+};
+
+use crate::dispatch::LaneKernel;
 
 pub trait SliceSearch<T> {
     type Index;
 
+    fn index_of<const N: usize>(&self, value: T) -> Option<Self::Index>
+    where
+        T: SimdElement + PartialEq,
+        Simd<T, N>: SimdPartialEq<Mask = Mask<T::Mask, N>>;
+
     fn index_of_any_except<const N: usize>(&self, value: T) -> Option<Self::Index>
     where
         T: SimdElement + PartialEq,
-        LaneCount<N>: SupportedLaneCount,
+        Simd<T, N>: SimdPartialEq<Mask = Mask<T::Mask, N>>;
+
+    fn last_index_of_any_except<const N: usize>(&self, value: T) -> Option<Self::Index>
+    where
+        T: SimdElement + PartialEq,
+        Simd<T, N>: SimdPartialEq<Mask = Mask<T::Mask, N>>;
+
+    fn count_eq<const N: usize>(&self, value: T) -> usize
+    where
+        T: SimdElement + PartialEq,
+        Simd<T, N>: SimdPartialEq<Mask = Mask<T::Mask, N>>;
+
+    fn all_eq<const N: usize>(&self, value: T) -> bool
+    where
+        T: SimdElement + PartialEq,
+        Simd<T, N>: SimdPartialEq<Mask = Mask<T::Mask, N>>;
+
+    /// Iterates over runs of equal values as `(value, start, len)`.
+    fn runs<const N: usize>(&self) -> Runs<'_, T, N>
+    where
+        T: SimdElement + PartialEq,
+        Simd<T, N>: SimdPartialEq<Mask = Mask<T::Mask, N>>;
+
+    /// Index of the first value other than the one at the same index of `other`,
+    /// within the length of the shorter slice.
+    fn index_of_mismatch<const N: usize>(&self, other: &[T]) -> Option<Self::Index>
+    where
+        T: SimdElement + PartialEq,
+        Simd<T, N>: SimdPartialEq<Mask = Mask<T::Mask, N>>;
+
+    /// Index of the first value equal to the one at the same index of `other`,
+    /// within the length of the shorter slice.
+    fn index_of_match<const N: usize>(&self, other: &[T]) -> Option<Self::Index>
+    where
+        T: SimdElement + PartialEq,
         Simd<T, N>: SimdPartialEq<Mask = Mask<T::Mask, N>>;
 }
 
 impl<T> SliceSearch<T> for [T] {
     type Index = usize;
 
+    #[inline]
+    fn index_of<const N: usize>(&self, value: T) -> Option<Self::Index>
+    where
+        T: SimdElement + PartialEq,
+        Simd<T, N>: SimdPartialEq<Mask = Mask<T::Mask, N>>,
+    {
+        let (prefix, suffix) = self.as_chunks::<N>();
+        let broad = Simd::splat(value);
+        let first = prefix
+            .iter()
+            .position(|v| Simd::from_array(*v).simd_eq(broad).any());
+
+        if let Some(found) = first {
+            let equals = Simd::from_array(prefix[found]).simd_eq(broad).to_bitmask();
+            let vec_index = equals.trailing_zeros();
+            let offset = found * N;
+            return Some(offset + vec_index as usize);
+        }
+
+        if let Some(found) = suffix.iter().position(|v| *v == value) {
+            let offset = prefix.len() * N;
+            return Some(offset + found);
+        }
+
+        return None;
+    }
+
     #[inline]
     fn index_of_any_except<const N: usize>(&self, value: T) -> Option<Self::Index>
     where
         T: SimdElement + PartialEq,
-        LaneCount<N>: SupportedLaneCount,
         Simd<T, N>: SimdPartialEq<Mask = Mask<T::Mask, N>>,
     {
         let (prefix, suffix) = self.as_chunks::<N>();
@@ -27,7 +97,7 @@ impl<T> SliceSearch<T> for [T] {
             .position(|v| Simd::from_array(*v).simd_ne(broad).any());
 
         if let Some(found) = first {
-            let not_equals = Simd::from_array(prefix[found]).simd_ne(broad).to_bitmask() as u32;
+            let not_equals = Simd::from_array(prefix[found]).simd_ne(broad).to_bitmask();
             let vec_index = not_equals.trailing_zeros();
             let offset = found * N;
             return Some(offset + vec_index as usize);
@@ -40,18 +110,281 @@ impl<T> SliceSearch<T> for [T] {
 
         return None;
     }
+
+    #[inline]
+    fn last_index_of_any_except<const N: usize>(&self, value: T) -> Option<Self::Index>
+    where
+        T: SimdElement + PartialEq,
+        Simd<T, N>: SimdPartialEq<Mask = Mask<T::Mask, N>>,
+    {
+        // Remainder is at the front, so the scalar path runs last.
+        let (prefix, suffix) = self.as_rchunks::<N>();
+        let broad = Simd::splat(value);
+        let last = suffix
+            .iter()
+            .rposition(|v| Simd::from_array(*v).simd_ne(broad).any());
+
+        if let Some(found) = last {
+            let not_equals = Simd::from_array(suffix[found]).simd_ne(broad).to_bitmask();
+            let vec_index = u64::BITS - 1 - not_equals.leading_zeros();
+            let offset = prefix.len() + found * N;
+            return Some(offset + vec_index as usize);
+        }
+
+        return prefix.iter().rposition(|v| *v != value);
+    }
+
+    #[inline]
+    fn count_eq<const N: usize>(&self, value: T) -> usize
+    where
+        T: SimdElement + PartialEq,
+        Simd<T, N>: SimdPartialEq<Mask = Mask<T::Mask, N>>,
+    {
+        let (prefix, suffix) = self.as_chunks::<N>();
+        let broad = Simd::splat(value);
+
+        let mut count = 0;
+        for v in prefix {
+            let equals = Simd::from_array(*v).simd_eq(broad).to_bitmask();
+            count += equals.count_ones() as usize;
+        }
+        count + suffix.iter().filter(|v| **v == value).count()
+    }
+
+    #[inline]
+    fn all_eq<const N: usize>(&self, value: T) -> bool
+    where
+        T: SimdElement + PartialEq,
+        Simd<T, N>: SimdPartialEq<Mask = Mask<T::Mask, N>>,
+    {
+        self.index_of_any_except::<N>(value).is_none()
+    }
+
+    #[inline]
+    fn runs<const N: usize>(&self) -> Runs<'_, T, N>
+    where
+        T: SimdElement + PartialEq,
+        Simd<T, N>: SimdPartialEq<Mask = Mask<T::Mask, N>>,
+    {
+        Runs {
+            rest: self,
+            start: 0,
+        }
+    }
+
+    #[inline]
+    fn index_of_mismatch<const N: usize>(&self, other: &[T]) -> Option<Self::Index>
+    where
+        T: SimdElement + PartialEq,
+        Simd<T, N>: SimdPartialEq<Mask = Mask<T::Mask, N>>,
+    {
+        index_of_pair::<T, N>(self, other, false)
+    }
+
+    #[inline]
+    fn index_of_match<const N: usize>(&self, other: &[T]) -> Option<Self::Index>
+    where
+        T: SimdElement + PartialEq,
+        Simd<T, N>: SimdPartialEq<Mask = Mask<T::Mask, N>>,
+    {
+        index_of_pair::<T, N>(self, other, true)
+    }
+}
+
+/// Index of the first pair of values at the same index of `a` and `b` that are
+/// `equal`, or not.
+#[inline]
+fn index_of_pair<T, const N: usize>(a: &[T], b: &[T], equal: bool) -> Option<usize>
+where
+    T: SimdElement + PartialEq,
+    Simd<T, N>: SimdPartialEq<Mask = Mask<T::Mask, N>>,
+{
+    let len = a.len().min(b.len());
+    let (a_prefix, a_suffix) = a[..len].as_chunks::<N>();
+    let (b_prefix, b_suffix) = b[..len].as_chunks::<N>();
+    let found = |a: &[T; N], b: &[T; N]| {
+        let equals = Simd::from_array(*a).simd_eq(Simd::from_array(*b));
+        return if equal { equals } else { !equals };
+    };
+    let first = a_prefix
+        .iter()
+        .zip(b_prefix)
+        .position(|(a, b)| found(a, b).any());
+
+    if let Some(found_at) = first {
+        let mask = found(&a_prefix[found_at], &b_prefix[found_at]).to_bitmask();
+        let vec_index = mask.trailing_zeros();
+        return Some(found_at * N + vec_index as usize);
+    }
+
+    let mut suffix = a_suffix.iter().zip(b_suffix);
+    if let Some(found_at) = suffix.position(|(a, b)| (a == b) == equal) {
+        return Some(a_prefix.len() * N + found_at);
+    }
+
+    return None;
+}
+
+/// Iterator over runs of equal values in a slice, created by [`SliceSearch::runs`].
+#[derive(Clone, Debug)]
+pub struct Runs<'a, T, const N: usize> {
+    rest: &'a [T],
+    start: usize,
+}
+
+impl<'a, T, const N: usize> Iterator for Runs<'a, T, N>
+where
+    T: SimdElement + PartialEq,
+    Simd<T, N>: SimdPartialEq<Mask = Mask<T::Mask, N>>,
+{
+    type Item = (T, usize, usize);
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        let value = *self.rest.first()?;
+
+        // Move ahead while there are duplicates in the source.
+        let len = self.rest.index_of_any_except::<N>(value);
+        let len = len.unwrap_or(self.rest.len()); // Rest of source is same value when None
+
+        let start = self.start;
+        self.rest = &self.rest[len..];
+        self.start += len;
+        Some((value, start, len))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.rest.len();
+        (len.min(1), Some(len))
+    }
+}
+
+impl<'a, T, const N: usize> FusedIterator for Runs<'a, T, N>
+where
+    T: SimdElement + PartialEq,
+    Simd<T, N>: SimdPartialEq<Mask = Mask<T::Mask, N>>,
+{
+}
+
+macro_rules! search_kernel {
+    ($(#[$attr:meta])* $name:ident, $method:ident, $output:ty) => {
+        $(#[$attr])*
+        #[derive(Clone, Copy, Debug)]
+        pub struct $name<'a, T> {
+            pub slice: &'a [T],
+            pub value: T,
+        }
+
+        search_kernel!(@impl $name, $method, $output, u8, u16, u32, u64, i8, i16, i32, i64);
+    };
+    (@impl $name:ident, $method:ident, $output:ty, $($ty:ty),*) => {
+        $(
+            impl LaneKernel for $name<'_, $ty> {
+                type Elem = $ty;
+                type Output = $output;
+
+                #[inline]
+                fn run<const N: usize>(self) -> Self::Output {
+                    self.slice.$method::<N>(self.value)
+                }
+            }
+        )*
+    };
+}
+
+search_kernel!(
+    /// Kernel for [`SliceSearch::index_of`], see [`dispatch`](crate::dispatch::dispatch).
+    IndexOf, index_of, Option<usize>
+);
+search_kernel!(
+    /// Kernel for [`SliceSearch::index_of_any_except`], see [`dispatch`](crate::dispatch::dispatch).
+    IndexOfAnyExcept, index_of_any_except, Option<usize>
+);
+search_kernel!(
+    /// Kernel for [`SliceSearch::last_index_of_any_except`], see [`dispatch`](crate::dispatch::dispatch).
+    LastIndexOfAnyExcept, last_index_of_any_except, Option<usize>
+);
+search_kernel!(
+    /// Kernel for [`SliceSearch::count_eq`], see [`dispatch`](crate::dispatch::dispatch).
+    CountEq, count_eq, usize
+);
+search_kernel!(
+    /// Kernel for [`SliceSearch::all_eq`], see [`dispatch`](crate::dispatch::dispatch).
+    AllEq, all_eq, bool
+);
+
+/// Kernel calling `f` with every run of [`SliceSearch::runs`] as `(value, start, len)`,
+/// see [`dispatch`](crate::dispatch::dispatch).
+#[derive(Clone, Copy, Debug)]
+pub struct ForEachRun<'a, T, F> {
+    pub slice: &'a [T],
+    pub f: F,
 }
 
+macro_rules! for_each_run_kernel {
+    ($($ty:ty),*) => {
+        $(
+            impl<F: FnMut($ty, usize, usize)> LaneKernel for ForEachRun<'_, $ty, F> {
+                type Elem = $ty;
+                type Output = ();
+
+                #[inline]
+                fn run<const N: usize>(mut self) -> Self::Output {
+                    for (value, start, len) in self.slice.runs::<N>() {
+                        (self.f)(value, start, len);
+                    }
+                }
+            }
+        )*
+    };
+}
+
+for_each_run_kernel!(u8, u16, u32, u64, i8, i16, i32, i64);
+
+macro_rules! pair_kernel {
+    ($(#[$attr:meta])* $name:ident, $method:ident) => {
+        $(#[$attr])*
+        #[derive(Clone, Copy, Debug)]
+        pub struct $name<'a, T> {
+            pub slice: &'a [T],
+            pub other: &'a [T],
+        }
+
+        pair_kernel!(@impl $name, $method, u8, u16, u32, u64, i8, i16, i32, i64);
+    };
+    (@impl $name:ident, $method:ident, $($ty:ty),*) => {
+        $(
+            impl LaneKernel for $name<'_, $ty> {
+                type Elem = $ty;
+                type Output = Option<usize>;
+
+                #[inline]
+                fn run<const N: usize>(self) -> Self::Output {
+                    self.slice.$method::<N>(self.other)
+                }
+            }
+        )*
+    };
+}
+
+pair_kernel!(
+    /// Kernel for [`SliceSearch::index_of_mismatch`], see [`dispatch`](crate::dispatch::dispatch).
+    IndexOfMismatch, index_of_mismatch
+);
+pair_kernel!(
+    /// Kernel for [`SliceSearch::index_of_match`], see [`dispatch`](crate::dispatch::dispatch).
+    IndexOfMatch, index_of_match
+);
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::dispatch::{SimdLevel, dispatch_at};
 
     const T_N: usize = 4;
 
-    fn test<const N: usize>(len: usize)
-    where
-        LaneCount<N>: SupportedLaneCount,
-    {
+    fn test<const N: usize>(len: usize) {
         for i in 0..=len {
             let mut v = Vec::new();
             for j in 0..len {
@@ -62,6 +395,12 @@ mod tests {
                 }
             }
             assert_eq!(v.index_of_any_except::<N>(1).unwrap_or(len), i);
+            assert_eq!(v.index_of::<N>(0).unwrap_or(len), i);
+            assert_eq!(v.count_eq::<N>(1), i);
+            assert_eq!(v.all_eq::<N>(1), i == len);
+
+            let last = v.last_index_of_any_except::<N>(0);
+            assert_eq!(last, i.checked_sub(1));
         }
     }
 
@@ -73,9 +412,9 @@ mod tests {
 
         let a = 1;
         let b = 2;
-        assert_eq!(vec![a; T_N - 1].index_of_any_except::<T_N>(a), None);
-        assert_eq!(vec![a; T_N - 1].index_of_any_except::<T_N>(b), Some(0));
-        assert_eq!(vec![a, b].index_of_any_except::<T_N>(a), Some(1));
+        assert_eq!([a; T_N - 1].index_of_any_except::<T_N>(a), None);
+        assert_eq!([a; T_N - 1].index_of_any_except::<T_N>(b), Some(0));
+        assert_eq!([a, b].index_of_any_except::<T_N>(a), Some(1));
     }
 
     #[test]
@@ -93,8 +432,8 @@ mod tests {
 
         let a = 1;
         let b = 2;
-        assert_eq!(vec![a; T_N + 1].index_of_any_except::<T_N>(a), None);
-        assert_eq!(vec![a; T_N + 1].index_of_any_except::<T_N>(b), Some(0));
+        assert_eq!([a; T_N + 1].index_of_any_except::<T_N>(a), None);
+        assert_eq!([a; T_N + 1].index_of_any_except::<T_N>(b), Some(0));
 
         let mut vec = Vec::new();
         for _i in 0..T_N {
@@ -102,5 +441,109 @@ mod tests {
         }
         vec.push(b);
         assert_eq!(vec.index_of_any_except::<T_N>(a), Some(T_N));
+        assert_eq!(vec.last_index_of_any_except::<T_N>(b), Some(T_N - 1));
+    }
+
+    #[test]
+    fn runs() {
+        let empty: [u32; 0] = [];
+        assert_eq!(empty.runs::<T_N>().next(), None);
+
+        let v = [1, 1, 2, 3, 3, 3, 3, 3, 3, 1];
+        let runs: Vec<_> = v.runs::<T_N>().collect();
+        assert_eq!(runs, [(1, 0, 2), (2, 2, 1), (3, 3, 6), (1, 9, 1)]);
+
+        let v = [7u8; T_N * 3 + 1];
+        let runs: Vec<_> = v.runs::<T_N>().collect();
+        assert_eq!(runs, [(7, 0, T_N * 3 + 1)]);
+    }
+
+    // AVX-512 runs u8 kernels with 64 lanes, wider than a `u32` mask.
+    #[test]
+    fn wide() {
+        const N: usize = 64;
+        for i in 0..N * 2 {
+            let mut v = [1u8; N * 2];
+            v[i] = 2;
+            assert_eq!(v.index_of::<N>(2), Some(i));
+            assert_eq!(v.index_of_any_except::<N>(1), Some(i));
+            assert_eq!(v.last_index_of_any_except::<N>(1), Some(i));
+            assert_eq!(v.count_eq::<N>(2), 1);
+            assert_eq!(v.index_of_mismatch::<N>(&[1; N * 2]), Some(i));
+            assert_eq!(v.index_of_match::<N>(&[2; N * 2]), Some(i));
+
+            let runs: Vec<_> = v.runs::<N>().collect();
+            let mut expected = Vec::new();
+            if i > 0 {
+                expected.push((1, 0, i));
+            }
+            expected.push((2, i, 1));
+            if i + 1 < N * 2 {
+                expected.push((1, i + 1, N * 2 - i - 1));
+            }
+            assert_eq!(runs, expected);
+        }
+    }
+
+    #[test]
+    fn pairs() {
+        for len in 0..T_N * 3 {
+            for i in 0..=len {
+                let a = vec![1u32; len];
+                let mut b = a.clone();
+                b[i..].fill(2);
+                assert_eq!(a.index_of_mismatch::<T_N>(&b), (i < len).then_some(i));
+                assert_eq!(b.index_of_match::<T_N>(&a), (i > 0).then_some(0));
+                assert_eq!(b[i..].index_of_match::<T_N>(&a[i..]), None);
+            }
+        }
+        // Only the shorter length is compared.
+        assert_eq!([1, 2, 3].index_of_mismatch::<T_N>(&[1, 2]), None);
+        assert_eq!([1, 2].index_of_match::<T_N>(&[0, 0, 2]), None);
+    }
+
+    fn at<K: LaneKernel>(level: SimdLevel, kernel: K) -> K::Output {
+        // SAFETY: callers clamp the level to the detected one.
+        unsafe { dispatch_at(level, kernel) }
+    }
+
+    #[test]
+    fn dispatched() {
+        let mut v = vec![3u16; 100];
+        v[61] = 4;
+        v[97] = 4;
+
+        let slice = v.as_slice();
+        let detected = SimdLevel::detect();
+        for level in SimdLevel::ALL.map(|level| level.min(detected)) {
+            assert_eq!(at(level, IndexOf { slice, value: 4 }), Some(61));
+            assert_eq!(at(level, IndexOfAnyExcept { slice, value: 3 }), Some(61));
+            assert_eq!(
+                at(level, LastIndexOfAnyExcept { slice, value: 3 }),
+                Some(97)
+            );
+            assert_eq!(at(level, CountEq { slice, value: 4 }), 2);
+            assert!(!at(level, AllEq { slice, value: 3 }));
+        }
+
+        let mut v = [5u8; 100];
+        v[40] = 6;
+
+        let slice = v.as_slice();
+        let other = &[5u8; 100];
+        for level in SimdLevel::ALL.map(|level| level.min(detected)) {
+            assert_eq!(at(level, IndexOf { slice, value: 6 }), Some(40));
+            assert_eq!(at(level, IndexOfAnyExcept { slice, value: 5 }), Some(40));
+            assert_eq!(
+                at(level, LastIndexOfAnyExcept { slice, value: 5 }),
+                Some(40)
+            );
+            assert_eq!(at(level, IndexOfMismatch { slice, other }), Some(40));
+
+            let mut runs = Vec::new();
+            let f = |value, start, len| runs.push((value, start, len));
+            at(level, ForEachRun { slice, f });
+            assert_eq!(runs, [(5, 0, 40), (6, 40, 1), (5, 41, 59)]);
+        }
     }
 }
@@ -1,6 +1,7 @@
 #![feature(allocator_api)]
+#![cfg_attr(not(feature = "std"), no_std)]
 
-mod fill_op;
+extern crate alloc;
 
 mod iter;
 
@@ -12,7 +13,13 @@ pub use pack_op::pack;
 pub mod part;
 pub mod span;
 
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+
 mod unpack_op;
 pub use unpack_op::unpack;
 
 pub mod vec;
+
+#[cfg(test)]
+mod tests;
@@ -1,4 +1,4 @@
-use num_traits::PrimInt;
+use num_traits::{Euclid, PrimInt};
 use seq_macro::seq;
 
 use super::part::PartSize;
@@ -43,5 +43,45 @@ where
     E: PrimInt,
     P: PrimInt,
 {
-    todo!()
+    let values_per_part = value_bits.values_per_part::<P>().unwrap().get();
+    let (dst_idx, dst_rem) = dst_offset.div_rem_euclid(&values_per_part);
+
+    let value_mask = value_bits.value_mask::<P>().unwrap();
+    let value_bits = value_bits.get();
+
+    dst = &mut dst[dst_idx..];
+
+    if dst_rem != 0 {
+        let head_count = (values_per_part - dst_rem).min(src.len());
+        let head_src;
+        (head_src, src) = src.split_at(head_count);
+
+        let head_part;
+        (head_part, dst) = dst.split_first_mut().unwrap();
+        *head_part = pack_part(*head_part, dst_rem, head_src, value_bits, value_mask);
+    }
+
+    for (dst_part, src_chunk) in dst.iter_mut().zip(src.chunks(values_per_part)) {
+        let part = if src_chunk.len() == values_per_part {
+            // Whole part is overwritten; skip reading the old value.
+            P::zero()
+        } else {
+            *dst_part
+        };
+        *dst_part = pack_part(part, 0, src_chunk, value_bits, value_mask);
+    }
+}
+
+#[inline(always)]
+fn pack_part<P, E>(mut part: P, start: usize, src: &[E], value_bits: usize, value_mask: P) -> P
+where
+    E: PrimInt,
+    P: PrimInt,
+{
+    for i in 0..src.len() {
+        let shift = ((start + i) * value_bits) as u32;
+        let value = P::from(src[i]).unwrap() & value_mask;
+        part = (part & value_mask.unsigned_shl(shift).not()) | value.unsigned_shl(shift);
+    }
+    part
 }
@@ -1,4 +1,4 @@
-use std::{hint::assert_unchecked, num::NonZeroU8};
+use core::{hint::assert_unchecked, num::NonZeroU8};
 
 use num_traits::{Euclid, PrimInt};
 
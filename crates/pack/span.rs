@@ -1,4 +1,4 @@
-use std::{
+use core::{
     fmt,
     marker::PhantomData,
     ops::{Bound, Range, RangeBounds},
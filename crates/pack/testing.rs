@@ -0,0 +1,53 @@
+//! Helpers for differential tests driven by random operation lists.
+
+use std::{
+    any::Any,
+    fmt::Debug,
+    panic::{self, AssertUnwindSafe},
+};
+
+/// Runs `f` on `ops`, shrinking to a minimal failing list of ops on failure.
+///
+/// Panics with the failure of the minimal list and the list itself.
+pub fn check<T: Clone + Debug>(seed: u64, ops: Vec<T>, f: impl Fn(&[T])) {
+    let failure = |ops: &[T]| panic::catch_unwind(AssertUnwindSafe(|| f(ops))).err();
+    if failure(&ops).is_none() {
+        return;
+    }
+
+    let ops = shrink(ops, |ops| failure(ops).is_some());
+    let Some(payload) = failure(&ops) else {
+        unreachable!("seed {seed} is flaky");
+    };
+    let message = message(&*payload);
+    panic!("seed {seed} failed: {message}\nminimal reproducer: {ops:#?}");
+}
+
+/// Removes chunks of `ops` for as long as `fails` still holds.
+pub fn shrink<T: Clone>(mut ops: Vec<T>, fails: impl Fn(&[T]) -> bool) -> Vec<T> {
+    let mut chunk = ops.len().div_ceil(2);
+    while chunk > 0 {
+        let mut start = 0;
+        while start < ops.len() {
+            let mut candidate = ops.clone();
+            candidate.drain(start..(start + chunk).min(ops.len()));
+            if fails(&candidate) {
+                ops = candidate;
+            } else {
+                start += chunk;
+            }
+        }
+        chunk /= 2;
+    }
+    return ops;
+}
+
+fn message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        return message.to_string();
+    }
+    if let Some(message) = payload.downcast_ref::<String>() {
+        return message.clone();
+    }
+    return String::from("non-string panic payload");
+}
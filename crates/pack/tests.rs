@@ -0,0 +1,168 @@
+//! Differential tests that drive packed storage with random operations
+//! and compare every observable value against a plain `Vec` model.
+
+use collections::OwnedCut;
+use rand::{Rng, SeedableRng};
+use rand_xoshiro::Xoshiro256PlusPlus;
+
+use crate::{
+    part::PartSize,
+    span::{PackAccess, PackAccessMut},
+    testing::check,
+    vec::PackVec,
+};
+
+const SEED_COUNT: u64 = 512;
+const OP_COUNT: usize = 48;
+
+#[derive(Clone, Debug)]
+enum Op {
+    Push(u64),
+    ExtendWith(usize, u64),
+    Set(usize, u64),
+    /// Fill `start..end` through a span cut.
+    Fill(usize, usize, u64),
+    /// Set `index` through a cut of a cut, `outer.0..outer.1` then `inner..`.
+    NestedSet((usize, usize), usize, usize, u64),
+    Unpack(usize, usize),
+    Pack(usize, Vec<u64>),
+}
+
+fn gen_op(rng: &mut Xoshiro256PlusPlus, len: usize) -> Op {
+    // Indices may exceed the length to exercise bound checks.
+    let index = |rng: &mut Xoshiro256PlusPlus| rng.gen_range(0..=len + 2);
+    match rng.gen_range(0..7) {
+        0 => Op::Push(rng.r#gen()),
+        1 => Op::ExtendWith(rng.gen_range(0..80), rng.r#gen()),
+        2 => Op::Set(index(rng), rng.r#gen()),
+        3 => {
+            let start = index(rng);
+            Op::Fill(start, start + rng.gen_range(0..70), rng.r#gen())
+        }
+        4 => {
+            let start = index(rng);
+            let outer = (start, start + rng.gen_range(0..70));
+            Op::NestedSet(
+                outer,
+                rng.gen_range(0..8),
+                rng.gen_range(0..70),
+                rng.r#gen(),
+            )
+        }
+        5 => Op::Unpack(index(rng), rng.gen_range(0..140)),
+        _ => {
+            let values = (0..rng.gen_range(0..140)).map(|_| rng.r#gen()).collect();
+            Op::Pack(index(rng), values)
+        }
+    }
+}
+
+fn run(value_bits: PartSize, ops: &[Op]) {
+    let mask: u64 = value_bits.value_mask().unwrap();
+    let mut vec = PackVec::new_var(value_bits);
+    let mut model: Vec<u64> = Vec::new();
+
+    for (step, op) in ops.iter().enumerate() {
+        match op {
+            Op::Push(value) => {
+                vec.push(*value);
+                model.push(value & mask);
+            }
+            Op::ExtendWith(n, value) => {
+                vec.extend_with(*n, *value);
+                model.extend(std::iter::repeat_n(value & mask, *n));
+            }
+            Op::Set(index, value) => {
+                let expected = model
+                    .get_mut(*index)
+                    .map(|v| std::mem::replace(v, value & mask));
+                assert_eq!(vec.set(*index, *value), expected, "step {step}: {op:?}");
+            }
+            Op::Fill(start, end, value) => {
+                let cut = vec.as_span_mut().cut_checked(*start..*end);
+                let range = model.get_mut(*start..*end);
+                assert_eq!(cut.is_some(), range.is_some(), "step {step}: {op:?}");
+                if let (Some(mut cut), Some(range)) = (cut, range) {
+                    cut.fill(*value);
+                    range.fill(value & mask);
+                }
+            }
+            Op::NestedSet((start, end), inner, index, value) => {
+                let outer = vec.as_span_mut().cut_checked(*start..*end);
+                let model_outer = model.get_mut(*start..*end);
+                assert_eq!(
+                    outer.is_some(),
+                    model_outer.is_some(),
+                    "step {step}: {op:?}"
+                );
+                let (Some(outer), Some(model_outer)) = (outer, model_outer) else {
+                    continue;
+                };
+
+                let cut = outer.cut_checked(*inner..);
+                let model_cut = model_outer.get_mut(*inner..);
+                assert_eq!(cut.is_some(), model_cut.is_some(), "step {step}: {op:?}");
+                if let (Some(mut cut), Some(model_cut)) = (cut, model_cut) {
+                    assert_eq!(cut.len(), model_cut.len(), "step {step}: {op:?}");
+                    let expected = model_cut
+                        .get_mut(*index)
+                        .map(|v| std::mem::replace(v, value & mask));
+                    assert_eq!(cut.set(*index, *value), expected, "step {step}: {op:?}");
+                }
+            }
+            Op::Unpack(start, len) => {
+                let Some(expected) = model.get(*start..(start + len)) else {
+                    continue;
+                };
+                let mut dst = vec![0u64; *len];
+                crate::unpack(&mut dst, vec.as_slice(), *start, value_bits);
+                assert_eq!(dst, expected, "step {step}: {op:?}");
+            }
+            Op::Pack(start, values) => {
+                let Some(range) = model.get_mut(*start..(start + values.len())) else {
+                    continue;
+                };
+                for (dst, src) in range.iter_mut().zip(values) {
+                    *dst = src & mask;
+                }
+                let values: Vec<u64> = values.iter().map(|v| v & mask).collect();
+                crate::pack(vec.as_slice_mut(), *start, &values, value_bits);
+            }
+        }
+
+        assert_eq!(vec.len(), model.len(), "step {step}: {op:?}");
+        for (i, expected) in model.iter().enumerate() {
+            assert_eq!(
+                vec.get::<u64>(i),
+                Some(*expected),
+                "step {step}: {op:?}, index {i}"
+            );
+        }
+        assert!(
+            vec.as_span().eq(model.iter().copied()),
+            "step {step}: {op:?}"
+        );
+    }
+}
+
+#[test]
+fn pack_vec_model() {
+    for seed in 0..SEED_COUNT {
+        let mut rng = Xoshiro256PlusPlus::seed_from_u64(seed);
+        let value_bits = PartSize::new(rng.gen_range(1..=64)).unwrap();
+
+        let mut len = 0;
+        let mut ops = Vec::with_capacity(OP_COUNT);
+        for _ in 0..OP_COUNT {
+            let op = gen_op(&mut rng, len);
+            match &op {
+                Op::Push(_) => len += 1,
+                Op::ExtendWith(n, _) => len += n,
+                _ => {}
+            }
+            ops.push(op);
+        }
+
+        check(seed, ops, |ops| run(value_bits, ops));
+    }
+}
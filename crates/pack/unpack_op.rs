@@ -51,10 +51,12 @@ where
     let value_mask = P::from(value_bits.value_mask::<E>().unwrap()).unwrap();
     let value_bits = value_bits.get();
 
+    src = &src[src_idx..];
+
     if src_rem != 0 {
         let head_offset = src_rem * value_bits;
-        let head_part = src[src_idx].unsigned_shr(head_offset as u32);
-        src = &src[src_idx..];
+        let head_part = src[0].unsigned_shr(head_offset as u32);
+        src = &src[1..];
 
         let head_count = (values_per_part - src_rem).min(dst.len());
         let head_dst;
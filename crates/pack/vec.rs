@@ -1,7 +1,6 @@
-use std::{
-    alloc::{Allocator, Global},
-    fmt,
-};
+use core::{alloc::Allocator, fmt};
+
+use alloc::alloc::Global;
 
 use num_traits::PrimInt;
 use raw_vec::RawVec;
@@ -81,7 +80,7 @@ impl<O: PackOrder, A: Allocator> PackVec<O, A> {
 
     #[inline]
     pub const fn as_slice(&self) -> &[Part] {
-        unsafe { std::slice::from_raw_parts(self.as_ptr(), self.parts.capacity()) }
+        unsafe { core::slice::from_raw_parts(self.as_ptr(), self.parts.capacity()) }
     }
 
     #[inline]
@@ -91,7 +90,7 @@ impl<O: PackOrder, A: Allocator> PackVec<O, A> {
 
     #[inline]
     pub const fn as_slice_mut(&mut self) -> &mut [Part] {
-        unsafe { std::slice::from_raw_parts_mut(self.as_mut_ptr(), self.parts.capacity()) }
+        unsafe { core::slice::from_raw_parts_mut(self.as_mut_ptr(), self.parts.capacity()) }
     }
 
     #[inline]
@@ -207,6 +206,19 @@ impl<O: PackOrder, A: Allocator> PackAccessMut for PackVec<O, A> {
     }
 }
 
+impl<O: PackOrder, A: Allocator + Clone> Clone for PackVec<O, A> {
+    /// Copies the used parts only, the clone has no spare capacity.
+    fn clone(&self) -> Self {
+        let mut vec =
+            Self::with_capacity_in(self.len(), self.order, self.parts.allocator().clone());
+        unsafe {
+            core::ptr::copy_nonoverlapping(self.as_ptr(), vec.as_mut_ptr(), self.part_len());
+            vec.set_len(self.len());
+        }
+        vec
+    }
+}
+
 impl<O: PackOrder, A: Allocator> fmt::Debug for PackVec<O, A> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         self.as_span().fmt(f)
@@ -215,7 +227,11 @@ impl<O: PackOrder, A: Allocator> fmt::Debug for PackVec<O, A> {
 
 #[cfg(test)]
 mod tests {
-    use crate::{part::PartSize, span::PackAccess, vec::PackVec};
+    use crate::{
+        part::PartSize,
+        span::{PackAccess, PackAccessMut},
+        vec::PackVec,
+    };
 
     #[test]
     #[inline(never)]
@@ -226,4 +242,18 @@ mod tests {
         }
         assert_eq!(vec.len(), 256);
     }
+
+    #[test]
+    fn clone_is_independent() {
+        let mut vec = PackVec::new_var(PartSize::new(3).unwrap());
+        for i in 0..100u32 {
+            vec.push(i % 8);
+        }
+        let copy = vec.clone();
+        vec.set(7, 0u32);
+        assert_eq!(copy.len(), 100);
+        assert_eq!(copy.get::<u32>(7), Some(7));
+        assert_eq!(vec.get::<u32>(7), Some(0));
+        assert!((0..100).all(|i| i == 7 || copy.get::<u32>(i) == vec.get(i)));
+    }
 }
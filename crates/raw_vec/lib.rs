@@ -8,6 +8,11 @@
 #![feature(alloc_layout_extra)]
 
 #![cfg_attr(test, allow(dead_code))]
+#![no_std]
+
+extern crate alloc;
+#[cfg(test)]
+extern crate std;
 
 // Note: This module is also included in the alloctests crate using #[path] to
 // run the tests. See the comment there for an explanation why this is the case.
@@ -18,11 +23,11 @@ use core::ptr::{self, NonNull, Unique};
 use core::{cmp, hint};
 
 #[cfg(not(no_global_oom_handling))]
-use std::alloc::handle_alloc_error;
-use std::alloc::{Allocator, Global, Layout};
-use std::boxed::Box;
-use std::collections::TryReserveError;
-use std::collections::TryReserveErrorKind::*;
+use alloc::alloc::handle_alloc_error;
+use alloc::alloc::{Allocator, Global, Layout};
+use alloc::boxed::Box;
+use alloc::collections::TryReserveError;
+use alloc::collections::TryReserveErrorKind::*;
 
 #[cfg(test)]
 mod tests;
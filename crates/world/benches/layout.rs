@@ -0,0 +1,131 @@
+//! Compares block access across storage layouts of a chunk.
+
+#![feature(test)]
+
+extern crate test;
+
+use test::{Bencher, black_box};
+use world::{
+    block::{BlockBox, BlockCoord, BlockId, BlockSize},
+    chunk::{BlockStorage, BlockView, Chunk16, layout::BlockLayout},
+};
+
+/// A chunk with enough distinct blocks to need a few bits per block.
+fn terrain(layout: BlockLayout) -> Chunk16 {
+    let mut chunk = Chunk16::empty();
+    chunk.set_layout(layout).unwrap();
+    let blocks = (0..Chunk16::VOLUME)
+        .map(|i| BlockId((i * 7 % 13) as u32))
+        .collect::<Vec<_>>();
+    let size = chunk.size();
+    chunk
+        .set_slice(chunk.bounds(), BlockCoord::default(), size, &blocks)
+        .unwrap();
+    return chunk;
+}
+
+/// Visits every block with its six neighbours, as meshing and lighting do.
+fn neighbours(b: &mut Bencher, layout: BlockLayout) {
+    let chunk = terrain(layout);
+    let offsets = [
+        chunk.get_offset(1, 0, 0),
+        chunk.get_offset(0, 1, 0),
+        chunk.get_offset(0, 0, 1),
+    ];
+    b.iter(|| {
+        let mut sum = 0u32;
+        for y in 1..15 {
+            for z in 1..15 {
+                for x in 1..15 {
+                    let offset = chunk.get_offset(x, y, z);
+                    for step in offsets {
+                        sum += chunk.get_at(offset - step).unwrap().0;
+                        sum += chunk.get_at(offset + step).unwrap().0;
+                    }
+                }
+            }
+        }
+        return black_box(sum);
+    });
+}
+
+fn get_slice(b: &mut Bencher, layout: BlockLayout, region: BlockBox) {
+    let chunk = terrain(layout);
+    let mut dst = vec![BlockId::default(); region.size.volume()];
+    b.iter(|| {
+        chunk
+            .get_slice(region, BlockCoord::default(), region.size, &mut dst)
+            .unwrap();
+        black_box(&dst);
+    });
+}
+
+fn set_slice(b: &mut Bencher, layout: BlockLayout) {
+    let mut chunk = terrain(layout);
+    let src = terrain(BlockLayout::Linear).to_blocks();
+    let (bounds, size) = (chunk.bounds(), chunk.size());
+    b.iter(|| {
+        chunk
+            .set_slice(bounds, BlockCoord::default(), size, &src)
+            .unwrap();
+        black_box(&chunk);
+    });
+}
+
+const FULL: BlockBox = BlockBox {
+    min: BlockCoord { x: 0, y: 0, z: 0 },
+    size: BlockSize {
+        width: 16,
+        height: 16,
+        depth: 16,
+    },
+};
+
+const CUBE: BlockBox = BlockBox {
+    min: BlockCoord { x: 4, y: 4, z: 4 },
+    size: BlockSize {
+        width: 4,
+        height: 4,
+        depth: 4,
+    },
+};
+
+#[bench]
+fn neighbours_linear(b: &mut Bencher) {
+    neighbours(b, BlockLayout::Linear);
+}
+
+#[bench]
+fn neighbours_morton(b: &mut Bencher) {
+    neighbours(b, BlockLayout::Morton);
+}
+
+#[bench]
+fn get_slice_linear(b: &mut Bencher) {
+    get_slice(b, BlockLayout::Linear, FULL);
+}
+
+#[bench]
+fn get_slice_morton(b: &mut Bencher) {
+    get_slice(b, BlockLayout::Morton, FULL);
+}
+
+#[bench]
+fn get_cube_linear(b: &mut Bencher) {
+    get_slice(b, BlockLayout::Linear, CUBE);
+}
+
+#[bench]
+fn get_cube_morton(b: &mut Bencher) {
+    get_slice(b, BlockLayout::Morton, CUBE);
+}
+
+#[bench]
+fn set_slice_linear(b: &mut Bencher) {
+    set_slice(b, BlockLayout::Linear);
+}
+
+#[bench]
+fn set_slice_morton(b: &mut Bencher) {
+    set_slice(b, BlockLayout::Morton);
+}
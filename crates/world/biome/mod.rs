@@ -0,0 +1,391 @@
+use std::{slice, sync::Arc};
+
+use bytemuck::NoUninit;
+use pack::{order::VarPackOrder, vec::PackVec};
+
+use crate::{
+    block::{BlockBox, BlockCoord, BlockSize},
+    chunk::{
+        StorageError, check_box, get_index_base,
+        palette::{
+            get_storage_bits_for_palette,
+            packed::{PackedPalette, PalIdx},
+        },
+    },
+    storage::{Decode, DecodeError, Encode, Reader},
+};
+
+#[derive(Clone, Copy, Debug, Default, Hash, Eq, PartialEq, NoUninit)]
+#[repr(transparent)]
+pub struct BiomeId(pub u16);
+
+/// Blocks per biome cell along each axis.
+pub const BIOME_SCALE: usize = 4;
+
+/// Biomes of a `W`x`H`x`D` chunk, one per cell of [`BIOME_SCALE`]³ blocks.
+///
+/// Cells are stored like a [`crate::chunk::palette::ChunkPalette`], as packed
+/// indices into a palette of the distinct biomes.
+#[derive(Clone, Debug, Default)]
+pub struct ChunkBiomes<const W: usize = 16, const H: usize = 16, const D: usize = 16> {
+    /// Biome of every cell while `cells` is `None`.
+    uniform: BiomeId,
+    /// Palette index of every cell, once cells differ. Shared between clones
+    /// until either side writes.
+    cells: Option<Arc<PackedPalette<BiomeId>>>,
+}
+
+impl<const W: usize, const H: usize, const D: usize> ChunkBiomes<W, H, D> {
+    /// Cells along each axis, partial cells at the far edges included.
+    pub const GRID: BlockSize = BlockSize {
+        width: W.div_ceil(BIOME_SCALE),
+        height: H.div_ceil(BIOME_SCALE),
+        depth: D.div_ceil(BIOME_SCALE),
+    };
+
+    pub const CELL_COUNT: usize = Self::GRID.width * Self::GRID.height * Self::GRID.depth;
+
+    const BLOCKS: BlockSize = BlockSize {
+        width: W,
+        height: H,
+        depth: D,
+    };
+
+    /// Every cell set to [`BiomeId::default`].
+    pub const fn new() -> Self {
+        Self::filled(BiomeId(0))
+    }
+
+    /// Every cell set to `biome`.
+    pub const fn filled(biome: BiomeId) -> Self {
+        Self {
+            uniform: biome,
+            cells: None,
+        }
+    }
+
+    /// Distinct biomes in palette order, including ones no cell uses anymore.
+    #[inline]
+    pub fn entries(&self) -> &[BiomeId] {
+        match &self.cells {
+            Some(cells) => cells.entries(),
+            None => slice::from_ref(&self.uniform),
+        }
+    }
+
+    /// The biome of every cell, if the cells are not packed.
+    #[inline]
+    pub fn uniform_value(&self) -> Option<BiomeId> {
+        match self.cells {
+            Some(_) => None,
+            None => Some(self.uniform),
+        }
+    }
+
+    #[inline]
+    fn cell_index(&self, cell: BlockCoord) -> Option<usize> {
+        let grid = Self::GRID;
+        if cell.x >= grid.width || cell.y >= grid.height || cell.z >= grid.depth {
+            return None;
+        }
+        return Some(get_index_base(grid.depth, grid.width, cell.y, cell.z) + cell.x);
+    }
+
+    pub fn get_cell(&self, cell: BlockCoord) -> Option<BiomeId> {
+        let index = self.cell_index(cell)?;
+        match &self.cells {
+            Some(cells) => cells.get(index),
+            None => Some(self.uniform),
+        }
+    }
+
+    /// Sets the biome of `cell`, returning whether it changed.
+    pub fn set_cell(&mut self, cell: BlockCoord, biome: BiomeId) -> Option<bool> {
+        let index = self.cell_index(cell)?;
+        if self.uniform_value() == Some(biome) {
+            return Some(false);
+        }
+
+        let uniform = self.uniform;
+        let cells = self
+            .cells
+            .get_or_insert_with(|| Arc::new(PackedPalette::new(uniform, Self::CELL_COUNT)));
+        return Arc::make_mut(cells).set(index, biome);
+    }
+
+    /// The biome of the cell containing the block at `coord`.
+    #[inline]
+    pub fn nearest(&self, coord: BlockCoord) -> Option<BiomeId> {
+        if !BlockBox::from_size(Self::BLOCKS).contains(coord) {
+            return None;
+        }
+        let cell = BlockCoord {
+            x: coord.x / BIOME_SCALE,
+            y: coord.y / BIOME_SCALE,
+            z: coord.z / BIOME_SCALE,
+        };
+        return self.get_cell(cell);
+    }
+
+    /// Biomes of the eight cells around the block at `coord`, weighted by the
+    /// distance to their centers and summing to one.
+    ///
+    /// Blend biome properties with these to hide cell borders. Cells at the
+    /// edges of the chunk extend to its faces.
+    pub fn trilinear(&self, coord: BlockCoord) -> Option<[(BiomeId, f32); 8]> {
+        if !BlockBox::from_size(Self::BLOCKS).contains(coord) {
+            return None;
+        }
+
+        // Lower cell, upper cell and weight of the upper one along an axis.
+        let axis = |block: usize, cells: usize| {
+            let scale = BIOME_SCALE as f32;
+            let center = (block as f32 + 0.5) / scale - 0.5;
+            let center = center.clamp(0.0, (cells - 1) as f32);
+            let lower = center as usize;
+            return (lower, (lower + 1).min(cells - 1), center - lower as f32);
+        };
+        let (x, y, z) = (
+            axis(coord.x, Self::GRID.width),
+            axis(coord.y, Self::GRID.height),
+            axis(coord.z, Self::GRID.depth),
+        );
+        let pick = |(lower, upper, t): (usize, usize, f32), upper_side: bool| match upper_side {
+            true => (upper, t),
+            false => (lower, 1.0 - t),
+        };
+
+        let mut weights = [(BiomeId::default(), 0.0); 8];
+        for (i, weight) in weights.iter_mut().enumerate() {
+            let (cx, wx) = pick(x, i & 1 != 0);
+            let (cy, wy) = pick(y, i & 2 != 0);
+            let (cz, wz) = pick(z, i & 4 != 0);
+            let cell = BlockCoord {
+                x: cx,
+                y: cy,
+                z: cz,
+            };
+            *weight = (self.get_cell(cell).unwrap(), wx * wy * wz);
+        }
+        return Some(weights);
+    }
+
+    /// Sets the biome of every cell overlapping `region`, given in blocks.
+    pub fn fill(&mut self, region: BlockBox, biome: BiomeId) -> Result<(), StorageError> {
+        check_box(region, Self::BLOCKS)?;
+        if region.is_empty() {
+            return Ok(());
+        }
+
+        let max = region.max();
+        let cells = BlockBox::from_corners(
+            BlockCoord {
+                x: region.min.x / BIOME_SCALE,
+                y: region.min.y / BIOME_SCALE,
+                z: region.min.z / BIOME_SCALE,
+            },
+            BlockCoord {
+                x: max.x.div_ceil(BIOME_SCALE),
+                y: max.y.div_ceil(BIOME_SCALE),
+                z: max.z.div_ceil(BIOME_SCALE),
+            },
+        );
+        if cells.size == Self::GRID {
+            *self = Self::filled(biome);
+            return Ok(());
+        }
+        for cell in cells {
+            self.set_cell(cell, biome);
+        }
+        return Ok(());
+    }
+
+    /// Drops palette entries no cell uses, unpacking the cells if only one is left.
+    ///
+    /// Returns whether the cells changed.
+    pub fn compact(&mut self) -> bool {
+        let Some(cells) = &mut self.cells else {
+            return false;
+        };
+        let compacted = Arc::make_mut(cells).compact();
+        if let [biome] = cells.entries() {
+            self.uniform = *biome;
+            self.cells = None;
+            return true;
+        }
+        return compacted;
+    }
+
+    /// Bytes allocated on the heap for the cells and the palette.
+    pub fn heap_size(&self) -> usize {
+        self.cells.as_ref().map_or(0, |cells| cells.heap_size())
+    }
+}
+
+/// Writes the palette length as `u32` and the entries, followed by one palette
+/// index per cell if there are several entries.
+///
+/// Indices take one byte, two for palettes of more than 256 entries, and four
+/// past 65536 entries.
+impl<const W: usize, const H: usize, const D: usize> Encode for ChunkBiomes<W, H, D> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        let palette = self.entries();
+        out.extend_from_slice(&(palette.len() as u32).to_le_bytes());
+        for biome in palette {
+            out.extend_from_slice(&biome.0.to_le_bytes());
+        }
+
+        let Some(cells) = &self.cells else {
+            return;
+        };
+        for index in cells.data().as_span() {
+            match palette.len() {
+                0..=0x100 => out.push(index as u8),
+                0x101..=0x10000 => out.extend_from_slice(&(index as u16).to_le_bytes()),
+                _ => out.extend_from_slice(&(index as u32).to_le_bytes()),
+            }
+        }
+    }
+}
+
+impl<const W: usize, const H: usize, const D: usize> Decode for ChunkBiomes<W, H, D> {
+    fn decode(input: &mut Reader<'_>) -> Result<Self, DecodeError> {
+        let len = input.read_u32()? as usize;
+        // Entries are distinct, so there are no more than there are biome ids.
+        if len > 1 << u16::BITS {
+            return Err(DecodeError::InvalidValue("biome palette length"));
+        }
+        let mut palette = Vec::with_capacity(len);
+        for _ in 0..len {
+            palette.push(BiomeId(input.read_u16()?));
+        }
+        if len <= 1 {
+            return Ok(Self::filled(palette.first().copied().unwrap_or_default()));
+        }
+
+        let order = VarPackOrder::new(get_storage_bits_for_palette(len));
+        let mut data = PackVec::with_capacity(Self::CELL_COUNT, order);
+        for _ in 0..Self::CELL_COUNT {
+            let index = match len {
+                0..=0x100 => input.read_u8()? as usize,
+                0x101..=0x10000 => input.read_u16()? as usize,
+                _ => input.read_u32()? as usize,
+            };
+            if index >= len {
+                return Err(DecodeError::InvalidValue("biome palette index"));
+            }
+            data.push(index as PalIdx);
+        }
+        let cells = PackedPalette::from_parts(&palette, data)
+            .ok_or(DecodeError::InvalidValue("biome palette"))?;
+        return Ok(Self {
+            uniform: BiomeId::default(),
+            cells: Some(Arc::new(cells)),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{decode_from_slice, encode_to_vec};
+
+    const PLAINS: BiomeId = BiomeId(1);
+    const DESERT: BiomeId = BiomeId(2);
+
+    #[test]
+    fn cells() {
+        let mut biomes = ChunkBiomes::<16, 16, 16>::new();
+        assert_eq!(ChunkBiomes::<16, 16, 16>::CELL_COUNT, 64);
+        assert_eq!(
+            ChunkBiomes::<18, 4, 1>::GRID,
+            BlockSize {
+                width: 5,
+                height: 1,
+                depth: 1,
+            }
+        );
+        assert_eq!(
+            biomes.nearest(BlockCoord::splat(15)),
+            Some(BiomeId::default())
+        );
+        assert_eq!(biomes.nearest(BlockCoord::splat(16)), None);
+
+        biomes
+            .fill(BlockBox::from_size(BlockSize::splat(16)), PLAINS)
+            .unwrap();
+        assert_eq!(biomes.uniform_value(), Some(PLAINS));
+        assert_eq!(biomes.entries(), [PLAINS]);
+
+        // Any overlap selects a cell.
+        let region = BlockBox::new(
+            BlockCoord { x: 7, y: 0, z: 0 },
+            BlockSize {
+                width: 2,
+                height: 16,
+                depth: 16,
+            },
+        );
+        biomes.fill(region, DESERT).unwrap();
+        for x in 0..16 {
+            let expected = if (4..12).contains(&x) { DESERT } else { PLAINS };
+            let coord = BlockCoord { x, y: 9, z: 3 };
+            assert_eq!(biomes.nearest(coord), Some(expected), "x = {x}");
+        }
+        let past_end = BlockBox::new(BlockCoord::splat(8), BlockSize::splat(9));
+        assert!(biomes.fill(past_end, DESERT).is_err());
+
+        let weight = |coord, biome| -> f32 {
+            let weights = biomes.trilinear(coord).unwrap();
+            weights
+                .iter()
+                .filter(|(b, _)| *b == biome)
+                .map(|(_, w)| w)
+                .sum()
+        };
+        // Block 3 is 1.5 blocks past the center of its cell, towards the desert.
+        let coord = BlockCoord { x: 3, y: 5, z: 5 };
+        assert!((weight(coord, PLAINS) - 0.625).abs() < 1e-6);
+        assert!((weight(coord, DESERT) - 0.375).abs() < 1e-6);
+        // Cells at the edge cover the blocks between their centers and the faces.
+        let coord = BlockCoord { x: 0, y: 0, z: 15 };
+        assert!((weight(coord, PLAINS) - 1.0).abs() < 1e-6);
+        assert_eq!(biomes.trilinear(BlockCoord::splat(16)), None);
+
+        let bytes = encode_to_vec(&biomes);
+        assert_eq!(bytes.len(), 4 + 2 * 2 + 64);
+        let decoded: ChunkBiomes = decode_from_slice(&bytes).unwrap();
+        for cell in BlockBox::from_size(ChunkBiomes::<16, 16, 16>::GRID) {
+            assert_eq!(decoded.get_cell(cell), biomes.get_cell(cell));
+        }
+        let mut invalid = bytes.clone();
+        invalid[8] = 2;
+        assert_eq!(
+            decode_from_slice::<ChunkBiomes>(&invalid).unwrap_err(),
+            DecodeError::InvalidValue("biome palette index")
+        );
+        let too_long = (u16::MAX as u32 + 2).to_le_bytes();
+        assert_eq!(
+            decode_from_slice::<ChunkBiomes>(&too_long).unwrap_err(),
+            DecodeError::InvalidValue("biome palette length")
+        );
+
+        // Unused entries are dropped before the cells widen.
+        biomes.fill(region, PLAINS).unwrap();
+        assert_eq!(biomes.entries(), [PLAINS, DESERT]);
+        let forest = BiomeId(3);
+        assert_eq!(biomes.set_cell(BlockCoord::splat(1), forest), Some(true));
+        assert_eq!(biomes.entries(), [PLAINS, forest]);
+        assert!(!biomes.compact());
+        biomes.set_cell(BlockCoord::splat(1), PLAINS);
+        let snapshot = biomes.clone();
+        assert!(Arc::ptr_eq(
+            snapshot.cells.as_ref().unwrap(),
+            biomes.cells.as_ref().unwrap()
+        ));
+        assert!(biomes.compact());
+        assert_eq!(biomes.uniform_value(), Some(PLAINS));
+        assert_eq!(snapshot.entries(), [PLAINS, forest]);
+        assert_eq!(encode_to_vec(&biomes), [1, 0, 0, 0, 1, 0]);
+    }
+}
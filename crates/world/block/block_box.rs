@@ -0,0 +1,374 @@
+use std::iter::FusedIterator;
+
+use bevy::math::IVec3;
+
+use crate::pos::Face;
+
+use super::{BlockCoord, BlockSize};
+
+/// Axis-aligned box of blocks, from `min` (inclusive) spanning `size`.
+#[derive(Clone, Copy, Debug, Default, Hash, Eq, PartialEq)]
+pub struct BlockBox {
+    pub min: BlockCoord,
+    pub size: BlockSize,
+}
+
+impl BlockBox {
+    #[inline]
+    pub const fn new(min: BlockCoord, size: BlockSize) -> Self {
+        Self { min, size }
+    }
+
+    /// Box at the origin covering `size`.
+    #[inline]
+    pub const fn from_size(size: BlockSize) -> Self {
+        Self {
+            min: BlockCoord { x: 0, y: 0, z: 0 },
+            size,
+        }
+    }
+
+    /// Box spanning `min..max`, empty if `max` is not past `min` on every axis.
+    pub fn from_corners(min: BlockCoord, max: BlockCoord) -> Self {
+        let size = BlockSize {
+            width: max.x.saturating_sub(min.x),
+            height: max.y.saturating_sub(min.y),
+            depth: max.z.saturating_sub(min.z),
+        };
+        return Self { min, size };
+    }
+
+    /// The exclusive upper corner, `None` if it doesn't fit in a `usize`.
+    #[inline]
+    pub fn checked_max(self) -> Option<BlockCoord> {
+        Some(BlockCoord {
+            x: self.min.x.checked_add(self.size.width)?,
+            y: self.min.y.checked_add(self.size.height)?,
+            z: self.min.z.checked_add(self.size.depth)?,
+        })
+    }
+
+    /// The exclusive upper corner, clamped to `usize::MAX` on axes where it doesn't fit.
+    #[inline]
+    pub fn saturating_max(self) -> BlockCoord {
+        BlockCoord {
+            x: self.min.x.saturating_add(self.size.width),
+            y: self.min.y.saturating_add(self.size.height),
+            z: self.min.z.saturating_add(self.size.depth),
+        }
+    }
+
+    /// The exclusive upper corner.
+    ///
+    /// Overflows unless [`BlockBox::checked_max`] succeeds, which storage boxes are checked for.
+    #[inline]
+    pub fn max(self) -> BlockCoord {
+        BlockCoord {
+            x: self.min.x + self.size.width,
+            y: self.min.y + self.size.height,
+            z: self.min.z + self.size.depth,
+        }
+    }
+
+    #[inline]
+    pub fn volume(self) -> usize {
+        self.size.volume()
+    }
+
+    #[inline]
+    pub fn is_empty(self) -> bool {
+        self.volume() == 0
+    }
+
+    #[inline]
+    pub fn contains(self, coord: BlockCoord) -> bool {
+        let max = self.saturating_max();
+        (self.min.x..max.x).contains(&coord.x)
+            && (self.min.y..max.y).contains(&coord.y)
+            && (self.min.z..max.z).contains(&coord.z)
+    }
+
+    /// Whether `other` is entirely inside, an empty box is inside every box.
+    pub fn contains_box(self, other: BlockBox) -> bool {
+        if other.is_empty() {
+            return true;
+        }
+        let (max, other_max) = (self.saturating_max(), other.saturating_max());
+        return self.min.x <= other.min.x
+            && self.min.y <= other.min.y
+            && self.min.z <= other.min.z
+            && other_max.x <= max.x
+            && other_max.y <= max.y
+            && other_max.z <= max.z;
+    }
+
+    /// Whether the box lies within storage of `bounds`, without overflowing.
+    pub fn fits(self, bounds: BlockSize) -> bool {
+        let fits = |min: usize, len: usize, bound: usize| {
+            min.checked_add(len).is_some_and(|end| end <= bound)
+        };
+        return fits(self.min.x, self.size.width, bounds.width)
+            && fits(self.min.y, self.size.height, bounds.height)
+            && fits(self.min.z, self.size.depth, bounds.depth);
+    }
+
+    /// The overlapping part of both boxes, `None` if they don't overlap.
+    pub fn intersect(self, other: BlockBox) -> Option<BlockBox> {
+        let (max, other_max) = (self.saturating_max(), other.saturating_max());
+        let min = BlockCoord {
+            x: self.min.x.max(other.min.x),
+            y: self.min.y.max(other.min.y),
+            z: self.min.z.max(other.min.z),
+        };
+        let max = BlockCoord {
+            x: max.x.min(other_max.x),
+            y: max.y.min(other_max.y),
+            z: max.z.min(other_max.z),
+        };
+        let intersection = Self::from_corners(min, max);
+        return (!intersection.is_empty()).then_some(intersection);
+    }
+
+    /// The smallest box containing both, ignoring empty boxes.
+    pub fn union(self, other: BlockBox) -> BlockBox {
+        if other.is_empty() {
+            return self;
+        }
+        if self.is_empty() {
+            return other;
+        }
+
+        let (max, other_max) = (self.saturating_max(), other.saturating_max());
+        let min = BlockCoord {
+            x: self.min.x.min(other.min.x),
+            y: self.min.y.min(other.min.y),
+            z: self.min.z.min(other.min.z),
+        };
+        let max = BlockCoord {
+            x: max.x.max(other_max.x),
+            y: max.y.max(other_max.y),
+            z: max.z.max(other_max.z),
+        };
+        return Self::from_corners(min, max);
+    }
+
+    /// Moves the box by `offset`, `None` if it would leave the positive octant
+    /// or its upper corner wouldn't fit in a `usize`.
+    pub fn translate(self, offset: IVec3) -> Option<BlockBox> {
+        let min = BlockCoord {
+            x: self.min.x.checked_add_signed(offset.x as isize)?,
+            y: self.min.y.checked_add_signed(offset.y as isize)?,
+            z: self.min.z.checked_add_signed(offset.z as isize)?,
+        };
+        let translated = Self { min, ..self };
+        translated.checked_max()?;
+        return Some(translated);
+    }
+
+    /// The part of the box within storage of `bounds`.
+    #[inline]
+    pub fn clip_to(self, bounds: BlockSize) -> Option<BlockBox> {
+        self.intersect(Self::from_size(bounds))
+    }
+
+    /// The eight corner blocks (inclusive), `None` for an empty box.
+    pub fn corners(self) -> Option<[BlockCoord; 8]> {
+        if self.is_empty() {
+            return None;
+        }
+
+        let max = self.saturating_max();
+        let (lo, hi) = (
+            self.min,
+            BlockCoord {
+                x: max.x - 1,
+                y: max.y - 1,
+                z: max.z - 1,
+            },
+        );
+        return Some(std::array::from_fn(|i| BlockCoord {
+            x: if i & 1 == 0 { lo.x } else { hi.x },
+            y: if i & 2 == 0 { lo.y } else { hi.y },
+            z: if i & 4 == 0 { lo.z } else { hi.z },
+        }));
+    }
+
+    /// The one block thick layer of the box on `face`, `None` for an empty box.
+    pub fn face(self, face: Face) -> Option<BlockBox> {
+        if self.is_empty() {
+            return None;
+        }
+
+        let max = self.saturating_max();
+        let (mut min, mut size) = (self.min, self.size);
+        match face {
+            Face::NegX => size.width = 1,
+            Face::PosX => (min.x, size.width) = (max.x - 1, 1),
+            Face::NegY => size.height = 1,
+            Face::PosY => (min.y, size.height) = (max.y - 1, 1),
+            Face::NegZ => size.depth = 1,
+            Face::PosZ => (min.z, size.depth) = (max.z - 1, 1),
+        }
+        return Some(Self { min, size });
+    }
+
+    /// Layers on every face, ordered like [`Face::ALL`].
+    pub fn faces(self) -> impl Iterator<Item = (Face, BlockBox)> {
+        Face::ALL
+            .into_iter()
+            .filter_map(move |face| Some((face, self.face(face)?)))
+    }
+
+    /// Iterates positions in storage order: X fastest, then Z, then Y.
+    #[inline]
+    pub fn iter(self) -> BlockBoxIter {
+        BlockBoxIter {
+            bounds: self,
+            next: self.min,
+            remaining: self.volume(),
+        }
+    }
+}
+
+impl IntoIterator for BlockBox {
+    type Item = BlockCoord;
+    type IntoIter = BlockBoxIter;
+
+    #[inline]
+    fn into_iter(self) -> BlockBoxIter {
+        self.iter()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct BlockBoxIter {
+    bounds: BlockBox,
+    next: BlockCoord,
+    remaining: usize,
+}
+
+impl Iterator for BlockBoxIter {
+    type Item = BlockCoord;
+
+    #[inline]
+    fn next(&mut self) -> Option<BlockCoord> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+
+        let current = self.next;
+        let max = self.bounds.saturating_max();
+        self.next.x += 1;
+        if self.next.x == max.x {
+            self.next.x = self.bounds.min.x;
+            self.next.z += 1;
+            if self.next.z == max.z {
+                self.next.z = self.bounds.min.z;
+                self.next.y += 1;
+            }
+        }
+        return Some(current);
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl ExactSizeIterator for BlockBoxIter {}
+
+impl FusedIterator for BlockBoxIter {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bbox(min: usize, size: usize) -> BlockBox {
+        BlockBox::new(BlockCoord::splat(min), BlockSize::splat(size))
+    }
+
+    #[test]
+    fn set_ops() {
+        let (a, b) = (bbox(0, 4), bbox(2, 4));
+        assert_eq!(a.intersect(b), Some(bbox(2, 2)));
+        assert_eq!(a.union(b), bbox(0, 6));
+        assert_eq!(a.intersect(bbox(4, 1)), None);
+        assert_eq!(a.union(bbox(9, 0)), a);
+
+        assert!(a.contains(BlockCoord::splat(3)));
+        assert!(!a.contains(BlockCoord::splat(4)));
+        assert!(a.union(b).contains_box(b));
+        assert!(!a.contains_box(b));
+
+        assert_eq!(b.clip_to(BlockSize::splat(5)), Some(bbox(2, 3)));
+        assert!(b.fits(BlockSize::splat(6)) && !b.fits(BlockSize::splat(5)));
+        let overflowing = BlockBox::new(BlockCoord::splat(usize::MAX), BlockSize::splat(2));
+        assert!(!overflowing.fits(BlockSize::splat(1)));
+        assert_eq!(overflowing.checked_max(), None);
+        assert_eq!(b.checked_max(), Some(b.max()));
+
+        assert_eq!(b.translate(IVec3::splat(-2)), Some(a));
+        assert_eq!(b.translate(IVec3::new(0, -3, 0)), None);
+    }
+
+    #[test]
+    fn overflowing() {
+        let edge = BlockBox::new(BlockCoord::splat(usize::MAX - 1), BlockSize::splat(4));
+        assert_eq!(edge.saturating_max(), BlockCoord::splat(usize::MAX));
+        assert!(edge.contains(BlockCoord::splat(usize::MAX - 1)));
+        assert!(!edge.contains(BlockCoord::splat(0)));
+        assert!(edge.contains_box(bbox(usize::MAX - 1, 1)));
+        assert!(!bbox(0, 4).contains_box(edge));
+        assert_eq!(edge.intersect(bbox(0, 4)), None);
+        assert_eq!(edge.clip_to(BlockSize::splat(8)), None);
+        assert_eq!(
+            BlockBox::new(BlockCoord::splat(2), BlockSize::splat(usize::MAX))
+                .clip_to(BlockSize::splat(8)),
+            Some(bbox(2, 6))
+        );
+        assert_eq!(edge.union(bbox(0, 1)), bbox(0, usize::MAX));
+        assert!(edge.corners().is_some());
+
+        assert_eq!(
+            bbox(usize::MAX - 4, 2).translate(IVec3::splat(2)),
+            Some(bbox(usize::MAX - 2, 2))
+        );
+        assert_eq!(bbox(usize::MAX - 4, 2).translate(IVec3::splat(3)), None);
+    }
+
+    #[test]
+    fn iteration() {
+        let b = BlockBox::new(
+            BlockCoord { x: 1, y: 2, z: 3 },
+            BlockSize {
+                width: 2,
+                height: 3,
+                depth: 4,
+            },
+        );
+        let coords: Vec<_> = b.iter().collect();
+        assert_eq!(coords.len(), b.volume());
+        assert_eq!(coords[0], b.min);
+        assert_eq!(coords[1], BlockCoord { x: 2, y: 2, z: 3 });
+        assert_eq!(coords[2], BlockCoord { x: 1, y: 2, z: 4 });
+        assert!(coords.windows(2).all(|w| {
+            let index = |c: BlockCoord| (c.y * 100 + c.z) * 100 + c.x;
+            index(w[0]) < index(w[1])
+        }));
+        assert!(coords.iter().all(|c| b.contains(*c)));
+
+        let corners = b.corners().unwrap();
+        assert!(corners.iter().all(|c| b.contains(*c)));
+        assert_eq!(corners[7], BlockCoord { x: 2, y: 4, z: 6 });
+
+        let faces: Vec<_> = b.faces().collect();
+        assert_eq!(faces.len(), 6);
+        assert_eq!(faces[1].1.min.x, 2);
+        assert_eq!(faces[3].1.size.height, 1);
+        assert!(faces.iter().all(|(_, face)| b.contains_box(*face)));
+        assert_eq!(bbox(0, 0).iter().next(), None);
+        assert_eq!(bbox(0, 0).corners(), None);
+    }
+}
@@ -1,10 +1,21 @@
+mod block_box;
+pub mod registry;
+pub mod state;
+
+pub use block_box::*;
+
 use bytemuck::NoUninit;
 
 #[derive(Clone, Copy, Debug, Default, Hash, Eq, PartialEq, NoUninit)]
 #[repr(transparent)]
 pub struct BlockId(pub u32);
 
-#[derive(Clone, Copy, Default, Hash, Eq, PartialEq)]
+impl BlockId {
+    /// Id of `core:air`, always the first block in a [`registry::BlockRegistry`].
+    pub const AIR: BlockId = BlockId(0);
+}
+
+#[derive(Clone, Copy, Debug, Default, Hash, Eq, PartialEq)]
 pub struct BlockCoord {
     pub x: usize,
     pub y: usize,
@@ -21,7 +32,7 @@ impl BlockCoord {
     }
 }
 
-#[derive(Clone, Copy, Default, Hash, Eq, PartialEq)]
+#[derive(Clone, Copy, Debug, Default, Hash, Eq, PartialEq)]
 pub struct BlockSize {
     pub width: usize,
     pub height: usize,
@@ -0,0 +1,581 @@
+use std::{
+    borrow::Borrow,
+    error::Error,
+    fmt,
+    hash::{Hash, Hasher},
+};
+
+use bevy::prelude::{App, Plugin, PostStartup, ResMut, Resource};
+use collections::IndexMap;
+use pack::part::PartSize;
+
+use super::{
+    BlockId,
+    state::{BlockState, StateKind, StateProperty},
+};
+
+/// Namespaced block name, e.g. `core:stone`.
+#[derive(Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
+pub struct BlockKey {
+    full: String,
+    split: usize,
+}
+
+impl BlockKey {
+    /// Namespace assumed for keys written without one.
+    pub const DEFAULT_NAMESPACE: &'static str = "core";
+
+    /// Parses `namespace:path`, or just `path` in the default namespace.
+    ///
+    /// Both parts must be non-empty and only contain `[a-z0-9_./-]`.
+    pub fn parse(key: &str) -> Result<Self, RegistryError> {
+        let (namespace, path) = key
+            .split_once(':')
+            .unwrap_or((Self::DEFAULT_NAMESPACE, key));
+        return Self::new(namespace, path);
+    }
+
+    pub fn new(namespace: &str, path: &str) -> Result<Self, RegistryError> {
+        let valid = |s: &str| {
+            !s.is_empty()
+                && s.bytes()
+                    .all(|b| matches!(b, b'a'..=b'z' | b'0'..=b'9' | b'_' | b'.' | b'/' | b'-'))
+        };
+        if !valid(namespace) || !valid(path) {
+            return Err(RegistryError::InvalidKey(format!("{namespace}:{path}")));
+        }
+
+        return Ok(Self {
+            full: format!("{namespace}:{path}"),
+            split: namespace.len(),
+        });
+    }
+
+    #[inline]
+    pub fn namespace(&self) -> &str {
+        &self.full[..self.split]
+    }
+
+    #[inline]
+    pub fn path(&self) -> &str {
+        &self.full[self.split + 1..]
+    }
+
+    #[inline]
+    pub fn as_str(&self) -> &str {
+        &self.full
+    }
+}
+
+// Must hash like `str` for lookups through `Borrow<str>`.
+impl Hash for BlockKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.full.hash(state);
+    }
+}
+
+impl Borrow<str> for BlockKey {
+    fn borrow(&self) -> &str {
+        &self.full
+    }
+}
+
+impl fmt::Display for BlockKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.full)
+    }
+}
+
+/// Per-block data shared by meshing, lighting, physics and saving.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BlockProperties {
+    /// Whether entities collide with the block.
+    pub solid: bool,
+
+    /// Whether the block fully hides the faces of its neighbors.
+    pub opaque: bool,
+
+    /// Light level emitted by the block, `0..=15`.
+    pub light_emission: u8,
+
+    /// Light levels absorbed when light passes through, `0..=15`.
+    pub light_opacity: u8,
+
+    /// sRGB color with alpha.
+    pub color: [u8; 4],
+
+    pub hardness: f32,
+
+    /// Whether the block keeps data beside its id, see [`crate::chunk::entity::BlockEntity`].
+    pub has_data: bool,
+}
+
+impl BlockProperties {
+    pub const SOLID: Self = Self {
+        solid: true,
+        opaque: true,
+        light_emission: 0,
+        light_opacity: 15,
+        color: [255, 255, 255, 255],
+        hardness: 1.0,
+        has_data: false,
+    };
+
+    pub const AIR: Self = Self {
+        solid: false,
+        opaque: false,
+        light_emission: 0,
+        light_opacity: 0,
+        color: [0, 0, 0, 0],
+        hardness: 0.0,
+        has_data: false,
+    };
+}
+
+impl Default for BlockProperties {
+    fn default() -> Self {
+        Self::SOLID
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum RegistryError {
+    InvalidKey(String),
+    InvalidProperties(BlockKey),
+    /// State properties or enum values are duplicated or misnamed, or the properties have no
+    /// values or too many combinations.
+    InvalidStates(BlockKey),
+    Duplicate(BlockKey),
+    Frozen,
+    Full,
+    UnknownBlock(String),
+    UnknownProperty(BlockKey, String),
+    InvalidValue(BlockKey, String, String),
+    Syntax(String),
+}
+
+impl fmt::Display for RegistryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegistryError::InvalidKey(key) => write!(f, "invalid block key `{key}`"),
+            RegistryError::InvalidProperties(key) => {
+                write!(f, "light levels of block `{key}` exceed 15")
+            }
+            RegistryError::InvalidStates(key) => write!(f, "invalid states for block `{key}`"),
+            RegistryError::Duplicate(key) => write!(f, "block `{key}` is already registered"),
+            RegistryError::Frozen => f.write_str("block registry is frozen"),
+            RegistryError::Full => f.write_str("block registry is out of ids"),
+            RegistryError::UnknownBlock(key) => write!(f, "unknown block `{key}`"),
+            RegistryError::UnknownProperty(key, property) => {
+                write!(f, "block `{key}` has no property `{property}`")
+            }
+            RegistryError::InvalidValue(key, property, value) => {
+                write!(f, "invalid value `{value}` for `{key}[{property}]`")
+            }
+            RegistryError::Syntax(state) => write!(f, "malformed block state `{state}`"),
+        }
+    }
+}
+
+impl Error for RegistryError {}
+
+#[derive(Debug)]
+struct BlockEntry {
+    base: BlockId,
+    properties: BlockProperties,
+    states: Vec<StateProperty>,
+}
+
+/// Assigns [`BlockId`]s to every registered [`BlockKey`] and stores its [`BlockProperties`].
+///
+/// A block gets one id per combination of its [`StateProperty`] values, see [`BlockState`].
+/// Ids are handed out in registration order, starting with [`BlockId::AIR`].
+/// Once frozen, ids are stable for the rest of the session.
+#[derive(Resource, Debug)]
+pub struct BlockRegistry {
+    /// Block key to index into `blocks`.
+    keys: IndexMap<BlockKey, u32>,
+    blocks: Vec<BlockEntry>,
+    /// Block id to index into `blocks`.
+    owners: Vec<u32>,
+    frozen: bool,
+}
+
+impl BlockRegistry {
+    pub const AIR_KEY: &'static str = "core:air";
+
+    pub fn new() -> Self {
+        let mut registry = Self {
+            keys: IndexMap::default(),
+            blocks: Vec::new(),
+            owners: Vec::new(),
+            frozen: false,
+        };
+        let air = registry
+            .register(Self::AIR_KEY, BlockProperties::AIR)
+            .unwrap();
+        debug_assert_eq!(air, BlockId::AIR);
+        return registry;
+    }
+
+    pub fn register(
+        &mut self,
+        key: &str,
+        properties: BlockProperties,
+    ) -> Result<BlockId, RegistryError> {
+        return self.register_states(key, properties, Vec::new());
+    }
+
+    /// Registers a block with a state for every combination of `states`.
+    ///
+    /// Returns the id of the default state, the first of the block's id range.
+    pub fn register_states(
+        &mut self,
+        key: &str,
+        properties: BlockProperties,
+        states: Vec<StateProperty>,
+    ) -> Result<BlockId, RegistryError> {
+        if self.frozen {
+            return Err(RegistryError::Frozen);
+        }
+
+        let key = BlockKey::parse(key)?;
+        if properties.light_emission > 15 || properties.light_opacity > 15 {
+            return Err(RegistryError::InvalidProperties(key));
+        }
+        if self.keys.index(&key).is_some() {
+            return Err(RegistryError::Duplicate(key));
+        }
+
+        let valid_name = |name: &str| {
+            !name.is_empty()
+                && name
+                    .bytes()
+                    .all(|b| matches!(b, b'a'..=b'z' | b'0'..=b'9' | b'_'))
+        };
+        // Enum values are parsed back with the same syntax as names, and must be distinct.
+        let valid_values = |kind: &StateKind| match kind {
+            StateKind::Enum(values) => values
+                .iter()
+                .enumerate()
+                .all(|(i, value)| valid_name(value) && !values[..i].contains(value)),
+            _ => true,
+        };
+        let mut state_count = 1u32;
+        for (i, state) in states.iter().enumerate() {
+            let duplicate = states[..i].iter().any(|s| s.name() == state.name());
+            let count = state_count.checked_mul(state.value_count());
+            if duplicate
+                || !valid_name(state.name())
+                || !valid_values(state.kind())
+                || !matches!(count, Some(1..))
+            {
+                return Err(RegistryError::InvalidStates(key));
+            }
+            state_count = count.unwrap();
+        }
+
+        let base = u32::try_from(self.owners.len()).map_err(|_| RegistryError::Full)?;
+        if base.checked_add(state_count).is_none() {
+            return Err(RegistryError::Full);
+        }
+
+        let (index, _) = self.keys.index_or_add(key);
+        let index = *index;
+        self.owners
+            .extend(std::iter::repeat_n(index, state_count as usize));
+        self.blocks.push(BlockEntry {
+            base: BlockId(base),
+            properties,
+            states,
+        });
+        return Ok(BlockId(base));
+    }
+
+    /// Prevents further registration.
+    pub fn freeze(&mut self) {
+        self.frozen = true;
+    }
+
+    #[inline]
+    pub fn is_frozen(&self) -> bool {
+        self.frozen
+    }
+
+    /// Number of ids handed out, counting every state.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.owners.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.owners.is_empty()
+    }
+
+    /// Bits needed to store any id handed out, for [`crate::chunk::direct::ChunkDirect`].
+    pub fn id_bits(&self) -> PartSize {
+        let max = self.owners.len().saturating_sub(1) as u32;
+        let bits = (u32::BITS - max.leading_zeros()).max(1);
+        return PartSize::new(bits as usize).unwrap();
+    }
+
+    /// Number of registered blocks.
+    #[inline]
+    pub fn block_count(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// Looks up the default state of a block by `namespace:path`, or by `path` in the default namespace.
+    pub fn id(&self, key: &str) -> Option<BlockId> {
+        let index = match key.contains(':') {
+            true => self.keys.map.get(key),
+            false => {
+                let key = format!("{}:{key}", BlockKey::DEFAULT_NAMESPACE);
+                self.keys.map.get(key.as_str())
+            }
+        };
+        return index.map(|index| self.blocks[*index as usize].base);
+    }
+
+    #[inline]
+    pub fn key(&self, id: BlockId) -> Option<&BlockKey> {
+        let index = *self.owners.get(id.0 as usize)?;
+        return self.keys.value(index);
+    }
+
+    #[inline]
+    pub fn properties(&self, id: BlockId) -> Option<&BlockProperties> {
+        let index = *self.owners.get(id.0 as usize)?;
+        return Some(&self.blocks[index as usize].properties);
+    }
+
+    pub fn get(&self, key: &str) -> Option<(BlockId, &BlockProperties)> {
+        let id = self.id(key)?;
+        return Some((id, self.properties(id).unwrap()));
+    }
+
+    pub fn state(&self, id: BlockId) -> Option<BlockState<'_>> {
+        let index = *self.owners.get(id.0 as usize)?;
+        let block = &self.blocks[index as usize];
+        return Some(BlockState {
+            key: &self.keys.list[index as usize],
+            properties: &block.properties,
+            states: &block.states,
+            base: block.base,
+            offset: id.0 - block.base.0,
+        });
+    }
+
+    /// Parses `namespace:path[property=value,...]`.
+    ///
+    /// Properties left out keep their default value.
+    pub fn parse_state(&self, state: &str) -> Result<BlockId, RegistryError> {
+        let syntax = || RegistryError::Syntax(state.to_owned());
+        let (key, values) = match state.split_once('[') {
+            Some((key, rest)) => (key, Some(rest.strip_suffix(']').ok_or_else(syntax)?)),
+            None => (state, None),
+        };
+
+        let id = self
+            .id(key)
+            .ok_or_else(|| RegistryError::UnknownBlock(key.to_owned()))?;
+        let mut block = self.state(id).unwrap();
+        for pair in values.into_iter().flat_map(|v| v.split(',')) {
+            let (property, value) = pair.split_once('=').ok_or_else(syntax)?;
+            let (property, value) = (property.trim(), value.trim());
+            if block.get(property).is_none() {
+                let key = block.key().clone();
+                return Err(RegistryError::UnknownProperty(key, property.to_owned()));
+            }
+            block = block.with_str(property, value).ok_or_else(|| {
+                let key = block.key().clone();
+                RegistryError::InvalidValue(key, property.to_owned(), value.to_owned())
+            })?;
+        }
+        return Ok(block.id());
+    }
+
+    /// Iterates blocks with their default state id.
+    pub fn iter(&self) -> impl ExactSizeIterator<Item = (BlockId, &BlockKey, &BlockProperties)> {
+        self.keys
+            .list
+            .iter()
+            .zip(&self.blocks)
+            .map(|(key, block)| (block.base, key, &block.properties))
+    }
+}
+
+impl Default for BlockRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Inserts a [`BlockRegistry`] and freezes it once startup systems have registered their blocks.
+pub struct BlockRegistryPlugin;
+
+impl Plugin for BlockRegistryPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<BlockRegistry>()
+            .add_systems(PostStartup, freeze_block_registry);
+    }
+}
+
+pub fn freeze_block_registry(mut registry: ResMut<BlockRegistry>) {
+    registry.freeze();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::state::StateValue;
+
+    #[test]
+    fn keys() {
+        let key = BlockKey::parse("core:stone").unwrap();
+        assert_eq!((key.namespace(), key.path()), ("core", "stone"));
+        assert_eq!(BlockKey::parse("stone"), Ok(key));
+        assert_eq!(BlockKey::parse("mod:ores/tin").unwrap().path(), "ores/tin");
+
+        for invalid in ["", ":stone", "core:", "Core:stone", "core:st one", "a:b:c"] {
+            assert!(BlockKey::parse(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn register() {
+        let mut registry = BlockRegistry::new();
+        assert_eq!(registry.id("air"), Some(BlockId::AIR));
+
+        let glow = BlockProperties {
+            light_emission: 14,
+            ..BlockProperties::SOLID
+        };
+        let stone = registry
+            .register("core:stone", BlockProperties::SOLID)
+            .unwrap();
+        let lamp = registry.register("deco:lamp", glow.clone()).unwrap();
+        assert_eq!((stone, lamp), (BlockId(1), BlockId(2)));
+
+        assert_eq!(registry.id("stone"), Some(stone));
+        assert_eq!(registry.id("deco:lamp"), Some(lamp));
+        assert_eq!(registry.id("lamp"), None);
+        assert_eq!(registry.key(lamp).unwrap().as_str(), "deco:lamp");
+        assert_eq!(registry.properties(lamp), Some(&glow));
+        assert_eq!(registry.properties(BlockId(3)), None);
+        assert_eq!(registry.iter().len(), 3);
+
+        assert_eq!(
+            registry.register("stone", BlockProperties::SOLID),
+            Err(RegistryError::Duplicate(BlockKey::parse("stone").unwrap()))
+        );
+        let too_bright = BlockProperties {
+            light_emission: 16,
+            ..BlockProperties::SOLID
+        };
+        assert!(matches!(
+            registry.register("core:sun", too_bright),
+            Err(RegistryError::InvalidProperties(_))
+        ));
+
+        registry.freeze();
+        assert_eq!(
+            registry.register("core:dirt", BlockProperties::SOLID),
+            Err(RegistryError::Frozen)
+        );
+        assert_eq!(registry.len(), 3);
+        assert_eq!(registry.id_bits().get(), 2);
+    }
+
+    #[test]
+    fn states() {
+        let mut registry = BlockRegistry::new();
+        let facing = StateProperty::enumeration("facing", &["north", "east", "south", "west"]);
+        let states = vec![
+            facing,
+            StateProperty::bool("lit"),
+            StateProperty::int("level", -1..=1),
+        ];
+        let furnace = registry
+            .register_states("core:furnace", BlockProperties::SOLID, states)
+            .unwrap();
+        let stone = registry.register("stone", BlockProperties::SOLID).unwrap();
+        assert_eq!((furnace, stone), (BlockId(1), BlockId(25)));
+        assert_eq!(registry.len(), 26);
+        assert_eq!(registry.id_bits().get(), 5);
+
+        let state = registry.state(furnace).unwrap();
+        assert_eq!(state.state_count(), 24);
+        assert_eq!(state.get("facing"), Some(StateValue::Enum("north")));
+        assert_eq!(state.get("level"), Some(StateValue::Int(-1)));
+        assert_eq!(state.get("missing"), None);
+        assert_eq!(
+            state.to_string(),
+            "core:furnace[facing=north,lit=false,level=-1]"
+        );
+
+        let lit = state
+            .with("facing", "south")
+            .unwrap()
+            .with("lit", true)
+            .unwrap();
+        assert_eq!(lit.id(), BlockId(1 + 2 * 6 + 3));
+        assert_eq!(lit.get("facing"), Some(StateValue::Enum("south")));
+        assert_eq!(lit.get("lit"), Some(StateValue::Bool(true)));
+        assert_eq!(
+            lit.with("facing", "north").unwrap().get("lit"),
+            Some(true.into())
+        );
+        assert!(lit.with("level", 2).is_none());
+        assert!(lit.with("lit", 1).is_none());
+
+        let printed = registry.state(lit.id()).unwrap().to_string();
+        assert_eq!(registry.parse_state(&printed), Ok(lit.id()));
+        assert_eq!(
+            registry.parse_state("furnace[level=1, lit=true]"),
+            Ok(BlockId(1 + 5))
+        );
+        assert_eq!(registry.parse_state("furnace"), Ok(furnace));
+        assert_eq!(registry.state(stone).unwrap().to_string(), "core:stone");
+        for id in 0..registry.len() as u32 {
+            let printed = registry.state(BlockId(id)).unwrap().to_string();
+            assert_eq!(registry.parse_state(&printed), Ok(BlockId(id)));
+        }
+
+        assert!(matches!(
+            registry.parse_state("furnace[heat=1]"),
+            Err(RegistryError::UnknownProperty(..))
+        ));
+        assert!(matches!(
+            registry.parse_state("furnace[lit=maybe]"),
+            Err(RegistryError::InvalidValue(..))
+        ));
+        assert!(matches!(
+            registry.parse_state("furnace[lit=true"),
+            Err(RegistryError::Syntax(_))
+        ));
+        assert!(matches!(
+            registry.parse_state("core:anvil"),
+            Err(RegistryError::UnknownBlock(_))
+        ));
+
+        let twice = vec![StateProperty::bool("lit"), StateProperty::bool("lit")];
+        assert!(matches!(
+            registry.register_states("lamp", BlockProperties::SOLID, twice),
+            Err(RegistryError::InvalidStates(_))
+        ));
+        let empty = vec![StateProperty::enumeration("color", &[])];
+        assert!(matches!(
+            registry.register_states("wool", BlockProperties::SOLID, empty),
+            Err(RegistryError::InvalidStates(_))
+        ));
+        for values in [&["red", "red"][..], &["red", ""], &["light blue"]] {
+            let states = vec![StateProperty::enumeration("color", values)];
+            assert!(matches!(
+                registry.register_states("wool", BlockProperties::SOLID, states),
+                Err(RegistryError::InvalidStates(_))
+            ));
+        }
+    }
+}
@@ -0,0 +1,248 @@
+use std::{fmt, ops::RangeInclusive};
+
+use super::{
+    BlockId,
+    registry::{BlockKey, BlockProperties},
+};
+
+/// A typed property that multiplies the number of states of a block.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct StateProperty {
+    name: String,
+    kind: StateKind,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum StateKind {
+    Bool,
+    Int(RangeInclusive<i32>),
+    Enum(Vec<String>),
+}
+
+/// Value of a [`StateProperty`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum StateValue<'a> {
+    Bool(bool),
+    Int(i32),
+    Enum(&'a str),
+}
+
+impl From<bool> for StateValue<'_> {
+    fn from(value: bool) -> Self {
+        StateValue::Bool(value)
+    }
+}
+
+impl From<i32> for StateValue<'_> {
+    fn from(value: i32) -> Self {
+        StateValue::Int(value)
+    }
+}
+
+impl<'a> From<&'a str> for StateValue<'a> {
+    fn from(value: &'a str) -> Self {
+        StateValue::Enum(value)
+    }
+}
+
+impl fmt::Display for StateValue<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StateValue::Bool(value) => write!(f, "{value}"),
+            StateValue::Int(value) => write!(f, "{value}"),
+            StateValue::Enum(value) => f.write_str(value),
+        }
+    }
+}
+
+impl StateProperty {
+    pub fn bool(name: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            kind: StateKind::Bool,
+        }
+    }
+
+    pub fn int(name: &str, range: RangeInclusive<i32>) -> Self {
+        Self {
+            name: name.to_owned(),
+            kind: StateKind::Int(range),
+        }
+    }
+
+    pub fn enumeration(name: &str, values: &[&str]) -> Self {
+        Self {
+            name: name.to_owned(),
+            kind: StateKind::Enum(values.iter().map(|v| (*v).to_owned()).collect()),
+        }
+    }
+
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    #[inline]
+    pub fn kind(&self) -> &StateKind {
+        &self.kind
+    }
+
+    /// Number of values the property can take, `0` if it has none.
+    pub fn value_count(&self) -> u32 {
+        match &self.kind {
+            StateKind::Bool => 2,
+            StateKind::Int(range) => match range.is_empty() {
+                true => 0,
+                false => range.end().abs_diff(*range.start()).saturating_add(1),
+            },
+            StateKind::Enum(values) => u32::try_from(values.len()).unwrap_or(u32::MAX),
+        }
+    }
+
+    /// Index of `value` among the values of the property.
+    ///
+    /// The first value (`false`, the range start, the first variant) is the default.
+    pub fn index_of(&self, value: StateValue<'_>) -> Option<u32> {
+        match (&self.kind, value) {
+            (StateKind::Bool, StateValue::Bool(value)) => Some(value as u32),
+            (StateKind::Int(range), StateValue::Int(value)) if range.contains(&value) => {
+                Some(value.abs_diff(*range.start()))
+            }
+            (StateKind::Enum(values), StateValue::Enum(value)) => {
+                values.iter().position(|v| v == value).map(|i| i as u32)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn value_at(&self, index: u32) -> Option<StateValue<'_>> {
+        if index >= self.value_count() {
+            return None;
+        }
+
+        return Some(match &self.kind {
+            StateKind::Bool => StateValue::Bool(index != 0),
+            StateKind::Int(range) => StateValue::Int(range.start().wrapping_add_unsigned(index)),
+            StateKind::Enum(values) => StateValue::Enum(&values[index as usize]),
+        });
+    }
+
+    /// Parses the textual form of a value, as printed by [`StateValue`].
+    pub fn parse_value(&self, value: &str) -> Option<u32> {
+        let value = match &self.kind {
+            StateKind::Bool => StateValue::Bool(value.parse().ok()?),
+            StateKind::Int(_) => StateValue::Int(value.parse().ok()?),
+            StateKind::Enum(_) => StateValue::Enum(value),
+        };
+        return self.index_of(value);
+    }
+}
+
+/// A single state of a registered block.
+///
+/// States of a block occupy a contiguous [`BlockId`] range starting at its default
+/// state. Each property is a digit of the offset into that range, the last property
+/// varying fastest.
+#[derive(Clone, Copy, Debug)]
+pub struct BlockState<'r> {
+    pub(super) key: &'r BlockKey,
+    pub(super) properties: &'r BlockProperties,
+    pub(super) states: &'r [StateProperty],
+    pub(super) base: BlockId,
+    pub(super) offset: u32,
+}
+
+impl<'r> BlockState<'r> {
+    #[inline]
+    pub fn id(&self) -> BlockId {
+        BlockId(self.base.0 + self.offset)
+    }
+
+    /// Id of the block with every property at its default value.
+    #[inline]
+    pub fn default_id(&self) -> BlockId {
+        self.base
+    }
+
+    #[inline]
+    pub fn key(&self) -> &'r BlockKey {
+        self.key
+    }
+
+    #[inline]
+    pub fn properties(&self) -> &'r BlockProperties {
+        self.properties
+    }
+
+    #[inline]
+    pub fn state_properties(&self) -> &'r [StateProperty] {
+        self.states
+    }
+
+    /// Number of states of the block.
+    pub fn state_count(&self) -> u32 {
+        self.states.iter().map(StateProperty::value_count).product()
+    }
+
+    pub fn get(&self, property: &str) -> Option<StateValue<'r>> {
+        let (index, stride) = self.find(property)?;
+        let state = &self.states[index];
+        return state.value_at(self.offset / stride % state.value_count());
+    }
+
+    /// The same block with `property` set to `value`.
+    pub fn with<'v>(&self, property: &str, value: impl Into<StateValue<'v>>) -> Option<Self> {
+        let (index, stride) = self.find(property)?;
+        let value = self.states[index].index_of(value.into())?;
+        return Some(self.with_index(index, stride, value));
+    }
+
+    /// Like [`Self::with`], but with the value in its textual form.
+    pub fn with_str(&self, property: &str, value: &str) -> Option<Self> {
+        let (index, stride) = self.find(property)?;
+        let value = self.states[index].parse_value(value)?;
+        return Some(self.with_index(index, stride, value));
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&'r StateProperty, StateValue<'r>)> {
+        let state = *self;
+        self.states
+            .iter()
+            .map(move |property| (property, state.get(&property.name).unwrap()))
+    }
+
+    fn with_index(&self, index: usize, stride: u32, value: u32) -> Self {
+        let current = self.offset / stride % self.states[index].value_count();
+        let offset = self.offset - current * stride + value * stride;
+        return Self { offset, ..*self };
+    }
+
+    /// Position of `property` and the id distance between consecutive values.
+    fn find(&self, property: &str) -> Option<(usize, u32)> {
+        let index = self.states.iter().position(|s| s.name == property)?;
+        let stride = self.states[index + 1..]
+            .iter()
+            .map(StateProperty::value_count)
+            .product();
+        return Some((index, stride));
+    }
+}
+
+/// Prints `namespace:path[property=value,...]`, omitting the brackets for blocks without properties.
+impl fmt::Display for BlockState<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.key)?;
+        if self.states.is_empty() {
+            return Ok(());
+        }
+
+        f.write_str("[")?;
+        for (i, (property, value)) in self.iter().enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+            write!(f, "{}={value}", property.name)?;
+        }
+        return f.write_str("]");
+    }
+}
@@ -0,0 +1,169 @@
+use crate::block::{BlockBox, BlockCoord, BlockSize};
+
+use super::get_index_base;
+
+/// Edge length of the cubic sections summarized by [`BlockChanges::dirty_sections`].
+pub const SECTION_SIDE: usize = 16;
+
+/// Blocks of a chunk changed since the last drain, see [`super::Chunk::drain_changes`].
+#[derive(Clone, Debug)]
+pub struct BlockChanges {
+    /// One bit per block, in storage order.
+    bits: Vec<u64>,
+    /// One bit per [`SECTION_SIDE`]³ section holding a changed block, in storage order.
+    sections: Vec<u64>,
+    size: BlockSize,
+    bounds: BlockBox,
+    len: usize,
+    count: u64,
+}
+
+impl BlockChanges {
+    pub fn new(size: BlockSize) -> Self {
+        let sections = section_grid(size).volume();
+        Self {
+            bits: vec![0; size.volume().div_ceil(u64::BITS as usize)],
+            sections: vec![0; sections.div_ceil(u64::BITS as usize)],
+            size,
+            bounds: BlockBox::default(),
+            len: 0,
+            count: 0,
+        }
+    }
+
+    /// Number of distinct changed blocks.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Number of recorded block changes, counting repeated changes of the same block.
+    #[inline]
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Smallest box containing every changed block, empty without changes.
+    #[inline]
+    pub fn bounds(&self) -> BlockBox {
+        self.bounds
+    }
+
+    /// Size of the chunk the changes belong to.
+    #[inline]
+    pub fn size(&self) -> BlockSize {
+        self.size
+    }
+
+    #[inline]
+    pub fn contains(&self, coord: BlockCoord) -> bool {
+        if !self.bounds.contains(coord) {
+            return false;
+        }
+        return self.is_set(self.offset(coord));
+    }
+
+    /// Changed blocks in storage order.
+    pub fn iter(&self) -> impl Iterator<Item = BlockCoord> + '_ {
+        self.bounds
+            .iter()
+            .filter(|coord| self.is_set(self.offset(*coord)))
+    }
+
+    /// `(y, z)` of rows along X holding at least one changed block, in storage order.
+    pub fn rows(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        let (min, max) = (self.bounds.min, self.bounds.max());
+        (min.y..max.y)
+            .flat_map(move |y| (min.z..max.z).map(move |z| (y, z)))
+            .filter(move |&(y, z)| {
+                let start = self.offset(BlockCoord { x: min.x, y, z });
+                (start..start + self.bounds.size.width).any(|offset| self.is_set(offset))
+            })
+    }
+
+    /// Number of sections along each axis, partial sections at the far edges included.
+    #[inline]
+    pub fn section_grid(&self) -> BlockSize {
+        section_grid(self.size)
+    }
+
+    /// Whether the section at `section`, in units of [`SECTION_SIDE`], holds a changed block.
+    pub fn is_section_dirty(&self, section: BlockCoord) -> bool {
+        let grid = self.section_grid();
+        if section.x >= grid.width || section.y >= grid.height || section.z >= grid.depth {
+            return false;
+        }
+        let index = get_index_base(grid.depth, grid.width, section.y, section.z) + section.x;
+        return is_set(&self.sections, index);
+    }
+
+    /// Sections holding at least one changed block, in storage order.
+    pub fn dirty_sections(&self) -> impl Iterator<Item = BlockCoord> + '_ {
+        BlockBox::from_size(self.section_grid())
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| is_set(&self.sections, *index))
+            .map(|(_, section)| section)
+    }
+
+    /// Records a change of the block at `coord`.
+    pub fn mark(&mut self, coord: BlockCoord) {
+        let offset = self.offset(coord);
+        let (word, bit) = (offset / u64::BITS as usize, offset % u64::BITS as usize);
+        self.count += 1;
+        if self.bits[word] & (1 << bit) != 0 {
+            return;
+        }
+
+        self.bits[word] |= 1 << bit;
+        self.len += 1;
+        let section = self.section_index(coord);
+        self.sections[section / u64::BITS as usize] |= 1 << (section % u64::BITS as usize);
+        self.bounds = self.bounds.union(BlockBox::new(coord, BlockSize::splat(1)));
+    }
+
+    /// Forgets every change, keeping the allocation.
+    pub fn clear(&mut self) {
+        self.bits.fill(0);
+        self.sections.fill(0);
+        self.bounds = BlockBox::default();
+        self.len = 0;
+        self.count = 0;
+    }
+
+    #[inline]
+    fn offset(&self, coord: BlockCoord) -> usize {
+        get_index_base(self.size.depth, self.size.width, coord.y, coord.z) + coord.x
+    }
+
+    #[inline]
+    fn section_index(&self, coord: BlockCoord) -> usize {
+        let grid = self.section_grid();
+        let (y, z) = (coord.y / SECTION_SIDE, coord.z / SECTION_SIDE);
+        get_index_base(grid.depth, grid.width, y, z) + coord.x / SECTION_SIDE
+    }
+
+    #[inline]
+    fn is_set(&self, offset: usize) -> bool {
+        is_set(&self.bits, offset)
+    }
+}
+
+fn section_grid(size: BlockSize) -> BlockSize {
+    BlockSize {
+        width: size.width.div_ceil(SECTION_SIDE),
+        height: size.height.div_ceil(SECTION_SIDE),
+        depth: size.depth.div_ceil(SECTION_SIDE),
+    }
+}
+
+#[inline]
+fn is_set(bits: &[u64], index: usize) -> bool {
+    let (word, bit) = (index / u64::BITS as usize, index % u64::BITS as usize);
+    bits[word] & (1 << bit) != 0
+}
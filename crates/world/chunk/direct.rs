@@ -0,0 +1,240 @@
+use std::num::NonZeroUsize;
+
+use collections::OwnedCut;
+use pack::{
+    order::PackOrder,
+    part::PartSize,
+    span::{PackAccess, PackAccessMut},
+    vec::PackVec,
+};
+
+use crate::block::{BlockCoord, BlockId, BlockSize};
+
+use super::{
+    BlockStorage, BlockView, Chunk, get_index_base, layout::BlockLayout, palette::resize_storage,
+};
+
+/// Block storage holding raw [`BlockId`]s, for chunks with too many distinct
+/// blocks to benefit from a [`super::palette::ChunkPalette`].
+///
+/// The bit width grows to fit the largest id written.
+#[derive(Clone, Debug)]
+pub struct ChunkDirect<const W: usize = 16, const H: usize = 16, const D: usize = 16> {
+    data: PackVec,
+    layout: BlockLayout,
+}
+
+pub(super) const fn get_storage_bits_for_id(id: BlockId) -> PartSize {
+    let used_bits = (u32::BITS - id.0.leading_zeros()) as usize;
+    let size = if used_bits == 0 { 1 } else { used_bits };
+    PartSize::new(size).unwrap()
+}
+
+impl<const W: usize, const H: usize, const D: usize> ChunkDirect<W, H, D> {
+    /// Storage with every block set to `value`, wide enough for ids of `value_bits`.
+    ///
+    /// Pass [`crate::block::registry::BlockRegistry::id_bits`] to avoid widening later.
+    #[inline]
+    pub fn new(value: BlockId, value_bits: PartSize) -> Self {
+        Self::with_layout(value, value_bits, BlockLayout::Linear)
+    }
+
+    /// Like [`ChunkDirect::new`], keeping blocks in `layout`, which must be
+    /// supported, see [`Chunk::set_layout`].
+    pub(super) fn with_layout(value: BlockId, value_bits: PartSize, layout: BlockLayout) -> Self {
+        let value_bits = value_bits.max(get_storage_bits_for_id(value));
+        let mut data = PackVec::new_var(value_bits);
+        data.extend_with(Chunk::<W, H, D>::VOLUME, value.0.into());
+        return Self { data, layout };
+    }
+
+    #[inline]
+    pub fn layout(&self) -> BlockLayout {
+        self.layout
+    }
+
+    /// Moves every block to its place in `layout`, which must be supported.
+    pub(super) fn set_layout(&mut self, layout: BlockLayout) {
+        if layout != self.layout {
+            self.data = BlockLayout::relayout::<W, H, D>(self.layout, layout, &self.data);
+            self.layout = layout;
+        }
+    }
+
+    /// Bits used to store each block.
+    #[inline]
+    pub fn value_bits(&self) -> usize {
+        self.data.order().value_bits().get()
+    }
+
+    /// The packed block data, for comparing storage of the same layout.
+    #[inline]
+    pub(super) fn data(&self) -> &PackVec {
+        &self.data
+    }
+
+    /// Bytes allocated on the heap for the block data.
+    #[inline]
+    pub fn heap_size(&self) -> usize {
+        size_of_val(self.data.as_slice())
+    }
+
+    /// Widens storage to fit `value`.
+    fn reserve_value(&mut self, value: BlockId) {
+        let value_bits = get_storage_bits_for_id(value);
+        if value_bits > self.data.order().value_bits() {
+            std::hint::cold_path();
+            self.data = resize_storage(&self.data, value_bits);
+        }
+    }
+
+    /// Row length and count per layer, merging rows when both sides store whole layers.
+    fn get_stride(&self, size: BlockSize, bounds: BlockSize) -> (usize, usize) {
+        if size.width == self.width().get()
+            && size.depth == self.depth().get()
+            && bounds.width == size.width
+            && bounds.depth == size.depth
+        {
+            return (size.width * size.depth, 1);
+        }
+        return (size.width, size.depth);
+    }
+}
+
+impl<const W: usize, const H: usize, const D: usize> BlockView for ChunkDirect<W, H, D> {
+    #[inline]
+    fn width(&self) -> NonZeroUsize {
+        Chunk::<W, H, D>::WIDTH
+    }
+
+    #[inline]
+    fn height(&self) -> NonZeroUsize {
+        Chunk::<W, H, D>::HEIGHT
+    }
+
+    #[inline]
+    fn depth(&self) -> NonZeroUsize {
+        Chunk::<W, H, D>::DEPTH
+    }
+
+    fn get_at(&self, offset: usize) -> Option<BlockId> {
+        let slot = self.layout.slot::<W, H, D>(offset);
+        self.data.get::<u32>(slot).map(BlockId)
+    }
+
+    fn get_slice_core(
+        &self,
+        offset: BlockCoord,
+        size: BlockSize,
+        dst_offset: BlockCoord,
+        dst_bounds: BlockSize,
+        dst: &mut [BlockId],
+    ) {
+        if self.layout != BlockLayout::Linear {
+            let (data, layout) = (&self.data, self.layout);
+            layout.for_each_slot::<W, H, D>(offset, size, dst_offset, dst_bounds, |slot, i| {
+                dst[i] = BlockId(data.get::<u32>(slot).unwrap());
+            });
+            return;
+        }
+
+        let (stride, rows) = self.get_stride(size, dst_bounds);
+        let value_bits = self.data.order().value_bits();
+        let mut buffer = vec![0u32; stride];
+
+        for y in 0..size.height {
+            let dst_y = dst_offset.y + y;
+
+            for z in 0..rows {
+                let src_idx = self.get_offset(offset.x, offset.y + y, offset.z + z);
+                pack::unpack(&mut buffer, self.data.as_slice(), src_idx, value_bits);
+
+                let dst_idx =
+                    get_index_base(dst_bounds.depth, dst_bounds.width, dst_y, dst_offset.z + z)
+                        + dst_offset.x;
+                let dst_slice = dst.cut(dst_idx..(dst_idx + stride));
+                for (dst, src) in dst_slice.iter_mut().zip(&buffer) {
+                    *dst = BlockId(*src);
+                }
+            }
+        }
+    }
+}
+
+impl<const W: usize, const H: usize, const D: usize> BlockStorage for ChunkDirect<W, H, D> {
+    fn set_at(&mut self, offset: usize, value: BlockId) -> Option<bool> {
+        if offset >= self.data.len() {
+            return None;
+        }
+        self.reserve_value(value);
+        let prev = self
+            .data
+            .set(self.layout.slot::<W, H, D>(offset), value.0)?;
+        return Some(prev != value.0);
+    }
+
+    fn set_slice_core(
+        &mut self,
+        offset: BlockCoord,
+        size: BlockSize,
+        src_offset: BlockCoord,
+        src_bounds: BlockSize,
+        src: &[BlockId],
+    ) {
+        if self.layout != BlockLayout::Linear {
+            let layout = self.layout;
+            layout.for_each_slot::<W, H, D>(offset, size, src_offset, src_bounds, |slot, i| {
+                self.reserve_value(src[i]);
+                self.data.set(slot, src[i].0);
+            });
+            return;
+        }
+
+        let (stride, rows) = self.get_stride(size, src_bounds);
+        let mut buffer = vec![0u32; stride];
+
+        for y in 0..size.height {
+            let src_y = src_offset.y + y;
+
+            for z in 0..rows {
+                let src_idx =
+                    get_index_base(src_bounds.depth, src_bounds.width, src_y, src_offset.z + z)
+                        + src_offset.x;
+                let src_slice = src.cut(src_idx..(src_idx + stride));
+                for (dst, src) in buffer.iter_mut().zip(src_slice) {
+                    *dst = src.0;
+                }
+
+                // Widen for the largest id before packing the row in bulk.
+                let max = buffer.iter().copied().max().unwrap_or(0);
+                self.reserve_value(BlockId(max));
+
+                let dst_idx = self.get_offset(offset.x, offset.y + y, offset.z + z);
+                let value_bits = self.data.order().value_bits();
+                pack::pack(self.data.as_slice_mut(), dst_idx, &buffer, value_bits);
+            }
+        }
+    }
+
+    fn fill_core(&mut self, offset: BlockCoord, size: BlockSize, value: BlockId) {
+        self.reserve_value(value);
+        if self.layout != BlockLayout::Linear {
+            let layout = self.layout;
+            layout.for_each_slot::<W, H, D>(offset, size, offset, self.size(), |slot, _| {
+                self.data.set(slot, value.0);
+            });
+            return;
+        }
+
+        let (stride, rows) = self.get_stride(size, size);
+        for y in 0..size.height {
+            for z in 0..rows {
+                let dst_idx = self.get_offset(offset.x, offset.y + y, offset.z + z);
+                self.data
+                    .as_span_mut()
+                    .cut(dst_idx..(dst_idx + stride))
+                    .fill(value.0);
+            }
+        }
+    }
+}
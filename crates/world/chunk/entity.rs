@@ -0,0 +1,567 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    error::Error,
+    fmt,
+    ops::{Range, RangeInclusive},
+    sync::Arc,
+};
+
+use bevy::{
+    prelude::Resource,
+    reflect::{Reflect, TypePath},
+};
+
+use crate::{
+    block::{BlockBox, BlockCoord, BlockId, state::BlockState},
+    storage::{Decode, DecodeError, Encode, Reader},
+};
+
+use super::{BlockStorage, BlockView, Chunk, StorageError, check_box};
+
+/// Data of a block beside its id, like the text of a sign.
+///
+/// Implemented for every cloneable reflected type with an [`Encode`] impl. Saved
+/// data is tagged with its type path, so renaming a type breaks existing saves.
+pub trait BlockData: Reflect {
+    /// Type path the data is saved under, see [`BlockDataTypes`].
+    fn data_type_path(&self) -> &str;
+
+    fn encode_data(&self, out: &mut Vec<u8>);
+
+    fn clone_data(&self) -> Box<dyn BlockData>;
+}
+
+impl<T: Reflect + TypePath + Encode + Clone> BlockData for T {
+    #[inline]
+    fn data_type_path(&self) -> &str {
+        T::type_path()
+    }
+
+    #[inline]
+    fn encode_data(&self, out: &mut Vec<u8>) {
+        self.encode(out);
+    }
+
+    #[inline]
+    fn clone_data(&self) -> Box<dyn BlockData> {
+        Box::new(self.clone())
+    }
+}
+
+/// Block entities by offset.
+pub(super) type EntityMap = BTreeMap<usize, BlockEntity>;
+
+/// Entities of a chunk without any, see [`Chunk::entity_map`].
+pub(super) static NO_ENTITIES: EntityMap = BTreeMap::new();
+
+/// Writes entities by offset, read back by [`decode_entity_map`].
+pub(super) fn encode_entities(entities: &EntityMap, out: &mut Vec<u8>) {
+    out.extend_from_slice(&(entities.len() as u32).to_le_bytes());
+    for (offset, entity) in entities {
+        let mut data = Vec::new();
+        entity.data.encode_data(&mut data);
+        let type_path = entity.data.data_type_path();
+
+        out.extend_from_slice(&(*offset as u32).to_le_bytes());
+        out.extend_from_slice(&entity.states.start.to_le_bytes());
+        out.extend_from_slice(&entity.states.end.to_le_bytes());
+        out.extend_from_slice(&(type_path.len() as u16).to_le_bytes());
+        out.extend_from_slice(type_path.as_bytes());
+        out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        out.extend_from_slice(&data);
+    }
+}
+
+/// Loaded data of a type not decoded yet, see [`BlockDataTypes::decode_entities`].
+///
+/// Saved again as it was loaded.
+#[derive(Reflect, Clone, Debug, PartialEq)]
+pub struct UndecodedData {
+    pub type_path: String,
+    pub bytes: Vec<u8>,
+}
+
+impl BlockData for UndecodedData {
+    #[inline]
+    fn data_type_path(&self) -> &str {
+        &self.type_path
+    }
+
+    #[inline]
+    fn encode_data(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.bytes);
+    }
+
+    #[inline]
+    fn clone_data(&self) -> Box<dyn BlockData> {
+        Box::new(self.clone())
+    }
+}
+
+/// Data attached to a block, kept while the block stays of the same type.
+///
+/// Changing the block to another state of its type keeps the data, changing it
+/// to any other block drops it.
+pub struct BlockEntity {
+    /// Ids of every state of the block type.
+    states: Range<u32>,
+    data: Box<dyn BlockData>,
+}
+
+impl BlockEntity {
+    /// Data for blocks of the type of `state`, `None` if the type does not declare data.
+    pub fn new(state: BlockState<'_>, data: impl BlockData) -> Option<Self> {
+        if !state.properties().has_data {
+            return None;
+        }
+        let base = state.default_id().0;
+        return Some(Self {
+            states: base..base + state.state_count(),
+            data: Box::new(data),
+        });
+    }
+
+    /// Whether `block` is of the type the data belongs to.
+    #[inline]
+    pub fn accepts(&self, block: BlockId) -> bool {
+        self.states.contains(&block.0)
+    }
+
+    #[inline]
+    pub fn data(&self) -> &dyn BlockData {
+        &*self.data
+    }
+
+    #[inline]
+    pub fn data_mut(&mut self) -> &mut dyn BlockData {
+        &mut *self.data
+    }
+
+    #[inline]
+    pub fn downcast_ref<T: Reflect>(&self) -> Option<&T> {
+        let data: &dyn Reflect = &*self.data;
+        return data.downcast_ref();
+    }
+
+    #[inline]
+    pub fn downcast_mut<T: Reflect>(&mut self) -> Option<&mut T> {
+        let data: &mut dyn Reflect = &mut *self.data;
+        return data.downcast_mut();
+    }
+}
+
+impl Clone for BlockEntity {
+    fn clone(&self) -> Self {
+        Self {
+            states: self.states.clone(),
+            data: self.data.clone_data(),
+        }
+    }
+}
+
+impl fmt::Debug for BlockEntity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let data: &dyn Reflect = &*self.data;
+        f.debug_struct("BlockEntity")
+            .field("states", &self.states)
+            .field("data", &data)
+            .finish()
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum EntityError {
+    /// The offset is past the end of the chunk.
+    OutOfBounds(usize),
+
+    /// The block at the offset is not of the type the data belongs to.
+    WrongBlock(BlockId),
+}
+
+impl fmt::Display for EntityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EntityError::OutOfBounds(offset) => write!(f, "offset {offset} is out of bounds"),
+            EntityError::WrongBlock(block) => write!(f, "{block:?} does not accept the data"),
+        }
+    }
+}
+
+impl Error for EntityError {}
+
+impl<const W: usize, const H: usize, const D: usize> Chunk<W, H, D> {
+    /// Entities by offset.
+    #[inline]
+    pub(super) fn entity_map(&self) -> &EntityMap {
+        self.entities.as_deref().unwrap_or(&NO_ENTITIES)
+    }
+
+    /// Entities by offset for writing, copied first if shared with a snapshot.
+    fn entity_map_mut(&mut self) -> &mut EntityMap {
+        Arc::make_mut(self.entities.get_or_insert_default())
+    }
+
+    #[inline]
+    pub fn entity(&self, offset: usize) -> Option<&BlockEntity> {
+        self.entity_map().get(&offset)
+    }
+
+    pub fn entity_mut(&mut self, offset: usize) -> Option<&mut BlockEntity> {
+        // Keeps shared entities shared when there is nothing to write.
+        if !self.entity_map().contains_key(&offset) {
+            return None;
+        }
+        return self.entity_map_mut().get_mut(&offset);
+    }
+
+    /// Entities with their offsets, in storage order.
+    pub fn entities(&self) -> impl ExactSizeIterator<Item = (usize, &BlockEntity)> {
+        self.entity_map()
+            .iter()
+            .map(|(offset, entity)| (*offset, entity))
+    }
+
+    /// Attaches `entity` to the block at `offset`, returning the entity it replaced.
+    pub fn insert_entity(
+        &mut self,
+        offset: usize,
+        entity: BlockEntity,
+    ) -> Result<Option<BlockEntity>, EntityError> {
+        let block = self
+            .get_at(offset)
+            .ok_or(EntityError::OutOfBounds(offset))?;
+        if !entity.accepts(block) {
+            return Err(EntityError::WrongBlock(block));
+        }
+        return Ok(self.entity_map_mut().insert(offset, entity));
+    }
+
+    pub fn remove_entity(&mut self, offset: usize) -> Option<BlockEntity> {
+        if !self.entity_map().contains_key(&offset) {
+            return None;
+        }
+        return self.entity_map_mut().remove(&offset);
+    }
+
+    /// Copies `region` of `src` to `dst_offset`, with the entities of the copied blocks.
+    ///
+    /// Unlike [`BlockStorage::set_slice`], which only keeps entities of blocks
+    /// whose type is unchanged, entities in the destination box are replaced by
+    /// clones of the source ones.
+    pub fn copy_from(
+        &mut self,
+        src: &Self,
+        region: BlockBox,
+        dst_offset: BlockCoord,
+    ) -> Result<(), StorageError> {
+        let dst_region = BlockBox::new(dst_offset, region.size);
+        check_box(dst_region, self.size())?;
+        let mut blocks = vec![BlockId::default(); region.volume()];
+        src.get_slice(region, BlockCoord::default(), region.size, &mut blocks)?;
+        self.set_slice(dst_region, BlockCoord::default(), region.size, &blocks)?;
+        if region.is_empty() {
+            return Ok(());
+        }
+
+        let dst_span = self.offset_span(dst_region);
+        let stale: Vec<_> = self
+            .entity_map()
+            .range(dst_span)
+            .map(|(offset, _)| *offset)
+            .filter(|offset| dst_region.contains(self.get_coord(*offset)))
+            .collect();
+        for offset in stale {
+            self.entity_map_mut().remove(&offset);
+        }
+        for (offset, entity) in src.entity_map().range(src.offset_span(region)) {
+            let coord = src.get_coord(*offset);
+            if !region.contains(coord) {
+                continue;
+            }
+            let dst = self.get_offset(
+                dst_offset.x + coord.x - region.min.x,
+                dst_offset.y + coord.y - region.min.y,
+                dst_offset.z + coord.z - region.min.z,
+            );
+            self.entity_map_mut().insert(dst, entity.clone());
+        }
+        return Ok(());
+    }
+
+    /// Offsets from the first to the last block of the non-empty `region`.
+    ///
+    /// Rows of the chunk between those of the box are included too.
+    fn offset_span(&self, region: BlockBox) -> RangeInclusive<usize> {
+        let max = region.max();
+        let last = self.get_offset(max.x - 1, max.y - 1, max.z - 1);
+        return self.get_coord_offset(region.min)..=last;
+    }
+
+    /// Drops entities in `region` whose block changed to another type, called after a write.
+    pub(super) fn prune_entities(&mut self, region: BlockBox) {
+        if self.entity_map().is_empty() || region.is_empty() {
+            return;
+        }
+
+        let stale: Vec<_> = self
+            .entity_map()
+            .range(self.offset_span(region))
+            .filter(|(offset, entity)| {
+                let coord = self.get_coord(**offset);
+                region.contains(coord) && !entity.accepts(self.get_at(**offset).unwrap())
+            })
+            .map(|(offset, _)| *offset)
+            .collect();
+        for offset in stale {
+            self.entity_map_mut().remove(&offset);
+        }
+    }
+
+    /// Reads entities written by [`encode_entities`] into the chunk.
+    pub(super) fn decode_entities(&mut self, input: &mut Reader<'_>) -> Result<(), DecodeError> {
+        for (offset, entity) in decode_entity_map(input)? {
+            self.insert_entity(offset, entity)
+                .map_err(|_| DecodeError::InvalidValue("block entity"))?;
+        }
+        return Ok(());
+    }
+}
+
+/// Reads entities written by [`encode_entities`] as [`UndecodedData`].
+pub(super) fn decode_entity_map(input: &mut Reader<'_>) -> Result<EntityMap, DecodeError> {
+    let count = input.read_u32()?;
+    let mut entities = EntityMap::new();
+    for _ in 0..count {
+        let offset = input.read_u32()? as usize;
+        let states = input.read_u32()?..input.read_u32()?;
+        let path_len = input.read_u16()? as usize;
+        let type_path = str::from_utf8(input.read_bytes(path_len)?)
+            .map_err(|_| DecodeError::InvalidValue("block data type path"))?;
+        let data_len = input.read_u32()? as usize;
+        let bytes = input.read_bytes(data_len)?;
+
+        let entity = BlockEntity {
+            states,
+            data: Box::new(UndecodedData {
+                type_path: type_path.to_owned(),
+                bytes: bytes.to_vec(),
+            }),
+        };
+        entities.insert(offset, entity);
+    }
+    return Ok(entities);
+}
+
+type DecodeData = fn(&mut Reader<'_>) -> Result<Box<dyn BlockData>, DecodeError>;
+
+/// Block data types by type path, to decode the data of loaded chunks.
+#[derive(Resource, Debug, Default)]
+pub struct BlockDataTypes {
+    decoders: HashMap<&'static str, DecodeData>,
+}
+
+impl BlockDataTypes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register<T: BlockData + TypePath + Decode>(&mut self) {
+        self.decoders.insert(T::type_path(), |input| {
+            return Ok(Box::new(T::decode(input)?));
+        });
+    }
+
+    /// Replaces [`UndecodedData`] of registered types in `chunk` with the decoded data.
+    ///
+    /// Data of unknown types stays undecoded. On error, the data of the failing
+    /// entity and of those after it stays undecoded.
+    pub fn decode_entities<const W: usize, const H: usize, const D: usize>(
+        &self,
+        chunk: &mut Chunk<W, H, D>,
+    ) -> Result<(), DecodeError> {
+        if chunk.entity_map().is_empty() {
+            return Ok(());
+        }
+        for entity in chunk.entity_map_mut().values_mut() {
+            let Some(undecoded) = entity.downcast_ref::<UndecodedData>() else {
+                continue;
+            };
+            let Some(decode) = self.decoders.get(undecoded.type_path.as_str()) else {
+                continue;
+            };
+
+            let mut input = Reader::new(&undecoded.bytes);
+            let data = decode(&mut input)?;
+            if !input.is_empty() {
+                return Err(DecodeError::TrailingBytes(input.remaining()));
+            }
+            entity.data = data;
+        }
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        block::{
+            BlockCoord, BlockSize,
+            registry::{BlockProperties, BlockRegistry},
+            state::StateProperty,
+        },
+        chunk::Chunk16,
+        storage::{decode_from_slice, encode_to_vec},
+    };
+
+    #[derive(Reflect, Clone, Debug, PartialEq)]
+    struct SignText {
+        text: String,
+    }
+
+    impl Encode for SignText {
+        fn encode(&self, out: &mut Vec<u8>) {
+            out.extend_from_slice(&(self.text.len() as u32).to_le_bytes());
+            out.extend_from_slice(self.text.as_bytes());
+        }
+    }
+
+    impl Decode for SignText {
+        fn decode(input: &mut Reader<'_>) -> Result<Self, DecodeError> {
+            let len = input.read_u32()? as usize;
+            let text = str::from_utf8(input.read_bytes(len)?)
+                .map_err(|_| DecodeError::InvalidValue("sign text"))?;
+            return Ok(Self {
+                text: text.to_owned(),
+            });
+        }
+    }
+
+    fn sign_text(chunk: &Chunk16, offset: usize) -> Option<&str> {
+        let text = chunk.entity(offset)?.downcast_ref::<SignText>()?;
+        return Some(&text.text);
+    }
+
+    #[test]
+    fn entities() {
+        let mut registry = BlockRegistry::new();
+        let stone = registry
+            .register("core:stone", BlockProperties::SOLID)
+            .unwrap();
+        let data = BlockProperties {
+            has_data: true,
+            ..BlockProperties::SOLID
+        };
+        let facing = StateProperty::enumeration("facing", &["north", "south"]);
+        let sign = registry
+            .register_states("core:sign", data, vec![facing])
+            .unwrap();
+        let sign_south = registry
+            .state(sign)
+            .unwrap()
+            .with("facing", "south")
+            .unwrap()
+            .id();
+        let new_entity = |text: &str| {
+            let text = SignText {
+                text: text.to_owned(),
+            };
+            BlockEntity::new(registry.state(sign).unwrap(), text).unwrap()
+        };
+        let text = SignText {
+            text: String::new(),
+        };
+        assert!(BlockEntity::new(registry.state(stone).unwrap(), text).is_none());
+
+        let mut chunk = Chunk16::empty();
+        let at = chunk.get_offset(1, 2, 3);
+        assert_eq!(
+            chunk.insert_entity(at, new_entity("hi")).unwrap_err(),
+            EntityError::WrongBlock(BlockId::AIR)
+        );
+        assert_eq!(
+            chunk
+                .insert_entity(Chunk16::VOLUME, new_entity("hi"))
+                .unwrap_err(),
+            EntityError::OutOfBounds(Chunk16::VOLUME)
+        );
+        chunk.set_at(at, sign);
+        assert!(chunk.insert_entity(at, new_entity("hi")).unwrap().is_none());
+        assert_eq!(sign_text(&chunk, at), Some("hi"));
+
+        // Another state of the sign keeps the text, as do copies of the same blocks.
+        chunk.set_at(at, sign_south);
+        let blocks = chunk.to_blocks();
+        let size = chunk.size();
+        chunk
+            .set_slice(chunk.bounds(), BlockCoord::default(), size, &blocks)
+            .unwrap();
+        let beside = BlockBox::new(
+            BlockCoord { x: 0, y: 2, z: 3 },
+            BlockSize {
+                width: 1,
+                height: 1,
+                depth: 1,
+            },
+        );
+        chunk.fill(beside, stone).unwrap();
+        assert_eq!(sign_text(&chunk, at), Some("hi"));
+
+        let bytes = encode_to_vec(&chunk);
+        let snapshot = chunk.snapshot();
+        let shared = |chunk: &Chunk16| {
+            let (_, entity) = snapshot.entities().next().unwrap();
+            return std::ptr::eq(entity, chunk.entity(at).unwrap());
+        };
+        assert_eq!(snapshot.entities().len(), 1);
+        assert!(shared(&chunk));
+        assert_eq!(encode_to_vec(&snapshot), bytes);
+        let mut decoded: Chunk16 = decode_from_slice(&bytes).unwrap();
+        let undecoded = decoded.entity(at).unwrap().downcast_ref::<UndecodedData>();
+        assert_eq!(undecoded.unwrap().type_path, SignText::type_path());
+        let mut types = BlockDataTypes::new();
+        types.decode_entities(&mut decoded).unwrap();
+        assert!(
+            decoded
+                .entity(at)
+                .unwrap()
+                .downcast_ref::<SignText>()
+                .is_none()
+        );
+        types.register::<SignText>();
+        types.decode_entities(&mut decoded).unwrap();
+        assert_eq!(sign_text(&decoded, at), Some("hi"));
+        assert_eq!(encode_to_vec(&decoded), bytes);
+
+        // Writing any other block type drops the data.
+        chunk
+            .entity_mut(at)
+            .unwrap()
+            .downcast_mut::<SignText>()
+            .unwrap()
+            .text = "bye".into();
+        assert_eq!(sign_text(&chunk, at), Some("bye"));
+        assert!(!shared(&chunk));
+        chunk.fill(chunk.bounds(), sign).unwrap();
+        assert_eq!(chunk.entities().len(), 1);
+        chunk.set_at(at, stone);
+        assert!(chunk.entity(at).is_none());
+        chunk.set_at(at, sign);
+        assert!(chunk.entity(at).is_none());
+        assert!(chunk.remove_entity(at).is_none());
+
+        // Snapshots keep saving the entities they were taken with.
+        assert_eq!(encode_to_vec(&snapshot), bytes);
+
+        // Copies between chunks carry the entities of the copied blocks.
+        chunk.insert_entity(at, new_entity("copied")).unwrap();
+        let mut copy = Chunk16::empty();
+        let region = BlockBox::new(BlockCoord { x: 1, y: 2, z: 2 }, BlockSize::splat(2));
+        copy.copy_from(&chunk, region, BlockCoord::splat(4))
+            .unwrap();
+        let copied = copy.get_offset(4, 4, 5);
+        assert_eq!(copy.get_at(copied), Some(sign));
+        assert_eq!(sign_text(&copy, copied), Some("copied"));
+        assert_eq!(copy.entities().len(), 1);
+    }
+}
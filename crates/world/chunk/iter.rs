@@ -0,0 +1,237 @@
+use std::iter::FusedIterator;
+
+use iters::{
+    dispatch::dispatch,
+    search::{AllEq, ForEachRun, IndexOfAnyExcept, IndexOfMatch, IndexOfMismatch},
+};
+
+use crate::block::{BlockBox, BlockBoxIter, BlockCoord, BlockId, BlockSize};
+
+use super::BlockView;
+
+/// Index of the first block in `slice` other than `value`, searched with the
+/// lane count picked by [`dispatch`].
+#[inline]
+pub(super) fn block_index_of_any_except(slice: &[BlockId], value: BlockId) -> Option<usize> {
+    dispatch(IndexOfAnyExcept {
+        slice: bytemuck::cast_slice::<BlockId, u32>(slice),
+        value: value.0,
+    })
+}
+
+/// Whether every block in `slice` is `value`, see [`block_index_of_any_except`].
+#[inline]
+pub(super) fn block_all_eq(slice: &[BlockId], value: BlockId) -> bool {
+    dispatch(AllEq {
+        slice: bytemuck::cast_slice::<BlockId, u32>(slice),
+        value: value.0,
+    })
+}
+
+/// Index of the first block of `a` other than the block at the same index of
+/// `b`, see [`block_index_of_any_except`].
+#[inline]
+pub(super) fn block_index_of_mismatch(a: &[BlockId], b: &[BlockId]) -> Option<usize> {
+    dispatch(IndexOfMismatch {
+        slice: bytemuck::cast_slice::<BlockId, u32>(a),
+        other: bytemuck::cast_slice::<BlockId, u32>(b),
+    })
+}
+
+/// Index of the first block of `a` equal to the block at the same index of
+/// `b`, see [`block_index_of_any_except`].
+#[inline]
+pub(super) fn block_index_of_match(a: &[BlockId], b: &[BlockId]) -> Option<usize> {
+    dispatch(IndexOfMatch {
+        slice: bytemuck::cast_slice::<BlockId, u32>(a),
+        other: bytemuck::cast_slice::<BlockId, u32>(b),
+    })
+}
+
+/// Calls `f` with every run of equal blocks in `slice` as `(value, start, len)`,
+/// see [`block_index_of_any_except`].
+#[inline]
+pub(super) fn block_runs(slice: &[BlockId], mut f: impl FnMut(BlockId, usize, usize)) {
+    dispatch(ForEachRun {
+        slice: bytemuck::cast_slice::<BlockId, u32>(slice),
+        f: |value, start, len| f(BlockId(value), start, len),
+    })
+}
+
+/// Blocks of a box with their coordinates in storage order, created by
+/// [`BlockView::iter_blocks`] and [`BlockView::iter_box`].
+///
+/// Decodes one row along X at a time, and none for uniform storage.
+#[derive(Debug)]
+pub struct BlockIter<'a, S> {
+    storage: &'a S,
+    /// Start of every row not decoded yet.
+    rows: BlockBoxIter,
+    row: Vec<BlockId>,
+    row_start: BlockCoord,
+    x: usize,
+    uniform: bool,
+}
+
+impl<'a, S: BlockView> BlockIter<'a, S> {
+    /// Iterates `region`, which must lie within `storage`.
+    pub(super) fn new(storage: &'a S, region: BlockBox) -> Self {
+        let uniform = storage.uniform_value();
+        let (starts, width) = match region.is_empty() {
+            true => (BlockBox::default(), 0),
+            false => {
+                let size = BlockSize {
+                    width: 1,
+                    ..region.size
+                };
+                (BlockBox::new(region.min, size), region.size.width)
+            }
+        };
+        return Self {
+            storage,
+            rows: starts.iter(),
+            row: vec![uniform.unwrap_or_default(); width],
+            row_start: region.min,
+            // Nothing is decoded until the first row is reached.
+            x: width,
+            uniform: uniform.is_some(),
+        };
+    }
+}
+
+impl<S: BlockView> Iterator for BlockIter<'_, S> {
+    type Item = (BlockCoord, BlockId);
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.x == self.row.len() {
+            self.row_start = self.rows.next()?;
+            self.x = 0;
+            if !self.uniform {
+                let size = BlockSize {
+                    width: self.row.len(),
+                    height: 1,
+                    depth: 1,
+                };
+                self.storage.get_slice_core(
+                    self.row_start,
+                    size,
+                    BlockCoord::default(),
+                    size,
+                    &mut self.row,
+                );
+            }
+        }
+
+        let coord = BlockCoord {
+            x: self.row_start.x + self.x,
+            ..self.row_start
+        };
+        let value = self.row[self.x];
+        self.x += 1;
+        return Some((coord, value));
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.rows.len() * self.row.len() + (self.row.len() - self.x);
+        (len, Some(len))
+    }
+}
+
+impl<S: BlockView> ExactSizeIterator for BlockIter<'_, S> {}
+
+impl<S: BlockView> FusedIterator for BlockIter<'_, S> {}
+
+/// Runs of equal blocks as `(value, offset, len)` in storage order, created by
+/// [`BlockView::runs`].
+///
+/// Runs continue across rows and layers. Uniform storage yields a single run
+/// without decoding, other storage is decoded one row at a time.
+#[derive(Debug)]
+pub struct BlockRuns<'a, S> {
+    storage: &'a S,
+    row: Vec<BlockId>,
+    /// Index of the next row to decode.
+    next_row: usize,
+    /// Runs of the decoded row as `(value, x, len)`.
+    row_runs: Vec<(BlockId, usize, usize)>,
+    /// Index of the next run in `row_runs`.
+    next_run: usize,
+    pending: Option<(BlockId, usize, usize)>,
+}
+
+impl<'a, S: BlockView> BlockRuns<'a, S> {
+    pub(super) fn new(storage: &'a S) -> Self {
+        let size = storage.size();
+        let mut runs = Self {
+            storage,
+            row: vec![BlockId::default(); size.width],
+            next_row: size.height * size.depth,
+            row_runs: Vec::new(),
+            next_run: 0,
+            pending: None,
+        };
+        match storage.uniform_value() {
+            Some(value) => runs.pending = Some((value, 0, size.volume())),
+            None => runs.next_row = 0,
+        }
+        return runs;
+    }
+
+    fn row_count(&self) -> usize {
+        self.storage.height().get() * self.storage.depth().get()
+    }
+
+    fn decode_row(&mut self) {
+        let width = self.row.len();
+        let depth = self.storage.depth().get();
+        let start = BlockCoord {
+            x: 0,
+            y: self.next_row / depth,
+            z: self.next_row % depth,
+        };
+        let size = BlockSize {
+            width,
+            height: 1,
+            depth: 1,
+        };
+        self.storage
+            .get_slice_core(start, size, BlockCoord::default(), size, &mut self.row);
+        self.next_row += 1;
+
+        let row_runs = &mut self.row_runs;
+        row_runs.clear();
+        block_runs(&self.row, |value, x, len| row_runs.push((value, x, len)));
+        self.next_run = 0;
+    }
+}
+
+impl<S: BlockView> Iterator for BlockRuns<'_, S> {
+    type Item = (BlockId, usize, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            while let Some(&(value, x, len)) = self.row_runs.get(self.next_run) {
+                self.next_run += 1;
+                let offset = (self.next_row - 1) * self.row.len() + x;
+
+                match &mut self.pending {
+                    Some((pending, _, pending_len)) if *pending == value => *pending_len += len,
+                    pending => {
+                        if let Some(run) = pending.replace((value, offset, len)) {
+                            return Some(run);
+                        }
+                    }
+                }
+            }
+
+            if self.next_row == self.row_count() {
+                return self.pending.take();
+            }
+            self.decode_row();
+        }
+    }
+}
+
+impl<S: BlockView> FusedIterator for BlockRuns<'_, S> {}
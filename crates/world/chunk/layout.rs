@@ -0,0 +1,307 @@
+use pack::{
+    span::{PackAccess, PackAccessMut},
+    vec::PackVec,
+};
+
+use crate::block::{BlockCoord, BlockSize};
+
+use super::{Chunk, get_index_base};
+
+/// Order of blocks in packed storage buffers.
+///
+/// Offsets passed through [`super::BlockStorage`] are linear in every layout,
+/// the layout only decides where storage keeps each block.
+#[derive(Clone, Copy, Debug, Default, Hash, Eq, PartialEq)]
+pub enum BlockLayout {
+    /// Rows along X, then Z, then layers along Y, see [`get_index_base`].
+    #[default]
+    Linear,
+
+    /// Z-order curve, so neighbours on every axis tend to be close in memory.
+    ///
+    /// Requires power of two dimensions up to 256.
+    Morton,
+}
+
+impl BlockLayout {
+    /// Whether chunks of `W`x`H`x`D` blocks can be kept in this layout.
+    #[inline]
+    pub const fn supports<const W: usize, const H: usize, const D: usize>(self) -> bool {
+        match self {
+            BlockLayout::Linear => true,
+            BlockLayout::Morton => Morton::<W, H, D>::SUPPORTED,
+        }
+    }
+
+    /// Slot in storage of the block at linear `offset`, or `offset` if it is
+    /// past the end.
+    #[inline]
+    pub fn slot<const W: usize, const H: usize, const D: usize>(self, offset: usize) -> usize {
+        match self {
+            BlockLayout::Linear => offset,
+            BlockLayout::Morton if offset >= Chunk::<W, H, D>::VOLUME => offset,
+            BlockLayout::Morton => Morton::<W, H, D>::encode(BlockCoord {
+                x: offset % W,
+                y: offset / (W * D),
+                z: offset / W % D,
+            }),
+        }
+    }
+
+    /// Calls `f` with the storage slot of every block in the box at `offset`
+    /// of `size`, and its index in a buffer of `bounds` holding the box at
+    /// `buf_offset`, in buffer order.
+    #[inline]
+    pub(super) fn for_each_slot<const W: usize, const H: usize, const D: usize>(
+        self,
+        offset: BlockCoord,
+        size: BlockSize,
+        buf_offset: BlockCoord,
+        bounds: BlockSize,
+        mut f: impl FnMut(usize, usize),
+    ) {
+        for y in 0..size.height {
+            for z in 0..size.depth {
+                let (src_y, src_z) = (offset.y + y, offset.z + z);
+                let row = get_index_base(
+                    bounds.depth,
+                    bounds.width,
+                    buf_offset.y + y,
+                    buf_offset.z + z,
+                ) + buf_offset.x;
+                match self {
+                    BlockLayout::Linear => {
+                        let base = get_index_base(D, W, src_y, src_z) + offset.x;
+                        (0..size.width).for_each(|x| f(base + x, row + x));
+                    }
+                    BlockLayout::Morton => {
+                        let yz = Morton::<W, H, D>::SPREAD[1][src_y]
+                            | Morton::<W, H, D>::SPREAD[2][src_z];
+                        for x in 0..size.width {
+                            let slot = (Morton::<W, H, D>::SPREAD[0][offset.x + x] | yz) as usize;
+                            f(slot, row + x);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Copies `data`, a buffer of a `W`x`H`x`D` box in layout `from`, into layout `to`.
+    pub(super) fn relayout<const W: usize, const H: usize, const D: usize>(
+        from: BlockLayout,
+        to: BlockLayout,
+        data: &PackVec,
+    ) -> PackVec {
+        let mut out = PackVec::with_capacity(data.len(), data.order());
+        out.extend_with(data.len(), 0);
+        for offset in 0..data.len() {
+            let value = data.get::<u32>(from.slot::<W, H, D>(offset)).unwrap();
+            out.set(to.slot::<W, H, D>(offset), value);
+        }
+        return out;
+    }
+}
+
+/// Morton (Z-order) indexing of a `W`x`H`x`D` box with power of two sides.
+///
+/// Index bits are taken by X, Z and Y in turn, skipping axes that ran out of
+/// bits, so a `16x256x16` column is a stack of `16³` Morton cubes.
+pub struct Morton<const W: usize, const H: usize, const D: usize>;
+
+impl<const W: usize, const H: usize, const D: usize> Morton<W, H, D> {
+    /// Whether every side is a power of two up to 256.
+    pub const SUPPORTED: bool = morton_side(W) && morton_side(H) && morton_side(D);
+
+    /// Index bits of each axis, in X, Y, Z order, all zero if not [`Morton::SUPPORTED`].
+    pub const MASKS: [u32; 3] = match Self::SUPPORTED {
+        true => morton_masks([W, H, D]),
+        false => [0; 3],
+    };
+
+    /// Index bits of every coordinate, per axis.
+    const SPREAD: [[u32; 256]; 3] = [
+        spread_table(Self::MASKS[0]),
+        spread_table(Self::MASKS[1]),
+        spread_table(Self::MASKS[2]),
+    ];
+
+    /// Coordinates packed as `x | y << 8 | z << 16` of every value of each index byte.
+    const GATHER: [[u32; 256]; 3] = [
+        gather_table(Self::MASKS, 0),
+        gather_table(Self::MASKS, 8),
+        gather_table(Self::MASKS, 16),
+    ];
+
+    #[inline]
+    pub fn encode(coord: BlockCoord) -> usize {
+        #[cfg(all(target_arch = "x86_64", target_feature = "bmi2"))]
+        {
+            use std::arch::x86_64::_pdep_u32;
+            let [x, y, z] = Self::MASKS;
+            // SAFETY: bmi2 is enabled at compile time.
+            return unsafe {
+                _pdep_u32(coord.x as u32, x)
+                    | _pdep_u32(coord.y as u32, y)
+                    | _pdep_u32(coord.z as u32, z)
+            } as usize;
+        }
+
+        #[allow(unreachable_code)]
+        return (Self::SPREAD[0][coord.x] | Self::SPREAD[1][coord.y] | Self::SPREAD[2][coord.z])
+            as usize;
+    }
+
+    #[inline]
+    pub fn decode(index: usize) -> BlockCoord {
+        #[cfg(all(target_arch = "x86_64", target_feature = "bmi2"))]
+        {
+            use std::arch::x86_64::_pext_u32;
+            let [x, y, z] = Self::MASKS;
+            let index = index as u32;
+            // SAFETY: bmi2 is enabled at compile time.
+            return unsafe {
+                BlockCoord {
+                    x: _pext_u32(index, x) as usize,
+                    y: _pext_u32(index, y) as usize,
+                    z: _pext_u32(index, z) as usize,
+                }
+            };
+        }
+
+        #[allow(unreachable_code)]
+        let packed = Self::GATHER[0][index & 0xff]
+            | Self::GATHER[1][(index >> 8) & 0xff]
+            | Self::GATHER[2][(index >> 16) & 0xff];
+        return BlockCoord {
+            x: (packed & 0xff) as usize,
+            y: ((packed >> 8) & 0xff) as usize,
+            z: (packed >> 16) as usize,
+        };
+    }
+}
+
+const fn morton_side(size: usize) -> bool {
+    size.is_power_of_two() && size <= 256
+}
+
+const fn morton_masks(sizes: [usize; 3]) -> [u32; 3] {
+    let mut bits = [0; 3];
+    let mut axis = 0;
+    while axis < 3 {
+        bits[axis] = sizes[axis].trailing_zeros();
+        axis += 1;
+    }
+
+    // X, then Z, then Y, matching the significance of axes in linear order.
+    let turns = [0, 2, 1];
+    let mut masks = [0; 3];
+    let mut taken = [0; 3];
+    let mut bit = 0;
+    while bit < bits[0] + bits[1] + bits[2] {
+        let mut turn = 0;
+        while turn < 3 {
+            let axis = turns[turn];
+            if taken[axis] < bits[axis] {
+                masks[axis] |= 1 << bit;
+                taken[axis] += 1;
+                bit += 1;
+            }
+            turn += 1;
+        }
+    }
+    return masks;
+}
+
+/// Software `pdep` of every byte value into `mask`.
+const fn spread_table(mask: u32) -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut value = 0;
+    while value < 256 {
+        let (mut out, mut rest, mut from) = (0, mask, 0);
+        while rest != 0 {
+            let bit = rest & rest.wrapping_neg();
+            if value & (1 << from) != 0 {
+                out |= bit;
+            }
+            rest &= rest - 1;
+            from += 1;
+        }
+        table[value] = out;
+        value += 1;
+    }
+    return table;
+}
+
+/// Coordinates contributed by every value of the index byte at `shift`.
+const fn gather_table(masks: [u32; 3], shift: u32) -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut value = 0;
+    while value < 256 {
+        let index = (value as u32) << shift;
+        let mut packed = 0;
+        let mut axis = 0;
+        while axis < 3 {
+            // Software `pext` of the index through the axis mask.
+            let (mut coord, mut rest, mut to) = (0, masks[axis], 0);
+            while rest != 0 {
+                let bit = rest & rest.wrapping_neg();
+                if index & bit != 0 {
+                    coord |= 1 << to;
+                }
+                rest &= rest - 1;
+                to += 1;
+            }
+            packed |= coord << (axis * 8);
+            axis += 1;
+        }
+        table[value] = packed;
+        value += 1;
+    }
+    return table;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        block::BlockId,
+        chunk::{BlockStorage, StorageError},
+    };
+
+    fn check<const W: usize, const H: usize, const D: usize>() {
+        let volume = W * H * D;
+        let mut seen = vec![false; volume];
+        for y in 0..H {
+            for z in 0..D {
+                for x in 0..W {
+                    let coord = BlockCoord { x, y, z };
+                    let index = Morton::<W, H, D>::encode(coord);
+                    assert!(index < volume && !seen[index], "{coord:?}");
+                    seen[index] = true;
+                    assert_eq!(Morton::<W, H, D>::decode(index), coord);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn morton() {
+        check::<16, 16, 16>();
+        check::<16, 256, 16>();
+        check::<4, 2, 8>();
+        assert_eq!(Morton::<16, 16, 16>::MASKS, [0o1111, 0o4444, 0o2222]);
+        assert!(!BlockLayout::Morton.supports::<12, 16, 16>());
+        assert!(BlockLayout::Linear.supports::<12, 16, 16>());
+        let mut chunk = Chunk::<12, 16, 16>::empty();
+        assert_eq!(
+            chunk.set_layout(BlockLayout::Morton),
+            Err(StorageError::UnsupportedLayout(BlockLayout::Morton))
+        );
+        assert_eq!(chunk.set_at(0, BlockId(1)), Some(true));
+
+        // The first octant is contiguous.
+        let index = Morton::<16, 16, 16>::encode(BlockCoord { x: 1, y: 1, z: 1 });
+        assert_eq!(index, 7);
+    }
+}
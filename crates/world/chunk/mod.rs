@@ -1,44 +1,694 @@
+pub mod changes;
+pub mod direct;
+pub mod entity;
+pub mod iter;
+pub mod layout;
+pub mod octree;
 pub mod palette;
+pub mod patch;
+pub mod snapshot;
 
-use std::num::NonZeroUsize;
+use std::{collections::HashSet, error::Error, fmt, num::NonZeroUsize, sync::Arc};
 
-use bevy::prelude::Component;
+use bevy::prelude::{App, Changed, Component, DetectChangesMut, Last, Plugin, Query};
+use changes::BlockChanges;
 use collections::OwnedCut;
+use direct::{ChunkDirect, get_storage_bits_for_id};
+use entity::EntityMap;
+use iter::{BlockIter, BlockRuns};
+use layout::BlockLayout;
 use num_traits::PrimInt;
-use palette::ChunkPalette;
+use octree::ChunkOctree;
+use pack::part::PartSize;
+use palette::{ChunkPalette, get_storage_bits_for_palette};
 
-use crate::block::{BlockCoord, BlockId, BlockSize};
+use crate::{
+    biome::ChunkBiomes,
+    block::{BlockBox, BlockCoord, BlockId, BlockSize},
+    pos::ChunkPos,
+    storage::{Decode, DecodeError, Encode, Reader},
+};
 
 #[derive(Component, Debug, Default)]
 pub struct ChunkLocation {
-    pub x: u32,
-    pub y: u32,
-    pub z: u32,
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
 }
 
+impl From<ChunkPos> for ChunkLocation {
+    fn from(pos: ChunkPos) -> Self {
+        Self {
+            x: pos.x,
+            y: pos.y,
+            z: pos.z,
+        }
+    }
+}
+
+impl From<&ChunkLocation> for ChunkPos {
+    fn from(location: &ChunkLocation) -> Self {
+        ChunkPos::new(location.x, location.y, location.z)
+    }
+}
+
+/// Blocks of a `W`x`H`x`D` chunk, 16³ unless stated otherwise.
+///
+/// Dimensions are const parameters so indexing folds to constants. Outside of type
+/// position the defaults do not apply, use a preset like [`Chunk16`] there instead.
 #[derive(Component, Debug)]
 #[require(ChunkLocation)]
-pub struct Chunk {
-    storage: ChunkStorage,
+pub struct Chunk<const W: usize = 16, const H: usize = 16, const D: usize = 16> {
+    storage: ChunkStorage<W, H, D>,
+    biomes: ChunkBiomes<W, H, D>,
+    /// Block data by offset, see [`entity::BlockEntity`], once there is any. Shared
+    /// with snapshots until either side writes.
+    entities: Option<Arc<EntityMap>>,
+    /// Blocks changed since the last drain, if tracked.
+    changes: Option<Box<BlockChanges>>,
+    /// Layout of new storage buffers.
+    layout: BlockLayout,
+    /// Bit width of new direct storage, see [`Chunk::set_id_bits`].
+    id_bits: PartSize,
 }
 
-impl Chunk {
-    pub const WIDTH: NonZeroUsize = NonZeroUsize::new(16).unwrap();
-    pub const HEIGHT: NonZeroUsize = NonZeroUsize::new(16).unwrap();
-    pub const DEPTH: NonZeroUsize = NonZeroUsize::new(16).unwrap();
+/// The default 16³ chunk.
+pub type Chunk16 = Chunk<16, 16, 16>;
+
+/// 32³ chunks, for level of detail and far terrain.
+pub type Chunk32 = Chunk<32, 32, 32>;
+
+/// 16x256x16 columns, for flat worlds.
+pub type ChunkColumn = Chunk<16, 256, 16>;
+
+impl<const W: usize, const H: usize, const D: usize> Chunk<W, H, D> {
+    pub const WIDTH: NonZeroUsize = NonZeroUsize::new(W).unwrap();
+    pub const HEIGHT: NonZeroUsize = NonZeroUsize::new(H).unwrap();
+    pub const DEPTH: NonZeroUsize = NonZeroUsize::new(D).unwrap();
+
+    pub const VOLUME: usize = Self::WIDTH.get() * Self::HEIGHT.get() * Self::DEPTH.get();
+
+    /// A chunk without block data, reading as [`BlockId::default`].
+    pub const fn empty() -> Self {
+        Self {
+            storage: ChunkStorage::Empty,
+            biomes: ChunkBiomes::new(),
+            entities: None,
+            changes: None,
+            layout: BlockLayout::Linear,
+            id_bits: PartSize::new(1).unwrap(),
+        }
+    }
+
+    /// A chunk with every block set to `value`.
+    pub const fn filled(value: BlockId) -> Self {
+        Self {
+            storage: ChunkStorage::Value(value),
+            biomes: ChunkBiomes::new(),
+            entities: None,
+            changes: None,
+            layout: BlockLayout::Linear,
+            id_bits: PartSize::new(1).unwrap(),
+        }
+    }
+
+    /// A chunk from [`Chunk::VOLUME`] blocks in storage order, see [`get_index_base`].
+    pub fn from_blocks(blocks: &[BlockId]) -> Result<Self, StorageError> {
+        if blocks.len() != Self::VOLUME {
+            return Err(StorageError::LengthMismatch {
+                expected: Self::VOLUME,
+                len: blocks.len(),
+            });
+        }
+
+        let mut chunk = Self::empty();
+        let size = chunk.size();
+        chunk.set_slice(chunk.bounds(), BlockCoord::default(), size, blocks)?;
+        chunk.optimize();
+        return Ok(chunk);
+    }
+
+    /// Every block in storage order, see [`get_index_base`].
+    pub fn to_blocks(&self) -> Vec<BlockId> {
+        let mut blocks = vec![BlockId::default(); Self::VOLUME];
+        let size = self.size();
+        self.get_slice(self.bounds(), BlockCoord::default(), size, &mut blocks)
+            .unwrap();
+        return blocks;
+    }
+
+    #[inline]
+    pub fn storage(&self) -> &ChunkStorage<W, H, D> {
+        &self.storage
+    }
+
+    #[inline]
+    pub fn layout(&self) -> BlockLayout {
+        self.layout
+    }
+
+    /// Keeps blocks in `layout` from now on, moving those in storage.
+    ///
+    /// [`BlockLayout::Morton`] trades contiguous rows for locality on every axis,
+    /// see the `layout` benchmarks.
+    ///
+    /// Fails if the chunk size does not support `layout`, see [`BlockLayout::supports`].
+    pub fn set_layout(&mut self, layout: BlockLayout) -> Result<(), StorageError> {
+        if !layout.supports::<W, H, D>() {
+            return Err(StorageError::UnsupportedLayout(layout));
+        }
+        self.layout = layout;
+        match &mut self.storage {
+            ChunkStorage::Empty | ChunkStorage::Value(_) | ChunkStorage::Octree(_) => {}
+            ChunkStorage::Palette(palette) => Arc::make_mut(palette).set_layout(layout),
+            ChunkStorage::Direct(direct) => Arc::make_mut(direct).set_layout(layout),
+        }
+        return Ok(());
+    }
+
+    #[inline]
+    pub fn id_bits(&self) -> PartSize {
+        self.id_bits
+    }
+
+    /// Creates [`ChunkStorage::Direct`] at least `id_bits` wide, so writes don't widen it.
+    ///
+    /// Pass [`crate::block::registry::BlockRegistry::id_bits`] once the registry is frozen.
+    #[inline]
+    pub fn set_id_bits(&mut self, id_bits: PartSize) {
+        self.id_bits = id_bits;
+    }
+
+    #[inline]
+    pub fn biomes(&self) -> &ChunkBiomes<W, H, D> {
+        &self.biomes
+    }
+
+    #[inline]
+    pub fn biomes_mut(&mut self) -> &mut ChunkBiomes<W, H, D> {
+        &mut self.biomes
+    }
+
+    /// Bytes used by the chunk, including heap allocations.
+    pub fn memory_footprint(&self) -> usize {
+        return size_of::<Self>() + self.storage.heap_size() + self.biomes.heap_size();
+    }
+
+    /// Starts recording changed blocks, see [`Chunk::drain_changes`].
+    pub fn track_changes(&mut self) {
+        if self.changes.is_none() {
+            self.changes = Some(Box::new(BlockChanges::new(self.size())));
+        }
+    }
+
+    /// Stops recording changed blocks, dropping changes not drained yet.
+    pub fn untrack_changes(&mut self) {
+        self.changes = None;
+    }
+
+    /// Changes recorded since the last drain, `None` if changes are not tracked.
+    #[inline]
+    pub fn changes(&self) -> Option<&BlockChanges> {
+        self.changes.as_deref()
+    }
+
+    /// Takes the changes recorded since the last drain and starts over.
+    ///
+    /// Returns `None` if changes are not tracked.
+    pub fn drain_changes(&mut self) -> Option<BlockChanges> {
+        let size = self.size();
+        let changes = self.changes.as_deref_mut()?;
+        return Some(std::mem::replace(changes, BlockChanges::new(size)));
+    }
+
+    /// Records blocks of the box at `offset` that differ from `value_at`, called before a write.
+    ///
+    /// `value_at` takes coordinates relative to `offset`.
+    fn record_changes(
+        &mut self,
+        offset: BlockCoord,
+        size: BlockSize,
+        value_at: impl Fn(BlockCoord) -> BlockId,
+    ) {
+        if self.changes.is_none() {
+            return;
+        }
+
+        let mut current = vec![BlockId::default(); size.volume()];
+        self.get_slice_core(offset, size, BlockCoord::default(), size, &mut current);
+
+        let changes = self.changes.as_deref_mut().unwrap();
+        for (local, current) in BlockBox::from_size(size).iter().zip(current) {
+            if value_at(local) != current {
+                changes.mark(BlockCoord {
+                    x: offset.x + local.x,
+                    y: offset.y + local.y,
+                    z: offset.z + local.z,
+                });
+            }
+        }
+    }
+
+    /// Switches to [`ChunkStorage::Direct`] once the palette has more than
+    /// [`DIRECT_THRESHOLD`] live entries, and to a palette once an octree
+    /// takes more bytes than the palette would, see [`Chunk::outgrew_palette`].
+    ///
+    /// Octrees are only checked when they grew, see [`ChunkOctree::grew_past_check`].
+    fn maybe_expand(&mut self) {
+        if let ChunkStorage::Octree(octree) = &mut self.storage {
+            // Writes leave the octree unshared.
+            let grew = Arc::get_mut(octree).is_some_and(|octree| octree.grew_past_check());
+            if !grew || !Self::outgrew_palette(octree) {
+                return;
+            }
+            std::hint::cold_path();
+            let palette = octree.to_palette(self.layout);
+            self.storage = ChunkStorage::Palette(Arc::new(palette));
+        }
+
+        let ChunkStorage::Palette(palette) = &mut self.storage else {
+            return;
+        };
+        if palette.entries().len() <= DIRECT_THRESHOLD {
+            return;
+        }
+        // A palette that kept this many live entries at its last compaction is not
+        // compacted again on every new entry.
+        if palette.live_hint() < DIRECT_THRESHOLD {
+            Arc::make_mut(palette).compact();
+            if palette.entries().len() <= DIRECT_THRESHOLD {
+                return;
+            }
+        }
+
+        std::hint::cold_path();
+        let max = palette.entries().iter().map(|id| id.0).max().unwrap_or(0);
+        let layout = palette.layout();
+        let blocks = self.to_blocks();
+        let value_bits = self.id_bits.max(get_storage_bits_for_id(BlockId(max)));
+        let mut direct =
+            ChunkDirect::<W, H, D>::with_layout(BlockId::default(), value_bits, layout);
+        let size = self.size();
+        direct.set_slice_core(
+            BlockCoord::default(),
+            size,
+            BlockCoord::default(),
+            size,
+            &blocks,
+        );
+        self.storage = ChunkStorage::Direct(Arc::new(direct));
+    }
+
+    /// Demotes a palette holding a single value to [`ChunkStorage::Value`],
+    /// and compacts any other palette.
+    ///
+    /// Palettes switch to [`ChunkStorage::Octree`] when the trees take at most
+    /// `1 / OCTREE_RATIO` of their size, and octrees switch back once they are
+    /// larger than a palette of their blocks. Direct storage is demoted once it
+    /// holds at most [`PALETTE_THRESHOLD`] distinct values.
+    /// Palettes shared with a [`snapshot::ChunkSnapshot`] are not compacted or converted.
+    ///
+    /// Returns whether the storage changed.
+    pub fn optimize(&mut self) -> bool {
+        match &mut self.storage {
+            ChunkStorage::Palette(palette) => {
+                let Some(value) = palette.uniform_value() else {
+                    let Some(palette) = Arc::get_mut(palette) else {
+                        return false;
+                    };
+                    let compacted = palette.compact();
+                    return self.shrink_to_octree() || compacted;
+                };
+                self.storage = ChunkStorage::Value(value);
+                return true;
+            }
+            ChunkStorage::Direct(_) => self.demote_direct(),
+            ChunkStorage::Octree(octree) => {
+                let Some(value) = octree.uniform_value() else {
+                    return self.expand_octree();
+                };
+                self.storage = ChunkStorage::Value(value);
+                return true;
+            }
+            ChunkStorage::Empty | ChunkStorage::Value(_) => false,
+        }
+    }
+
+    fn shrink_to_octree(&mut self) -> bool {
+        let ChunkStorage::Palette(palette) = &self.storage else {
+            return false;
+        };
+        let max_heap_size = palette.heap_size() / OCTREE_RATIO;
+        let Some(octree) = ChunkOctree::from_palette(palette, max_heap_size) else {
+            return false;
+        };
+        self.storage = ChunkStorage::Octree(Arc::new(octree));
+        return true;
+    }
+
+    /// Whether the branches in use by `octree` take more bytes than a palette
+    /// of its blocks would.
+    fn outgrew_palette(octree: &ChunkOctree<W, H, D>) -> bool {
+        // No palette takes less than a bit per block.
+        let size = octree.live_size();
+        if size <= Self::VOLUME / 8 {
+            return false;
+        }
+        let mut distinct = HashSet::new();
+        octree.for_each_leaf(|_, value| {
+            distinct.insert(value);
+        });
+        let value_bits = get_storage_bits_for_palette(distinct.len()).get();
+        return size > Self::VOLUME * value_bits / 8;
+    }
+
+    fn expand_octree(&mut self) -> bool {
+        let ChunkStorage::Octree(octree) = &self.storage else {
+            return false;
+        };
+        if !Self::outgrew_palette(octree) {
+            return false;
+        }
+
+        let palette = octree.to_palette(self.layout);
+        self.storage = ChunkStorage::Palette(Arc::new(palette));
+        return true;
+    }
+
+    fn demote_direct(&mut self) -> bool {
+        let blocks = self.to_blocks();
+        let mut distinct = HashSet::new();
+        for block in &blocks {
+            distinct.insert(*block);
+            if distinct.len() > PALETTE_THRESHOLD {
+                return false;
+            }
+        }
+
+        if distinct.len() == 1 {
+            self.storage = ChunkStorage::Value(blocks[0]);
+            return true;
+        }
+
+        let mut palette = ChunkPalette::<W, H, D>::with_layout(blocks[0], self.layout);
+        let size = self.size();
+        palette.set_slice_core(
+            BlockCoord::default(),
+            size,
+            BlockCoord::default(),
+            size,
+            &blocks,
+        );
+        self.storage = ChunkStorage::Palette(Arc::new(palette));
+        return true;
+    }
+}
+
+/// Palettes with more live entries switch to [`ChunkStorage::Direct`].
+const DIRECT_THRESHOLD: usize = 256;
+
+/// Direct storage with at most this many distinct values switches back to a palette.
+///
+/// Kept below [`DIRECT_THRESHOLD`] so chunks near it don't switch back and forth.
+const PALETTE_THRESHOLD: usize = DIRECT_THRESHOLD / 2;
+
+/// Palettes switch to [`ChunkStorage::Octree`] when the trees take at most this
+/// fraction of their size.
+///
+/// Octrees switch back only once they outgrow the palette, so chunks near the
+/// threshold don't switch back and forth.
+const OCTREE_RATIO: usize = 2;
+
+/// Runs [`optimize_chunks`] for `Chunk<W, H, D>` at the end of every frame.
+#[derive(Default)]
+pub struct ChunkStoragePlugin<const W: usize = 16, const H: usize = 16, const D: usize = 16>;
+
+impl<const W: usize, const H: usize, const D: usize> Plugin for ChunkStoragePlugin<W, H, D> {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Last, optimize_chunks::<W, H, D>);
+    }
+}
+
+/// Optimizes chunks modified since the last run.
+///
+/// Storage changes are not content changes, so this does not mark chunks as changed.
+pub fn optimize_chunks<const W: usize, const H: usize, const D: usize>(
+    mut chunks: Query<&mut Chunk<W, H, D>, Changed<Chunk<W, H, D>>>,
+) {
+    for mut chunk in &mut chunks {
+        chunk.bypass_change_detection().optimize();
+    }
+}
+
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq)]
+pub enum ChunkStorageKind {
+    Empty,
+    Value,
+    Palette,
+    Direct,
+    Octree,
 }
 
-#[derive(Debug)]
-pub enum ChunkStorage {
+/// Block storage of a chunk.
+///
+/// Palette and direct buffers may be shared with snapshots, see [`Chunk::snapshot`].
+/// Writes copy shared buffers first.
+#[derive(Clone, Debug)]
+pub enum ChunkStorage<const W: usize = 16, const H: usize = 16, const D: usize = 16> {
     Empty,
 
     /// A single value represents the entire storage.
     Value(BlockId),
 
-    Palette(ChunkPalette),
+    Palette(Arc<ChunkPalette<W, H, D>>),
+
+    /// Raw block ids, for chunks with too many distinct blocks for a palette.
+    Direct(Arc<ChunkDirect<W, H, D>>),
+
+    /// Uniform cubes, for chunks that are mostly one block.
+    Octree(Arc<ChunkOctree<W, H, D>>),
+}
+
+impl<const W: usize, const H: usize, const D: usize> ChunkStorage<W, H, D> {
+    #[inline]
+    pub fn kind(&self) -> ChunkStorageKind {
+        match self {
+            ChunkStorage::Empty => ChunkStorageKind::Empty,
+            ChunkStorage::Value(_) => ChunkStorageKind::Value,
+            ChunkStorage::Palette(_) => ChunkStorageKind::Palette,
+            ChunkStorage::Direct(_) => ChunkStorageKind::Direct,
+            ChunkStorage::Octree(_) => ChunkStorageKind::Octree,
+        }
+    }
+
+    /// Bytes allocated on the heap for the block data, including shared buffers.
+    pub fn heap_size(&self) -> usize {
+        match self {
+            ChunkStorage::Empty | ChunkStorage::Value(_) => 0,
+            ChunkStorage::Palette(palette) => size_of_val(&**palette) + palette.heap_size(),
+            ChunkStorage::Direct(direct) => size_of_val(&**direct) + direct.heap_size(),
+            ChunkStorage::Octree(octree) => size_of_val(&**octree) + octree.heap_size(),
+        }
+    }
+
+    /// Layout of the storage buffers, [`BlockLayout::Linear`] for storage without one.
+    pub fn layout(&self) -> BlockLayout {
+        match self {
+            ChunkStorage::Empty | ChunkStorage::Value(_) | ChunkStorage::Octree(_) => {
+                BlockLayout::Linear
+            }
+            ChunkStorage::Palette(palette) => palette.layout(),
+            ChunkStorage::Direct(direct) => direct.layout(),
+        }
+    }
+
+    /// Storage for writes, switching to [`ChunkStorage::Palette`] in `layout`
+    /// seeded with the current value, and copying shared buffers.
+    fn promote(&mut self, layout: BlockLayout) -> &mut dyn BlockStorage {
+        if let ChunkStorage::Empty | ChunkStorage::Value(_) = self {
+            let value = self.uniform_value().unwrap();
+            *self = ChunkStorage::Palette(Arc::new(ChunkPalette::with_layout(value, layout)));
+        }
+        match self {
+            ChunkStorage::Palette(palette) => Arc::<ChunkPalette<W, H, D>>::make_mut(palette),
+            ChunkStorage::Direct(direct) => Arc::<ChunkDirect<W, H, D>>::make_mut(direct),
+            ChunkStorage::Octree(octree) => Arc::<ChunkOctree<W, H, D>>::make_mut(octree),
+            ChunkStorage::Empty | ChunkStorage::Value(_) => unreachable!(),
+        }
+    }
+}
+
+impl<const W: usize, const H: usize, const D: usize> BlockView for ChunkStorage<W, H, D> {
+    #[inline]
+    fn width(&self) -> NonZeroUsize {
+        Chunk::<W, H, D>::WIDTH
+    }
+
+    #[inline]
+    fn height(&self) -> NonZeroUsize {
+        Chunk::<W, H, D>::HEIGHT
+    }
+
+    #[inline]
+    fn depth(&self) -> NonZeroUsize {
+        Chunk::<W, H, D>::DEPTH
+    }
+
+    fn get_at(&self, offset: usize) -> Option<BlockId> {
+        match self {
+            ChunkStorage::Empty | ChunkStorage::Value(_) => {
+                if offset >= self.size().volume() {
+                    return None;
+                }
+                self.uniform_value()
+            }
+            ChunkStorage::Palette(palette) => palette.get_at(offset),
+            ChunkStorage::Direct(direct) => direct.get_at(offset),
+            ChunkStorage::Octree(octree) => octree.get_at(offset),
+        }
+    }
+
+    fn uniform_value(&self) -> Option<BlockId> {
+        match self {
+            ChunkStorage::Empty => Some(BlockId::default()),
+            ChunkStorage::Value(value) => Some(*value),
+            ChunkStorage::Palette(_) | ChunkStorage::Direct(_) => None,
+            ChunkStorage::Octree(octree) => octree.uniform_value(),
+        }
+    }
+
+    fn get_slice_core(
+        &self,
+        offset: BlockCoord,
+        size: BlockSize,
+        dst_offset: BlockCoord,
+        dst_bounds: BlockSize,
+        dst: &mut [BlockId],
+    ) {
+        match self {
+            ChunkStorage::Empty => fill(dst_offset, size, BlockId::default(), dst_bounds, dst),
+            ChunkStorage::Value(value) => fill(dst_offset, size, *value, dst_bounds, dst),
+            ChunkStorage::Palette(palette) => {
+                palette.get_slice_core(offset, size, dst_offset, dst_bounds, dst)
+            }
+            ChunkStorage::Direct(direct) => {
+                direct.get_slice_core(offset, size, dst_offset, dst_bounds, dst)
+            }
+            ChunkStorage::Octree(octree) => {
+                octree.get_slice_core(offset, size, dst_offset, dst_bounds, dst)
+            }
+        }
+    }
+}
+
+impl<const W: usize, const H: usize, const D: usize> BlockStorage for ChunkStorage<W, H, D> {
+    fn set_at(&mut self, offset: usize, value: BlockId) -> Option<bool> {
+        // Also keeps shared buffers shared.
+        if self.get_at(offset)? == value {
+            return Some(false);
+        }
+        return self.promote(self.layout()).set_at(offset, value);
+    }
+
+    fn set_slice_core(
+        &mut self,
+        offset: BlockCoord,
+        size: BlockSize,
+        src_offset: BlockCoord,
+        src_bounds: BlockSize,
+        src: &[BlockId],
+    ) {
+        self.promote(self.layout())
+            .set_slice_core(offset, size, src_offset, src_bounds, src);
+    }
+
+    fn fill_core(&mut self, offset: BlockCoord, size: BlockSize, value: BlockId) {
+        if size == self.size() {
+            *self = ChunkStorage::Value(value);
+        } else if self.uniform_value() != Some(value) {
+            self.promote(self.layout()).fill_core(offset, size, value);
+        }
+    }
+}
+
+impl<const W: usize, const H: usize, const D: usize> Default for Chunk<W, H, D> {
+    fn default() -> Self {
+        Self::empty()
+    }
+}
+
+const TAG_EMPTY: u8 = 0;
+const TAG_VALUE: u8 = 1;
+const TAG_RUNS: u8 = 2;
+
+/// Writes the blocks as a tag followed by a single id or by runs of equal
+/// blocks in storage order, then the biomes and the block entities.
+///
+/// The format does not depend on the storage kind, and tracked changes are not
+/// included. Entities decode as [`entity::UndecodedData`], see
+/// [`entity::BlockDataTypes::decode_entities`].
+///
+/// [`ChunkSnapshot`](snapshot::ChunkSnapshot) writes the same format.
+impl<const W: usize, const H: usize, const D: usize> Encode for Chunk<W, H, D> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        encode_storage(&self.storage, out);
+        self.biomes.encode(out);
+        entity::encode_entities(self.entity_map(), out);
+    }
 }
 
-pub trait BlockStorage {
+/// Writes the blocks of a chunk, see [`Chunk`]'s [`Encode`] impl.
+fn encode_storage<const W: usize, const H: usize, const D: usize>(
+    storage: &ChunkStorage<W, H, D>,
+    out: &mut Vec<u8>,
+) {
+    match storage {
+        ChunkStorage::Empty => out.push(TAG_EMPTY),
+        ChunkStorage::Value(value) => {
+            out.push(TAG_VALUE);
+            out.extend_from_slice(&value.0.to_le_bytes());
+        }
+        ChunkStorage::Palette(_) | ChunkStorage::Direct(_) | ChunkStorage::Octree(_) => {
+            let runs: Vec<_> = storage.runs().collect();
+            out.push(TAG_RUNS);
+            out.extend_from_slice(&(runs.len() as u32).to_le_bytes());
+            for (value, _, len) in runs {
+                out.extend_from_slice(&value.0.to_le_bytes());
+                out.extend_from_slice(&(len as u32).to_le_bytes());
+            }
+        }
+    }
+}
+
+impl<const W: usize, const H: usize, const D: usize> Decode for Chunk<W, H, D> {
+    fn decode(input: &mut Reader<'_>) -> Result<Self, DecodeError> {
+        let mut chunk = match input.read_u8()? {
+            TAG_EMPTY => Self::empty(),
+            TAG_VALUE => Self::filled(BlockId(input.read_u32()?)),
+            TAG_RUNS => {
+                let count = input.read_u32()?;
+                let mut blocks = Vec::with_capacity(Self::VOLUME);
+                for _ in 0..count {
+                    let value = BlockId(input.read_u32()?);
+                    let len = input.read_u32()? as usize;
+                    if len > Self::VOLUME - blocks.len() {
+                        return Err(DecodeError::InvalidValue("chunk block runs"));
+                    }
+                    blocks.resize(blocks.len() + len, value);
+                }
+                Self::from_blocks(&blocks)
+                    .map_err(|_| DecodeError::InvalidValue("chunk block runs"))?
+            }
+            tag => return Err(DecodeError::InvalidTag("chunk storage", tag)),
+        };
+        chunk.biomes = ChunkBiomes::decode(input)?;
+        chunk.decode_entities(input)?;
+        return Ok(chunk);
+    }
+}
+
+/// Read access to blocks, see [`BlockStorage`] for writes.
+pub trait BlockView {
     fn width(&self) -> NonZeroUsize;
 
     fn height(&self) -> NonZeroUsize;
@@ -61,9 +711,88 @@ pub trait BlockStorage {
         return self.get_offset(offset.x, offset.y, offset.z);
     }
 
-    fn get_at(&self, offset: usize) -> Option<&BlockId>;
+    /// Inverse of [`BlockView::get_coord_offset`].
+    fn get_coord(&self, offset: usize) -> BlockCoord {
+        let (width, depth) = (self.width().get(), self.depth().get());
+        return BlockCoord {
+            x: offset % width,
+            y: offset / (width * depth),
+            z: offset / width % depth,
+        };
+    }
+
+    fn bounds(&self) -> BlockBox {
+        return BlockBox::from_size(self.size());
+    }
+
+    fn get_at(&self, offset: usize) -> Option<BlockId>;
+
+    /// The value of every block, if known without decoding them.
+    fn uniform_value(&self) -> Option<BlockId> {
+        return None;
+    }
+
+    /// Every block with its coordinate, in storage order.
+    fn iter_blocks(&self) -> BlockIter<'_, Self>
+    where
+        Self: Sized,
+    {
+        return BlockIter::new(self, self.bounds());
+    }
+
+    /// Blocks in `region` with their coordinates, in storage order.
+    fn iter_box(&self, region: BlockBox) -> Result<BlockIter<'_, Self>, StorageError>
+    where
+        Self: Sized,
+    {
+        check_box(region, self.size())?;
+        return Ok(BlockIter::new(self, region));
+    }
+
+    /// Blocks other than [`BlockId::default`] with their coordinates, in storage order.
+    ///
+    /// Runs of default blocks are skipped without visiting their blocks.
+    fn iter_non_default(&self) -> impl Iterator<Item = (BlockCoord, BlockId)> + '_
+    where
+        Self: Sized,
+    {
+        self.runs()
+            .filter(|(value, _, _)| *value != BlockId::default())
+            .flat_map(move |(value, offset, len)| {
+                (offset..offset + len).map(move |offset| (self.get_coord(offset), value))
+            })
+    }
+
+    /// Runs of equal blocks as `(value, offset, len)` in storage order.
+    fn runs(&self) -> BlockRuns<'_, Self>
+    where
+        Self: Sized,
+    {
+        return BlockRuns::new(self);
+    }
 
+    /// Copies `region` into `dst`, a buffer of `dst_bounds` with the copy placed at `dst_offset`.
     fn get_slice(
+        &self,
+        region: BlockBox,
+        dst_offset: BlockCoord,
+        dst_bounds: BlockSize,
+        dst: &mut [BlockId],
+    ) -> Result<(), StorageError> {
+        check_box(region, self.size())?;
+        check_buffer(
+            BlockBox::new(dst_offset, region.size),
+            dst_bounds,
+            dst.len(),
+        )?;
+        if !region.is_empty() {
+            self.get_slice_core(region.min, region.size, dst_offset, dst_bounds, dst);
+        }
+        return Ok(());
+    }
+
+    /// Like [`BlockView::get_slice`], with inputs already validated.
+    fn get_slice_core(
         &self,
         offset: BlockCoord,
         size: BlockSize,
@@ -71,10 +800,37 @@ pub trait BlockStorage {
         dst_bounds: BlockSize,
         dst: &mut [BlockId],
     );
+}
 
+/// Write access to blocks.
+pub trait BlockStorage: BlockView {
     fn set_at(&mut self, offset: usize, value: BlockId) -> Option<bool>;
 
+    /// Copies into `region` from `src`, a buffer of `src_bounds` with the copy placed at `src_offset`.
+    ///
+    /// Chunks keep the entities of blocks whose type is unchanged, see
+    /// [`Chunk::copy_from`] to copy entities too.
     fn set_slice(
+        &mut self,
+        region: BlockBox,
+        src_offset: BlockCoord,
+        src_bounds: BlockSize,
+        src: &[BlockId],
+    ) -> Result<(), StorageError> {
+        check_box(region, self.size())?;
+        check_buffer(
+            BlockBox::new(src_offset, region.size),
+            src_bounds,
+            src.len(),
+        )?;
+        if !region.is_empty() {
+            self.set_slice_core(region.min, region.size, src_offset, src_bounds, src);
+        }
+        return Ok(());
+    }
+
+    /// Like [`BlockStorage::set_slice`], with inputs already validated.
+    fn set_slice_core(
         &mut self,
         offset: BlockCoord,
         size: BlockSize,
@@ -83,31 +839,107 @@ pub trait BlockStorage {
         src: &[BlockId],
     );
 
-    fn fill(&mut self, offset: BlockCoord, size: BlockSize, value: BlockId);
+    fn fill(&mut self, region: BlockBox, value: BlockId) -> Result<(), StorageError> {
+        check_box(region, self.size())?;
+        if !region.is_empty() {
+            self.fill_core(region.min, region.size, value);
+        }
+        return Ok(());
+    }
+
+    /// Like [`BlockStorage::fill`], with inputs already validated.
+    fn fill_core(&mut self, offset: BlockCoord, size: BlockSize, value: BlockId);
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum StorageError {
+    /// The box reaches outside storage or a buffer of `bounds`.
+    OutOfBounds { region: BlockBox, bounds: BlockSize },
+
+    /// The buffer holds fewer than the `bounds.volume()` blocks it claims.
+    BufferTooSmall { bounds: BlockSize, len: usize },
+
+    /// A buffer meant to cover the whole storage has the wrong length.
+    LengthMismatch { expected: usize, len: usize },
+
+    /// The storage size does not support the layout, see [`BlockLayout::supports`].
+    UnsupportedLayout(BlockLayout),
 }
 
-impl BlockStorage for Chunk {
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageError::OutOfBounds { region, bounds } => {
+                write!(f, "{region:?} is out of bounds of {bounds:?}")
+            }
+            StorageError::BufferTooSmall { bounds, len } => {
+                write!(f, "buffer of {len} blocks is too small for {bounds:?}")
+            }
+            StorageError::LengthMismatch { expected, len } => {
+                write!(f, "expected {expected} blocks, got {len}")
+            }
+            StorageError::UnsupportedLayout(layout) => {
+                write!(f, "{layout:?} layout is not supported for this size")
+            }
+        }
+    }
+}
+
+impl Error for StorageError {}
+
+pub(crate) fn check_box(region: BlockBox, bounds: BlockSize) -> Result<(), StorageError> {
+    // Empty boxes may lie anywhere, but their corner must still be addressable.
+    let addressable = region.checked_max().is_some();
+    if addressable && (region.is_empty() || region.fits(bounds)) {
+        return Ok(());
+    }
+    return Err(StorageError::OutOfBounds { region, bounds });
+}
+
+pub(crate) fn check_buffer(
+    region: BlockBox,
+    bounds: BlockSize,
+    len: usize,
+) -> Result<(), StorageError> {
+    check_box(region, bounds)?;
+    let volume = bounds
+        .width
+        .checked_mul(bounds.height)
+        .and_then(|v| v.checked_mul(bounds.depth));
+    if !region.is_empty() && volume.is_none_or(|volume| len < volume) {
+        return Err(StorageError::BufferTooSmall { bounds, len });
+    }
+    return Ok(());
+}
+
+impl<const W: usize, const H: usize, const D: usize> BlockView for Chunk<W, H, D> {
+    #[inline]
     fn width(&self) -> NonZeroUsize {
         Self::WIDTH
     }
 
+    #[inline]
     fn height(&self) -> NonZeroUsize {
         Self::HEIGHT
     }
 
+    #[inline]
     fn depth(&self) -> NonZeroUsize {
         Self::DEPTH
     }
 
-    fn get_at(&self, offset: usize) -> Option<&BlockId> {
-        match &self.storage {
-            ChunkStorage::Empty => None,
-            ChunkStorage::Value(value) => Some(value),
-            ChunkStorage::Palette(palette) => palette.get_at(offset),
-        }
+    #[inline]
+    fn get_at(&self, offset: usize) -> Option<BlockId> {
+        self.storage.get_at(offset)
     }
 
-    fn get_slice(
+    #[inline]
+    fn uniform_value(&self) -> Option<BlockId> {
+        self.storage.uniform_value()
+    }
+
+    #[inline]
+    fn get_slice_core(
         &self,
         offset: BlockCoord,
         size: BlockSize,
@@ -115,23 +947,30 @@ impl BlockStorage for Chunk {
         dst_bounds: BlockSize,
         dst: &mut [BlockId],
     ) {
-        match &self.storage {
-            ChunkStorage::Empty => fill(dst_offset, size, BlockId::default(), dst_bounds, dst),
-            ChunkStorage::Value(value) => fill(dst_offset, size, *value, dst_bounds, dst),
-            ChunkStorage::Palette(palette) => {
-                palette.get_slice(offset, size, dst_offset, dst_bounds, dst)
-            }
-        }
+        self.storage
+            .get_slice_core(offset, size, dst_offset, dst_bounds, dst);
     }
+}
 
+impl<const W: usize, const H: usize, const D: usize> BlockStorage for Chunk<W, H, D> {
     fn set_at(&mut self, offset: usize, value: BlockId) -> Option<bool> {
-        match &mut self.storage {
-            ChunkStorage::Palette(palette) => palette.set_at(offset, value),
-            _ => todo!(),
+        // Also keeps shared buffers shared.
+        if self.get_at(offset)? == value {
+            return Some(false);
         }
+        let changed = self.storage.promote(self.layout).set_at(offset, value);
+        self.maybe_expand();
+        if changed == Some(true) {
+            let coord = self.get_coord(offset);
+            if let Some(changes) = &mut self.changes {
+                changes.mark(coord);
+            }
+            self.prune_entities(BlockBox::new(coord, BlockSize::splat(1)));
+        }
+        return changed;
     }
 
-    fn set_slice(
+    fn set_slice_core(
         &mut self,
         offset: BlockCoord,
         size: BlockSize,
@@ -139,19 +978,29 @@ impl BlockStorage for Chunk {
         src_bounds: BlockSize,
         src: &[BlockId],
     ) {
-        match &mut self.storage {
-            ChunkStorage::Palette(palette) => {
-                palette.set_slice(offset, size, src_offset, src_bounds, src)
-            }
-            _ => todo!(),
-        }
+        self.record_changes(offset, size, |local| {
+            let y = src_offset.y + local.y;
+            let z = src_offset.z + local.z;
+            src[get_index_base(src_bounds.depth, src_bounds.width, y, z) + src_offset.x + local.x]
+        });
+        self.storage
+            .promote(self.layout)
+            .set_slice_core(offset, size, src_offset, src_bounds, src);
+        self.maybe_expand();
+        self.prune_entities(BlockBox::new(offset, size));
     }
 
-    fn fill(&mut self, offset: BlockCoord, size: BlockSize, value: BlockId) {
-        match &mut self.storage {
-            ChunkStorage::Palette(palette) => palette.fill(offset, size, value),
-            _ => todo!(),
+    fn fill_core(&mut self, offset: BlockCoord, size: BlockSize, value: BlockId) {
+        self.record_changes(offset, size, |_| value);
+        if size == self.size() {
+            self.storage = ChunkStorage::Value(value);
+        } else if self.uniform_value() != Some(value) {
+            self.storage
+                .promote(self.layout)
+                .fill_core(offset, size, value);
+            self.maybe_expand();
         }
+        self.prune_entities(BlockBox::new(offset, size));
     }
 }
 
@@ -221,16 +1070,507 @@ where
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+
+    fn is_value(chunk: &Chunk, value: BlockId) -> bool {
+        matches!(chunk.storage, ChunkStorage::Value(v) if v == value)
+    }
+
+    #[test]
+    fn promote() {
+        let stone = BlockId(1);
+        let mut chunk = Chunk16::empty();
+        assert_eq!(chunk.set_at(0, BlockId::default()), Some(false));
+        assert!(matches!(chunk.storage, ChunkStorage::Empty));
+        assert_eq!(chunk.set_at(chunk.size().volume(), stone), None);
+
+        assert_eq!(chunk.set_at(5, stone), Some(true));
+        assert!(matches!(chunk.storage, ChunkStorage::Palette(_)));
+        assert_eq!(chunk.get_at(4), Some(BlockId::default()));
+        assert_eq!(chunk.get_at(5), Some(stone));
+
+        let mut chunk = Chunk16::filled(stone);
+        let corner = BlockBox::new(BlockCoord::default(), BlockSize::splat(2));
+        chunk.fill(corner, stone).unwrap();
+        assert!(is_value(&chunk, stone));
+
+        let src = vec![BlockId(2); 8];
+        chunk
+            .set_slice(corner, BlockCoord::default(), corner.size, &src)
+            .unwrap();
+        assert_eq!(chunk.get_at(0), Some(BlockId(2)));
+        assert_eq!(chunk.get_at(chunk.get_offset(2, 0, 0)), Some(stone));
+    }
+
+    #[test]
+    fn demote() {
+        let (stone, dirt) = (BlockId(1), BlockId(2));
+        let mut chunk = Chunk16::empty();
+        chunk.set_at(0, stone);
+        assert!(chunk.optimize());
+        assert_eq!(chunk.storage().kind(), ChunkStorageKind::Octree);
+        assert!(!chunk.optimize());
+
+        // Writes expand an octree that outgrew a palette of its two blocks.
+        for offset in (0..Chunk16::VOLUME).step_by(128) {
+            chunk.set_at(offset, stone);
+        }
+        assert_eq!(chunk.storage().kind(), ChunkStorageKind::Palette);
+        assert!(!chunk.expand_octree());
+
+        chunk.fill(chunk.bounds(), dirt).unwrap();
+        assert!(is_value(&chunk, dirt));
+
+        // Overwriting every block one at a time leaves stale palette entries behind.
+        chunk.set_at(0, stone);
+        for offset in 0..chunk.size().volume() {
+            chunk.set_at(offset, stone);
+        }
+        assert!(matches!(chunk.storage, ChunkStorage::Palette(_)));
+        assert!(chunk.optimize());
+        assert!(is_value(&chunk, stone));
+        assert!(!chunk.optimize());
+    }
+
+    #[test]
+    fn plugin() {
+        let mut app = App::new();
+        app.add_plugins(ChunkStoragePlugin::<16, 16, 16>);
+        let mut chunk = Chunk16::empty();
+        chunk.set_at(0, BlockId(1));
+        assert_eq!(chunk.storage().kind(), ChunkStorageKind::Palette);
+        let entity = app.world_mut().spawn(chunk).id();
+
+        app.update();
+        let chunk = app.world().get::<Chunk16>(entity).unwrap();
+        assert_eq!(chunk.storage().kind(), ChunkStorageKind::Octree);
+        assert_eq!(chunk.get_at(0), Some(BlockId(1)));
+    }
+
+    #[test]
+    fn blocks() {
+        let blocks: Vec<_> = (0..Chunk16::VOLUME as u32)
+            .map(|i| BlockId(i % 5))
+            .collect();
+        let chunk = Chunk16::from_blocks(&blocks).unwrap();
+        assert_eq!(chunk.to_blocks(), blocks);
+        assert_eq!(chunk.get_at(chunk.get_offset(1, 0, 0)), Some(BlockId(1)));
+
+        let ChunkStorage::Palette(palette) = chunk.storage() else {
+            panic!("expected a palette, got {:?}", chunk.storage().kind());
+        };
+        assert_eq!(palette.entries(), &[0, 1, 2, 3, 4].map(BlockId));
+        assert_eq!(palette.value_bits(), 3);
+        assert!(chunk.memory_footprint() >= Chunk16::VOLUME * 3 / 8);
+
+        let uniform = Chunk16::from_blocks(&vec![BlockId(3); Chunk16::VOLUME]).unwrap();
+        assert_eq!(uniform.storage().kind(), ChunkStorageKind::Value);
+        assert_eq!(uniform.memory_footprint(), size_of::<Chunk>());
+        assert_eq!(
+            Chunk16::empty().to_blocks(),
+            vec![BlockId::default(); Chunk16::VOLUME]
+        );
+        assert_eq!(
+            Chunk16::from_blocks(&blocks[1..]).unwrap_err(),
+            StorageError::LengthMismatch {
+                expected: Chunk16::VOLUME,
+                len: Chunk16::VOLUME - 1
+            }
+        );
+    }
+
+    #[test]
+    fn direct() {
+        let mut chunk = Chunk16::empty();
+        for i in 0..=DIRECT_THRESHOLD {
+            chunk.set_at(i, BlockId(i as u32 + 1));
+        }
+        assert_eq!(chunk.storage().kind(), ChunkStorageKind::Direct);
+        assert_eq!(chunk.get_at(DIRECT_THRESHOLD), Some(BlockId(257)));
+        assert_eq!(chunk.get_at(Chunk16::VOLUME - 1), Some(BlockId::default()));
+
+        // Direct storage is as wide as the registry ids.
+        let mut wide = Chunk16::empty();
+        wide.set_id_bits(PartSize::new(12).unwrap());
+        for i in 0..=DIRECT_THRESHOLD {
+            wide.set_at(i, BlockId(i as u32 + 1));
+        }
+        let ChunkStorage::Direct(direct) = wide.storage() else {
+            panic!("expected direct, got {:?}", wide.storage().kind());
+        };
+        assert_eq!(direct.value_bits(), 12);
+
+        let blocks: Vec<_> = (0..Chunk16::VOLUME as u32)
+            .map(|i| BlockId(i % 300))
+            .collect();
+        let mut chunk = Chunk16::from_blocks(&blocks).unwrap();
+        let ChunkStorage::Direct(direct) = chunk.storage() else {
+            panic!("expected direct, got {:?}", chunk.storage().kind());
+        };
+        assert_eq!(direct.value_bits(), 9);
+        assert_eq!(chunk.to_blocks(), blocks);
+
+        // Larger ids widen the storage in place of the palette growing.
+        let large = BlockId(1 << 20);
+        assert_eq!(chunk.set_at(3, large), Some(true));
+        assert_eq!(chunk.get_at(3), Some(large));
+        assert_eq!(chunk.get_at(4), Some(BlockId(4)));
+        let region = BlockBox::new(BlockCoord::splat(1), BlockSize::splat(3));
+        let src: Vec<_> = (0..27).map(|i| BlockId(i + 1000)).collect();
+        chunk
+            .set_slice(region, BlockCoord::default(), region.size, &src)
+            .unwrap();
+        let mut dst = vec![BlockId::default(); 27];
+        chunk
+            .get_slice(region, BlockCoord::default(), region.size, &mut dst)
+            .unwrap();
+        assert_eq!(dst, src);
+
+        // Too many distinct values remain to switch back.
+        let (stone, dirt) = (BlockId(5000), BlockId(5001));
+        let lower = BlockBox::new(
+            BlockCoord::default(),
+            BlockSize {
+                height: Chunk16::HEIGHT.get() - 1,
+                ..chunk.size()
+            },
+        );
+        chunk.fill(lower, stone).unwrap();
+        let mut top = BlockBox::new(
+            BlockCoord {
+                y: Chunk16::HEIGHT.get() - 1,
+                ..Default::default()
+            },
+            BlockSize {
+                width: 8,
+                height: 1,
+                depth: Chunk16::DEPTH.get(),
+            },
+        );
+        chunk.fill(top, dirt).unwrap();
+        assert!(!chunk.optimize());
+        assert_eq!(chunk.storage().kind(), ChunkStorageKind::Direct);
+
+        top.size.width = 12;
+        chunk.fill(top, dirt).unwrap();
+        let expected = chunk.to_blocks();
+        assert!(chunk.optimize());
+        assert_eq!(chunk.storage().kind(), ChunkStorageKind::Palette);
+        assert_eq!(chunk.to_blocks(), expected);
+
+        chunk.fill(chunk.bounds(), stone).unwrap();
+        assert!(is_value(&chunk, stone));
+    }
+
+    #[test]
+    fn octree() {
+        let (stone, leaves) = (BlockId(1), BlockId(2));
+        let mut chunk = Chunk32::empty();
+        let trunk = BlockBox::new(
+            BlockCoord { x: 7, y: 0, z: 7 },
+            BlockSize {
+                width: 1,
+                height: 6,
+                depth: 1,
+            },
+        );
+        let crown = BlockBox::new(BlockCoord { x: 5, y: 6, z: 5 }, BlockSize::splat(5));
+        chunk.fill(crown, leaves).unwrap();
+        chunk.fill(trunk, stone).unwrap();
+        let expected = chunk.to_blocks();
+        let palette_size = chunk.storage().heap_size();
+
+        assert!(chunk.optimize());
+        assert_eq!(chunk.storage().kind(), ChunkStorageKind::Octree);
+        assert!(chunk.storage().heap_size() * OCTREE_RATIO <= palette_size);
+        assert_eq!(chunk.to_blocks(), expected);
+        assert!(!chunk.optimize());
+
+        // Writes stay in the tree until it outgrows a palette.
+        assert_eq!(chunk.set_at(0, stone), Some(true));
+        assert_eq!(chunk.storage().kind(), ChunkStorageKind::Octree);
+        let noise: Vec<_> = (0..Chunk32::VOLUME as u32)
+            .map(|i| BlockId(i * 7 % 3))
+            .collect();
+        let size = chunk.size();
+        chunk
+            .set_slice(chunk.bounds(), BlockCoord::default(), size, &noise)
+            .unwrap();
+        assert_eq!(chunk.storage().kind(), ChunkStorageKind::Palette);
+        assert_eq!(chunk.to_blocks(), noise);
+        assert!(!chunk.optimize());
+
+        // Only sides that are powers of two are supported.
+        let mut odd = Chunk::<12, 16, 16>::empty();
+        odd.set_at(0, stone);
+        assert!(!odd.optimize());
+        assert_eq!(odd.storage().kind(), ChunkStorageKind::Palette);
+    }
+
+    #[test]
+    fn changes() {
+        let (stone, dirt) = (BlockId(1), BlockId(2));
+        let mut chunk = Chunk16::empty();
+        chunk.set_at(0, stone);
+        assert!(chunk.changes().is_none() && chunk.drain_changes().is_none());
+
+        chunk.track_changes();
+        assert_eq!(chunk.set_at(0, stone), Some(false));
+        let offset = chunk.get_offset(3, 4, 5);
+        chunk.set_at(offset, dirt);
+        chunk.set_at(offset, stone);
+        let changes = chunk.changes().unwrap();
+        assert_eq!((changes.len(), changes.count()), (1, 2));
+        assert!(changes.contains(BlockCoord { x: 3, y: 4, z: 5 }));
+        assert_eq!(changes.bounds().volume(), 1);
+
+        // Only blocks that differ from the written values are recorded.
+        let corner = BlockBox::new(BlockCoord::default(), BlockSize::splat(2));
+        chunk.fill(corner, stone).unwrap();
+        let changes = chunk.drain_changes().unwrap();
+        assert_eq!(changes.len(), 8);
+        assert_eq!(
+            changes.bounds(),
+            BlockBox::new(
+                BlockCoord::default(),
+                BlockSize {
+                    width: 4,
+                    height: 5,
+                    depth: 6
+                }
+            )
+        );
+        assert!(!changes.contains(BlockCoord::default()));
+        assert_eq!(changes.iter().count(), changes.len());
+        assert!(changes.iter().all(|c| changes.contains(c)));
+        assert_eq!(changes.rows().count(), 5);
+        assert!(chunk.changes().unwrap().is_empty());
+
+        let src = vec![stone, dirt, stone, dirt];
+        let row = BlockBox::new(
+            BlockCoord { x: 0, y: 9, z: 0 },
+            BlockSize {
+                width: 4,
+                height: 1,
+                depth: 1,
+            },
+        );
+        chunk
+            .set_slice(row, BlockCoord::default(), row.size, &src)
+            .unwrap();
+        let changes = chunk.drain_changes().unwrap();
+        let coords: Vec<_> = changes.iter().map(|c| c.x).collect();
+        assert_eq!(coords, [0, 1, 2, 3]);
+        assert_eq!(changes.rows().collect::<Vec<_>>(), [(9, 0)]);
+
+        chunk.fill(chunk.bounds(), BlockId::default()).unwrap();
+        assert_eq!(chunk.drain_changes().unwrap().len(), 4 + 8 + 1);
+        chunk.untrack_changes();
+        chunk.set_at(0, stone);
+        assert!(chunk.changes().is_none());
+
+        // Sections summarize changes of taller chunks.
+        let mut column = ChunkColumn::empty();
+        column.track_changes();
+        column.set_at(column.get_offset(1, 40, 2), stone);
+        let changes = column.changes().unwrap();
+        let section = BlockCoord { x: 0, y: 2, z: 0 };
+        assert_eq!(changes.section_grid().height, 16);
+        assert!(changes.is_section_dirty(section));
+        assert!(!changes.is_section_dirty(BlockCoord { y: 3, ..section }));
+        assert_eq!(changes.dirty_sections().collect::<Vec<_>>(), [section]);
+        column.drain_changes();
+        assert_eq!(column.changes().unwrap().dirty_sections().count(), 0);
+    }
 
-    /*
-    let size = black_box(64);
-    let src = vec![256; size];
-    let mut dst8 = vec![0 as u8; size];
-    let mut dst16 = vec![0 as u16; size];
-    world::chunk::convert(&src, &mut dst8);
-    world::chunk::convert(&dst8, &mut dst16);
-    println!("{:?}", src);
-    println!("{:?}", dst8);
-    println!("{:?}", dst16);
-    */
+    #[test]
+    fn iteration() {
+        let stone = BlockId(1);
+        let mut palette = Chunk16::empty();
+        palette.set_at(palette.get_offset(3, 0, 0), stone);
+        palette.set_at(palette.get_offset(15, 0, 0), stone);
+        palette.set_at(palette.get_offset(0, 0, 1), stone);
+        let blocks: Vec<_> = (0..Chunk16::VOLUME as u32)
+            .map(|i| BlockId(i % 300))
+            .collect();
+        let chunks = [
+            Chunk16::empty(),
+            Chunk16::filled(stone),
+            palette,
+            Chunk16::from_blocks(&blocks).unwrap(),
+        ];
+
+        for chunk in &chunks {
+            let blocks = chunk.to_blocks();
+            let all: Vec<_> = chunk.iter_blocks().collect();
+            assert_eq!(all.len(), Chunk16::VOLUME);
+            assert!(
+                all.iter()
+                    .enumerate()
+                    .all(|(i, (c, v))| chunk.get_coord_offset(*c) == i && *v == blocks[i])
+            );
+
+            let non_default: Vec<_> = chunk.iter_non_default().collect();
+            let expected: Vec<_> = all
+                .iter()
+                .copied()
+                .filter(|(_, v)| *v != BlockId::default())
+                .collect();
+            assert_eq!(non_default, expected);
+
+            let runs: Vec<_> = chunk.runs().collect();
+            assert_eq!(runs.iter().map(|r| r.2).sum::<usize>(), Chunk16::VOLUME);
+            assert!(runs.windows(2).all(|w| w[0].0 != w[1].0));
+            assert!(runs.windows(2).all(|w| w[0].1 + w[0].2 == w[1].1));
+            assert!(
+                runs.iter().all(|(v, start, len)| {
+                    blocks[*start..*start + *len].iter().all(|b| b == v)
+                })
+            );
+
+            let region = BlockBox::new(
+                BlockCoord { x: 2, y: 1, z: 0 },
+                BlockSize {
+                    width: 3,
+                    height: 2,
+                    depth: 4,
+                },
+            );
+            let boxed: Vec<_> = chunk.iter_box(region).unwrap().collect();
+            let coords: Vec<_> = region.iter().collect();
+            assert_eq!(boxed.iter().map(|(c, _)| *c).collect::<Vec<_>>(), coords);
+            assert!(
+                boxed
+                    .iter()
+                    .all(|(c, v)| *v == blocks[chunk.get_coord_offset(*c)])
+            );
+        }
+
+        assert_eq!(
+            chunks[1].runs().collect::<Vec<_>>(),
+            [(stone, 0, Chunk16::VOLUME)]
+        );
+        assert_eq!(chunks[0].iter_non_default().count(), 0);
+        assert_eq!(
+            chunks[2].runs().take(4).collect::<Vec<_>>(),
+            [
+                (BlockId::default(), 0, 3),
+                (stone, 3, 1),
+                (BlockId::default(), 4, 11),
+                (stone, 15, 2)
+            ]
+        );
+        let past_end = BlockBox::new(BlockCoord::splat(15), BlockSize::splat(2));
+        assert!(chunks[2].iter_box(past_end).is_err());
+        let empty = BlockBox::new(BlockCoord::default(), BlockSize::default());
+        assert_eq!(chunks[2].iter_box(empty).unwrap().len(), 0);
+    }
+
+    fn check_preset<const W: usize, const H: usize, const D: usize>() {
+        let mut chunk = Chunk::<W, H, D>::empty();
+        assert_eq!(chunk.size().volume(), Chunk::<W, H, D>::VOLUME);
+        let last = chunk.get_offset(W - 1, H - 1, D - 1);
+        assert_eq!(last, Chunk::<W, H, D>::VOLUME - 1);
+        assert_eq!(chunk.set_at(last, BlockId(1)), Some(true));
+        assert_eq!(chunk.set_at(last + 1, BlockId(1)), None);
+        assert_eq!(chunk.get_at(last), Some(BlockId(1)));
+
+        for distinct in [5, 300] {
+            let blocks: Vec<_> = (0..Chunk::<W, H, D>::VOLUME as u32)
+                .map(|i| BlockId(i % distinct))
+                .collect();
+            let chunk = Chunk::<W, H, D>::from_blocks(&blocks).unwrap();
+            assert_eq!(chunk.to_blocks(), blocks);
+
+            // A box across several rows and layers, offset from the origin.
+            let region = BlockBox::new(
+                BlockCoord {
+                    x: 1,
+                    y: H - 3,
+                    z: 2,
+                },
+                BlockSize {
+                    width: W - 2,
+                    height: 3,
+                    depth: D - 3,
+                },
+            );
+            let mut dst = vec![BlockId::default(); region.volume()];
+            chunk
+                .get_slice(region, BlockCoord::default(), region.size, &mut dst)
+                .unwrap();
+            let expected: Vec<_> = region
+                .iter()
+                .map(|c| blocks[chunk.get_coord_offset(c)])
+                .collect();
+            assert_eq!(dst, expected);
+        }
+    }
+
+    #[test]
+    fn presets() {
+        check_preset::<16, 16, 16>();
+        check_preset::<32, 32, 32>();
+        check_preset::<16, 256, 16>();
+        check_preset::<4, 8, 4>();
+        assert_eq!(Chunk32::VOLUME, 32 * 32 * 32);
+        assert_eq!(ChunkColumn::HEIGHT.get(), 256);
+    }
+
+    #[test]
+    fn serialize() {
+        use crate::{
+            biome::BiomeId,
+            storage::{decode_from_slice, encode_to_vec},
+        };
+
+        let stone = BlockId(1);
+        let mut chunk = Chunk16::empty();
+        assert_eq!(
+            encode_to_vec(&chunk),
+            [TAG_EMPTY, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0]
+        );
+        chunk.fill(chunk.bounds(), stone).unwrap();
+        assert_eq!(
+            encode_to_vec(&chunk),
+            [TAG_VALUE, 1, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0]
+        );
+
+        let region = BlockBox::new(BlockCoord { x: 2, y: 3, z: 4 }, BlockSize::splat(5));
+        chunk.fill(region, BlockId(7)).unwrap();
+        chunk.set_at(4000, BlockId(9));
+        chunk
+            .biomes_mut()
+            .fill(
+                BlockBox::new(BlockCoord::default(), BlockSize::splat(4)),
+                BiomeId(3),
+            )
+            .unwrap();
+
+        let bytes = encode_to_vec(&chunk);
+        // 2 runs per row crossing the box, 1 merged run between rows and 2 around the single block.
+        let runs = 5 * 5 * 2 + 1 + 2;
+        assert_eq!(bytes[..5], [TAG_RUNS, runs, 0, 0, 0]);
+        let decoded: Chunk16 = decode_from_slice(&bytes).unwrap();
+        assert_eq!(decoded.to_blocks(), chunk.to_blocks());
+        assert_eq!(
+            decoded.biomes().nearest(BlockCoord::splat(3)),
+            Some(BiomeId(3))
+        );
+        assert_eq!(
+            decoded.biomes().nearest(BlockCoord::splat(4)),
+            Some(BiomeId::default())
+        );
+
+        let mut overlong = bytes.clone();
+        overlong[9..13].copy_from_slice(&(Chunk16::VOLUME as u32).to_le_bytes());
+        assert_eq!(
+            decode_from_slice::<Chunk16>(&overlong).unwrap_err(),
+            DecodeError::InvalidValue("chunk block runs")
+        );
+        assert_eq!(
+            decode_from_slice::<Chunk16>(&[3]).unwrap_err(),
+            DecodeError::InvalidTag("chunk storage", 3)
+        );
+    }
 }
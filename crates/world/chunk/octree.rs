@@ -0,0 +1,558 @@
+use std::num::NonZeroUsize;
+
+use crate::block::{BlockBox, BlockCoord, BlockId, BlockSize};
+
+use super::{
+    BlockStorage, BlockView, Chunk, fill, get_index_base, layout::BlockLayout,
+    palette::ChunkPalette,
+};
+
+/// Block storage of cubes split into eight until each part is uniform, for
+/// chunks that are mostly one block, like the sky above builds.
+///
+/// The chunk is tiled by cubes as large as its shortest side, so every side
+/// must be a power of two, see [`ChunkOctree::SUPPORTED`]. Writes split
+/// uniform cubes as needed and merge them back once their parts match again.
+#[derive(Clone, Debug)]
+pub struct ChunkOctree<const W: usize = 16, const H: usize = 16, const D: usize = 16> {
+    roots: Box<[Node]>,
+    /// Children of every branch, see [`Node::Branch`].
+    branches: Vec<[Node; 8]>,
+    /// Unused slots in `branches`.
+    free: Vec<u32>,
+    /// Most branches in use at a [`ChunkOctree::grew_past_check`].
+    checked_branches: usize,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Node {
+    Leaf(BlockId),
+
+    /// Index of the children in [`ChunkOctree::branches`], in [`Cube::child`] order.
+    Branch(u32),
+}
+
+/// A cube of `side` blocks at `min`, with `side` a power of two.
+#[derive(Clone, Copy, Debug)]
+struct Cube {
+    min: BlockCoord,
+    side: usize,
+}
+
+impl Cube {
+    #[inline]
+    fn to_box(self) -> BlockBox {
+        BlockBox::new(self.min, BlockSize::splat(self.side))
+    }
+
+    /// Child `child` of eight, by halves along X, then Z, then Y.
+    #[inline]
+    fn child(self, child: usize) -> Cube {
+        let side = self.side / 2;
+        let min = BlockCoord {
+            x: self.min.x + (child & 1) * side,
+            y: self.min.y + (child >> 2) * side,
+            z: self.min.z + (child >> 1 & 1) * side,
+        };
+        return Cube { min, side };
+    }
+
+    /// The child holding `coord`.
+    #[inline]
+    fn child_index(self, coord: BlockCoord) -> usize {
+        let side = self.side / 2;
+        let x = (coord.x - self.min.x) / side;
+        let y = (coord.y - self.min.y) / side;
+        let z = (coord.z - self.min.z) / side;
+        return x | z << 1 | y << 2;
+    }
+}
+
+impl<const W: usize, const H: usize, const D: usize> ChunkOctree<W, H, D> {
+    /// Whether chunks of this size can use octree storage.
+    pub const SUPPORTED: bool = W.is_power_of_two() && H.is_power_of_two() && D.is_power_of_two();
+
+    /// Side of the cube at the root of each tree.
+    const SIDE: usize = if W < H && W < D {
+        W
+    } else if H < D {
+        H
+    } else {
+        D
+    };
+
+    const ROOTS: usize = Chunk::<W, H, D>::VOLUME / Self::SIDE.pow(3);
+
+    /// Storage with every block set to `value`.
+    pub fn new(value: BlockId) -> Self {
+        assert!(Self::SUPPORTED, "octree storage needs power of two sides");
+        return Self {
+            roots: vec![Node::Leaf(value); Self::ROOTS].into_boxed_slice(),
+            branches: Vec::new(),
+            free: Vec::new(),
+            checked_branches: 0,
+        };
+    }
+
+    /// Converts `palette`, or `None` if the trees would take more than
+    /// `max_heap_size` bytes or chunks of this size are not supported.
+    pub fn from_palette(palette: &ChunkPalette<W, H, D>, max_heap_size: usize) -> Option<Self> {
+        if !Self::SUPPORTED {
+            return None;
+        }
+        let max_branches =
+            max_heap_size.checked_sub(Self::ROOTS * size_of::<Node>())? / size_of::<[Node; 8]>();
+
+        let mut blocks = vec![BlockId::default(); Chunk::<W, H, D>::VOLUME];
+        let (origin, size) = (BlockCoord::default(), palette.size());
+        palette.get_slice_core(origin, size, origin, size, &mut blocks);
+        let value_at = |coord: BlockCoord| blocks[palette.get_coord_offset(coord)];
+
+        let mut octree = Self::new(BlockId::default());
+        for root in 0..Self::ROOTS {
+            octree.roots[root] = octree.build(Self::root(root), &value_at, max_branches)?;
+        }
+        octree.branches.shrink_to_fit();
+        octree.checked_branches = octree.branch_count();
+        return Some(octree);
+    }
+
+    /// A palette of the same blocks, in `layout`.
+    ///
+    /// Each uniform cube is written with a single fill.
+    pub fn to_palette(&self, layout: BlockLayout) -> ChunkPalette<W, H, D> {
+        let mut palette = ChunkPalette::with_layout(BlockId::default(), layout);
+        self.for_each_leaf(|cube, value| palette.fill_core(cube.min, cube.size, value));
+        palette.compact();
+        return palette;
+    }
+
+    /// Calls `f` with every uniform cube and its value.
+    pub fn for_each_leaf(&self, mut f: impl FnMut(BlockBox, BlockId)) {
+        for (root, node) in self.roots.iter().enumerate() {
+            self.visit(*node, Self::root(root), &mut f);
+        }
+    }
+
+    /// The value of every block, if they are all the same.
+    pub fn uniform_value(&self) -> Option<BlockId> {
+        return uniform(&self.roots);
+    }
+
+    /// Bytes allocated on the heap for the trees, including unused slots.
+    #[inline]
+    pub fn heap_size(&self) -> usize {
+        size_of_val(&*self.roots)
+            + self.branches.capacity() * size_of::<[Node; 8]>()
+            + self.free.capacity() * size_of::<u32>()
+    }
+
+    /// Bytes the trees need on the heap, without unused slots.
+    #[inline]
+    pub fn live_size(&self) -> usize {
+        size_of_val(&*self.roots) + self.branch_count() * size_of::<[Node; 8]>()
+    }
+
+    /// Whether more branches are in use than at any earlier call, or since
+    /// conversion from a palette.
+    ///
+    /// Only growing trees can outgrow a palette of their blocks, so writes
+    /// check the palette size once per new high.
+    pub fn grew_past_check(&mut self) -> bool {
+        let count = self.branch_count();
+        if count <= self.checked_branches {
+            return false;
+        }
+        self.checked_branches = count;
+        return true;
+    }
+
+    /// Branches in use.
+    #[inline]
+    fn branch_count(&self) -> usize {
+        self.branches.len() - self.free.len()
+    }
+
+    /// The root cube holding `coord`, with its index.
+    #[inline]
+    fn root_of(coord: BlockCoord) -> (usize, Cube) {
+        let (columns, rows) = (W / Self::SIDE, D / Self::SIDE);
+        let root = get_index_base(rows, columns, coord.y / Self::SIDE, coord.z / Self::SIDE)
+            + coord.x / Self::SIDE;
+        return (root, Self::root(root));
+    }
+
+    #[inline]
+    fn root(root: usize) -> Cube {
+        let (columns, rows) = (W / Self::SIDE, D / Self::SIDE);
+        let min = BlockCoord {
+            x: root % columns * Self::SIDE,
+            y: root / (columns * rows) * Self::SIDE,
+            z: root / columns % rows * Self::SIDE,
+        };
+        return Cube {
+            min,
+            side: Self::SIDE,
+        };
+    }
+
+    fn alloc(&mut self, children: [Node; 8]) -> u32 {
+        if let Some(index) = self.free.pop() {
+            self.branches[index as usize] = children;
+            return index;
+        }
+        self.branches.push(children);
+        return (self.branches.len() - 1) as u32;
+    }
+
+    /// Frees the branches under `node`.
+    fn release(&mut self, node: Node) {
+        if let Node::Branch(index) = node {
+            for child in self.branches[index as usize] {
+                self.release(child);
+            }
+            self.free.push(index);
+        }
+    }
+
+    /// Replaces the branch at `index` with a leaf if its children are one.
+    fn merge(&mut self, index: u32) -> Node {
+        match uniform(&self.branches[index as usize]) {
+            Some(value) => {
+                self.free.push(index);
+                return Node::Leaf(value);
+            }
+            None => return Node::Branch(index),
+        }
+    }
+
+    /// The tree of `cube` holding the blocks of `value_at`, or `None` once
+    /// there are more than `max_branches` branches.
+    fn build(
+        &mut self,
+        cube: Cube,
+        value_at: &impl Fn(BlockCoord) -> BlockId,
+        max_branches: usize,
+    ) -> Option<Node> {
+        if cube.side == 1 {
+            return Some(Node::Leaf(value_at(cube.min)));
+        }
+
+        let mut children = [Node::Leaf(BlockId::default()); 8];
+        for (child, node) in children.iter_mut().enumerate() {
+            *node = self.build(cube.child(child), value_at, max_branches)?;
+        }
+        if let Some(value) = uniform(&children) {
+            return Some(Node::Leaf(value));
+        }
+        if self.branch_count() >= max_branches {
+            return None;
+        }
+        return Some(Node::Branch(self.alloc(children)));
+    }
+
+    fn get(&self, mut node: Node, mut cube: Cube, coord: BlockCoord) -> BlockId {
+        loop {
+            match node {
+                Node::Leaf(value) => return value,
+                Node::Branch(index) => {
+                    let child = cube.child_index(coord);
+                    node = self.branches[index as usize][child];
+                    cube = cube.child(child);
+                }
+            }
+        }
+    }
+
+    /// Sets the block at `coord` in `node`, returning the new node and whether
+    /// the block changed.
+    fn set(&mut self, node: Node, cube: Cube, coord: BlockCoord, value: BlockId) -> (Node, bool) {
+        let index = match node {
+            Node::Leaf(current) if current == value => return (node, false),
+            Node::Leaf(_) if cube.side == 1 => return (Node::Leaf(value), true),
+            Node::Leaf(current) => self.alloc([Node::Leaf(current); 8]),
+            Node::Branch(index) => index,
+        };
+
+        let child = cube.child_index(coord);
+        let old = self.branches[index as usize][child];
+        let (new, changed) = self.set(old, cube.child(child), coord, value);
+        self.branches[index as usize][child] = new;
+        return (self.merge(index), changed);
+    }
+
+    /// Copies the part of `node` in `region` into `dst`, a buffer of
+    /// `dst_bounds` holding `region` at `dst_offset`.
+    fn read(
+        &self,
+        node: Node,
+        cube: Cube,
+        region: BlockBox,
+        dst_offset: BlockCoord,
+        dst_bounds: BlockSize,
+        dst: &mut [BlockId],
+    ) {
+        let Some(part) = cube.to_box().intersect(region) else {
+            return;
+        };
+        match node {
+            Node::Leaf(value) => {
+                let offset = BlockCoord {
+                    x: dst_offset.x + part.min.x - region.min.x,
+                    y: dst_offset.y + part.min.y - region.min.y,
+                    z: dst_offset.z + part.min.z - region.min.z,
+                };
+                fill(offset, part.size, value, dst_bounds, dst);
+            }
+            Node::Branch(index) => {
+                for (child, node) in self.branches[index as usize].iter().enumerate() {
+                    self.read(
+                        *node,
+                        cube.child(child),
+                        region,
+                        dst_offset,
+                        dst_bounds,
+                        dst,
+                    );
+                }
+            }
+        }
+    }
+
+    /// Writes `value`, or the blocks of `value_at` if it is `None`, to the
+    /// part of `node` in `region`, returning the new node.
+    fn write(
+        &mut self,
+        node: Node,
+        cube: Cube,
+        region: BlockBox,
+        value: Option<BlockId>,
+        value_at: &impl Fn(BlockCoord) -> BlockId,
+    ) -> Node {
+        let Some(part) = cube.to_box().intersect(region) else {
+            return node;
+        };
+        if value.is_some_and(|value| node == Node::Leaf(value)) {
+            return node;
+        }
+        if part == cube.to_box() {
+            self.release(node);
+            return match value {
+                Some(value) => Node::Leaf(value),
+                None => self.build(cube, value_at, usize::MAX).unwrap(),
+            };
+        }
+
+        let index = match node {
+            Node::Leaf(current) => self.alloc([Node::Leaf(current); 8]),
+            Node::Branch(index) => index,
+        };
+        for child in 0..8 {
+            let old = self.branches[index as usize][child];
+            let new = self.write(old, cube.child(child), region, value, value_at);
+            self.branches[index as usize][child] = new;
+        }
+        return self.merge(index);
+    }
+
+    fn visit(&self, node: Node, cube: Cube, f: &mut impl FnMut(BlockBox, BlockId)) {
+        match node {
+            Node::Leaf(value) => f(cube.to_box(), value),
+            Node::Branch(index) => {
+                for (child, node) in self.branches[index as usize].iter().enumerate() {
+                    self.visit(*node, cube.child(child), f);
+                }
+            }
+        }
+    }
+
+    /// Writes `value`, or the blocks of `value_at` if it is `None`, to `region`.
+    fn write_region(
+        &mut self,
+        region: BlockBox,
+        value: Option<BlockId>,
+        value_at: &impl Fn(BlockCoord) -> BlockId,
+    ) {
+        for root in 0..Self::ROOTS {
+            let node = self.roots[root];
+            self.roots[root] = self.write(node, Self::root(root), region, value, value_at);
+        }
+    }
+}
+
+/// The value of all `nodes` if they are leaves of the same value.
+fn uniform(nodes: &[Node]) -> Option<BlockId> {
+    let Node::Leaf(value) = nodes[0] else {
+        return None;
+    };
+    return nodes[1..]
+        .iter()
+        .all(|node| *node == Node::Leaf(value))
+        .then_some(value);
+}
+
+impl<const W: usize, const H: usize, const D: usize> BlockView for ChunkOctree<W, H, D> {
+    #[inline]
+    fn width(&self) -> NonZeroUsize {
+        Chunk::<W, H, D>::WIDTH
+    }
+
+    #[inline]
+    fn height(&self) -> NonZeroUsize {
+        Chunk::<W, H, D>::HEIGHT
+    }
+
+    #[inline]
+    fn depth(&self) -> NonZeroUsize {
+        Chunk::<W, H, D>::DEPTH
+    }
+
+    fn get_at(&self, offset: usize) -> Option<BlockId> {
+        if offset >= Chunk::<W, H, D>::VOLUME {
+            return None;
+        }
+        let coord = self.get_coord(offset);
+        let (root, cube) = Self::root_of(coord);
+        return Some(self.get(self.roots[root], cube, coord));
+    }
+
+    fn uniform_value(&self) -> Option<BlockId> {
+        ChunkOctree::uniform_value(self)
+    }
+
+    fn get_slice_core(
+        &self,
+        offset: BlockCoord,
+        size: BlockSize,
+        dst_offset: BlockCoord,
+        dst_bounds: BlockSize,
+        dst: &mut [BlockId],
+    ) {
+        let region = BlockBox::new(offset, size);
+        for (root, node) in self.roots.iter().enumerate() {
+            self.read(*node, Self::root(root), region, dst_offset, dst_bounds, dst);
+        }
+    }
+}
+
+impl<const W: usize, const H: usize, const D: usize> BlockStorage for ChunkOctree<W, H, D> {
+    fn set_at(&mut self, offset: usize, value: BlockId) -> Option<bool> {
+        if offset >= Chunk::<W, H, D>::VOLUME {
+            return None;
+        }
+        let coord = self.get_coord(offset);
+        let (root, cube) = Self::root_of(coord);
+        let (node, changed) = self.set(self.roots[root], cube, coord, value);
+        self.roots[root] = node;
+        return Some(changed);
+    }
+
+    fn set_slice_core(
+        &mut self,
+        offset: BlockCoord,
+        size: BlockSize,
+        src_offset: BlockCoord,
+        src_bounds: BlockSize,
+        src: &[BlockId],
+    ) {
+        let value_at = |coord: BlockCoord| {
+            let y = src_offset.y + coord.y - offset.y;
+            let z = src_offset.z + coord.z - offset.z;
+            let x = src_offset.x + coord.x - offset.x;
+            src[get_index_base(src_bounds.depth, src_bounds.width, y, z) + x]
+        };
+        self.write_region(BlockBox::new(offset, size), None, &value_at);
+    }
+
+    fn fill_core(&mut self, offset: BlockCoord, size: BlockSize, value: BlockId) {
+        self.write_region(BlockBox::new(offset, size), Some(value), &|_| value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check<const W: usize, const H: usize, const D: usize>() {
+        let stone = BlockId(1);
+        let mut octree = ChunkOctree::<W, H, D>::new(BlockId::AIR);
+        let mut model = vec![BlockId::AIR; Chunk::<W, H, D>::VOLUME];
+
+        // A single block splits its way down and merges back once cleared.
+        let offset = octree.get_offset(1, H - 1, 2);
+        assert_eq!(octree.set_at(offset, stone), Some(true));
+        assert_eq!(octree.set_at(offset, stone), Some(false));
+        assert_eq!(octree.get_at(offset), Some(stone));
+        assert_eq!(octree.get_at(offset + 1), Some(BlockId::AIR));
+        assert_eq!(octree.uniform_value(), None);
+        assert_eq!(
+            octree.branch_count(),
+            W.min(H).min(D).trailing_zeros() as usize
+        );
+        assert!(octree.grew_past_check());
+        assert!(!octree.grew_past_check());
+        assert_eq!(octree.set_at(offset, BlockId::AIR), Some(true));
+        assert_eq!(octree.uniform_value(), Some(BlockId::AIR));
+        assert_eq!(octree.branch_count(), 0);
+        assert_eq!(
+            octree.live_size(),
+            size_of::<Node>() * ChunkOctree::<W, H, D>::ROOTS
+        );
+        assert!(octree.heap_size() > octree.live_size());
+        assert_eq!(octree.set_at(offset, stone), Some(true));
+        assert!(!octree.grew_past_check());
+        assert_eq!(octree.set_at(offset, BlockId::AIR), Some(true));
+        assert_eq!(octree.set_at(Chunk::<W, H, D>::VOLUME, stone), None);
+
+        let floor = BlockBox::from_size(BlockSize {
+            width: W,
+            height: 2,
+            depth: D,
+        });
+        octree.fill(floor, stone).unwrap();
+        for coord in floor.iter() {
+            model[octree.get_coord_offset(coord)] = stone;
+        }
+        let region = BlockBox::new(BlockCoord::splat(1), BlockSize::splat(3));
+        let src: Vec<_> = (0..27).map(|i| BlockId(i % 4)).collect();
+        octree
+            .set_slice(region, BlockCoord::default(), region.size, &src)
+            .unwrap();
+        for (coord, value) in region.iter().zip(&src) {
+            model[octree.get_coord_offset(coord)] = *value;
+        }
+
+        let mut blocks = vec![BlockId(u32::MAX); model.len()];
+        let size = octree.size();
+        octree
+            .get_slice(octree.bounds(), BlockCoord::default(), size, &mut blocks)
+            .unwrap();
+        assert_eq!(blocks, model);
+        let mut dst = vec![BlockId(u32::MAX); 4 * 4 * 4];
+        let dst_offset = BlockCoord::splat(1);
+        octree
+            .get_slice(region, dst_offset, BlockSize::splat(4), &mut dst)
+            .unwrap();
+        let copied = BlockBox::new(dst_offset, region.size).iter();
+        let copied: Vec<_> = copied
+            .map(|c| dst[get_index_base(4, 4, c.y, c.z) + c.x])
+            .collect();
+        assert_eq!(copied, src);
+        assert_eq!(dst[0], BlockId(u32::MAX));
+
+        let palette = octree.to_palette(BlockLayout::Morton);
+        assert_eq!(palette.entries().len(), 4);
+        assert!((0..model.len()).all(|i| palette.get_at(i) == Some(model[i])));
+        let back = ChunkOctree::from_palette(&palette, usize::MAX).unwrap();
+        assert_eq!(back.branch_count(), octree.branch_count());
+        assert!((0..model.len()).all(|i| back.get_at(i) == Some(model[i])));
+        assert!(ChunkOctree::from_palette(&palette, back.heap_size() - 1).is_none());
+    }
+
+    #[test]
+    fn octree() {
+        check::<16, 16, 16>();
+        check::<16, 256, 16>();
+        check::<4, 8, 4>();
+    }
+}
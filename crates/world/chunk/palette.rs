@@ -1,58 +1,202 @@
 use std::{
-    collections::hash_map::Entry,
+    marker::PhantomData,
     num::NonZeroUsize,
-    simd::{LaneCount, Mask, Simd, SimdElement, SupportedLaneCount, cmp::SimdPartialEq},
+    simd::{Mask, Simd, SimdElement, cmp::SimdPartialEq},
 };
 
-use collections::{IndexMap, OwnedCut};
-use iters::search::SliceSearch;
+use collections::OwnedCut;
+use iters::{
+    dispatch::{LaneKernel, dispatch},
+    search::SliceSearch,
+};
 use num_traits::PrimInt;
 use pack::{
-    order::{ConstPackOrder, PackOrder, VarPackOrder},
-    part::{Part, PartSize},
-    span::{PackAccess, PackAccessMut, PackSpanMut},
-    vec::{ConstVec, PackVec},
+    order::VarPackOrder,
+    part::PartSize,
+    span::{PackAccess, PackAccessMut},
+    vec::PackVec,
 };
 
 use crate::block::{BlockCoord, BlockId, BlockSize};
 
-use super::{BlockStorage, Chunk, get_index_base};
+use super::{
+    BlockStorage, BlockView, Chunk, get_index_base, iter::block_index_of_any_except,
+    layout::BlockLayout,
+};
+
+pub(crate) mod packed;
+
+use packed::{PackedPalette, PalIdx};
+
+#[derive(Clone, Debug)]
+pub struct ChunkPalette<const W: usize = 16, const H: usize = 16, const D: usize = 16> {
+    core: PackedPalette<BlockId>,
+    layout: BlockLayout,
+}
+
+impl<const W: usize, const H: usize, const D: usize> ChunkPalette<W, H, D> {
+    /// A palette with every block set to `value`.
+    #[inline]
+    pub fn new(value: BlockId) -> Self {
+        Self::with_layout(value, BlockLayout::Linear)
+    }
+
+    /// Like [`ChunkPalette::new`], keeping blocks in `layout`, which must be
+    /// supported, see [`Chunk::set_layout`].
+    pub(super) fn with_layout(value: BlockId, layout: BlockLayout) -> Self {
+        return Self {
+            core: PackedPalette::new(value, Chunk::<W, H, D>::VOLUME),
+            layout,
+        };
+    }
+
+    /// The value of every block, if they are all the same.
+    #[inline]
+    pub fn uniform_value(&self) -> Option<BlockId> {
+        self.core.uniform_value()
+    }
+
+    /// Distinct values in palette order, including ones no block uses anymore.
+    #[inline]
+    pub fn entries(&self) -> &[BlockId] {
+        self.core.entries()
+    }
+
+    /// Live entries found by the last compaction.
+    #[inline]
+    pub(super) fn live_hint(&self) -> usize {
+        self.core.live_hint()
+    }
+
+    /// Bits used to store the palette index of each block.
+    #[inline]
+    pub fn value_bits(&self) -> usize {
+        self.core.value_bits().get()
+    }
+
+    #[inline]
+    pub fn layout(&self) -> BlockLayout {
+        self.layout
+    }
+
+    /// Moves every block to its place in `layout`, which must be supported.
+    pub(super) fn set_layout(&mut self, layout: BlockLayout) {
+        if layout != self.layout {
+            let data = &mut self.core.data;
+            *data = BlockLayout::relayout::<W, H, D>(self.layout, layout, data);
+            self.layout = layout;
+        }
+    }
+
+    /// The packed block data, for comparing storage of the same layout.
+    #[inline]
+    pub(super) fn data(&self) -> &PackVec {
+        self.core.data()
+    }
 
-type PalIdx = u32;
+    /// Drops entries no block uses, remaps the block data and narrows its bit width.
+    ///
+    /// Live entries keep their relative order. Returns whether the palette or the data changed.
+    #[inline]
+    pub fn compact(&mut self) -> bool {
+        self.core.compact()
+    }
 
-#[derive(Debug)]
-pub struct ChunkPalette {
-    indices: IndexMap<BlockId, PalIdx>,
-    data: PackVec,
+    /// Bytes allocated on the heap for the block data and the palette.
+    #[inline]
+    pub fn heap_size(&self) -> usize {
+        self.core.heap_size()
+    }
 }
 
-const fn get_storage_bits_for_palette(count: usize) -> PartSize {
+pub(crate) const fn get_storage_bits_for_palette(count: usize) -> PartSize {
     let size = if count <= 1 {
         1
     } else {
         let max_bits = size_of::<BlockId>() * 8;
         assert!(max_bits <= PartSize::MAX.get());
 
-        let free_bits = (count - 1).leading_zeros() as usize;
-        max_bits
-            .checked_sub(free_bits)
-            .expect("count exceeds representable range")
+        let used_bits = (usize::BITS - (count - 1).leading_zeros()) as usize;
+        assert!(used_bits <= max_bits, "count exceeds representable range");
+        used_bits
     };
     PartSize::new(size).unwrap()
 }
 
-fn block_index_of_any_except<const N: usize>(slice: &[BlockId], value: BlockId) -> Option<usize>
-where
-    LaneCount<N>: SupportedLaneCount,
-    Simd<u32, N>: SimdPartialEq<Mask = Mask<<u32 as SimdElement>::Mask, N>>,
+struct GetBlocks<'a, E, const W: usize, const H: usize, const D: usize> {
+    palette: &'a ChunkPalette<W, H, D>,
+    offset: BlockCoord,
+    size: BlockSize,
+    dst_offset: BlockCoord,
+    dst_bounds: BlockSize,
+    dst: &'a mut [BlockId],
+    _elem: PhantomData<E>,
+}
+
+macro_rules! impl_get_blocks_kernel {
+    ($($elem:ty),*) => {
+        $(
+            impl<const W: usize, const H: usize, const D: usize> LaneKernel for GetBlocks<'_, $elem, W, H, D> {
+                type Elem = $elem;
+                type Output = ();
+
+                #[inline(always)]
+                fn run<const N: usize>(self) -> Self::Output {
+                    self.palette.get_blocks_core::<$elem, N>(
+                        self.offset,
+                        self.size,
+                        self.dst_offset,
+                        self.dst_bounds,
+                        self.dst,
+                    )
+                }
+            }
+        )*
+    };
+}
+
+impl_get_blocks_kernel!(u8, u16, u32);
+
+struct SetBlocks<'a, T, const W: usize, const H: usize, const D: usize> {
+    palette: &'a mut ChunkPalette<W, H, D>,
+    offset: BlockCoord,
+    size: BlockSize,
+    src_offset: BlockCoord,
+    src_bounds: BlockSize,
+    src: &'a [BlockId],
+    _elem: PhantomData<T>,
+}
+
+impl<T: PrimInt, const W: usize, const H: usize, const D: usize> LaneKernel
+    for SetBlocks<'_, T, W, H, D>
 {
-    let slice_32 = bytemuck::cast_slice::<BlockId, u32>(slice);
-    slice_32.index_of_any_except::<N>(value.0)
+    // Lanes are used to search runs in the source blocks.
+    type Elem = BlockId;
+    type Output = ();
+
+    #[inline(always)]
+    fn run<const N: usize>(self) -> Self::Output {
+        self.palette.set_blocks_core::<T, N>(
+            self.offset,
+            self.size,
+            self.src_offset,
+            self.src_bounds,
+            self.src,
+        )
+    }
 }
 
-impl ChunkPalette {
-    #[inline(never)]
-    fn get_blocks_core<E, const BPV: u8, const N: usize>(
+impl<const W: usize, const H: usize, const D: usize> ChunkPalette<W, H, D> {
+    /// Whether whole layers of `size` are contiguous in both storage and a buffer of `bounds`.
+    fn is_layer_contiguous(&self, size: BlockSize, bounds: BlockSize) -> bool {
+        size.width == self.width().get()
+            && size.depth == self.depth().get()
+            && bounds.width == size.width
+            && bounds.depth == size.depth
+    }
+
+    #[inline(always)]
+    fn get_blocks_core<E, const N: usize>(
         &self,
         offset: BlockCoord,
         size: BlockSize,
@@ -60,78 +204,64 @@ impl ChunkPalette {
         dst_bounds: BlockSize,
         dst: &mut [BlockId],
     ) where
-        E: 'static + SimdElement + PrimInt,
-        LaneCount<N>: SupportedLaneCount,
+        E: SimdElement + PrimInt,
         Simd<E, N>: SimdPartialEq<Mask = Mask<E::Mask, N>>,
     {
-        let depth = self.depth().get();
-        let width = self.width().get();
-        let stride = size.width;
-
-        // TODO: increase buffer size preferrably to layer-wide instead of row-wide, to increase SIMD utilization
-        let mut index_buffer: ConstVec<E, BPV> = Default::default();
-        index_buffer.extend_with(stride, 0);
+        let (stride, rows) = if self.is_layer_contiguous(size, dst_bounds) {
+            (size.width * size.depth, 1)
+        } else {
+            (size.width, size.depth)
+        };
 
-        let span = &mut index_buffer.as_span_mut();
+        let value_bits = self.core.value_bits();
+        let mut index_buffer = vec![E::zero(); stride];
 
         for y in 0..size.height {
             let src_y = offset.y + y;
             let dst_y = dst_offset.y + y;
 
-            for z in 0..size.depth {
+            for z in 0..rows {
                 let dst_z = dst_offset.z + z;
                 let dst_idx =
                     get_index_base(dst_bounds.depth, dst_bounds.width, dst_y, dst_z) + dst_offset.x;
                 let dst_slice = dst.cut(dst_idx..(dst_idx + stride));
 
-                let src_z = offset.z + z;
-                let src_idx = offset.x + get_index_base(depth, width, src_y, src_z);
-                self.get_contiguous_blocks(dst_slice, span.cut(src_idx..));
+                // Unpack block indices in bulk.
+                let src_idx = self.get_offset(offset.x, src_y, offset.z + z);
+                pack::unpack(
+                    &mut index_buffer,
+                    self.data().as_slice(),
+                    src_idx,
+                    value_bits,
+                );
+
+                self.get_contiguous_blocks::<E, N>(dst_slice, &index_buffer);
             }
         }
     }
 
-    fn get_contiguous_blocks<E, const BPV: u8, const N: usize>(
-        &self,
-        mut dst: &mut [BlockId],
-        index_buffer: PackSpanMut<ConstPackOrder<E, BPV>>,
-    ) where
-        E: 'static + SimdElement + PrimInt,
-        LaneCount<N>: SupportedLaneCount,
+    #[inline(always)]
+    fn get_contiguous_blocks<E, const N: usize>(&self, dst: &mut [BlockId], src: &[E])
+    where
+        E: SimdElement + PrimInt,
         Simd<E, N>: SimdPartialEq<Mask = Mask<E::Mask, N>>,
     {
-        assert_eq!(index_buffer.len(), dst.len());
-
-        let storage = &self.data;
-        let palette = &self.indices.list;
-
-        let mut src = index_buffer;
+        assert_eq!(src.len(), dst.len());
 
-        // TODO: Add BitArray.IndexOfAnyExcept to reduce unpacking?
+        let palette = self.entries();
 
-        // Unpack block indices in bulk.
-        storage.copy_to(&mut src);
-
-        while src.len() > 0 {
+        for (index, start, len) in src.runs::<N>() {
             // TODO: assert that src (with a specific bits_per_value) can never return values larger than palette len;
             //       could remove boundcheck
-            let index: E = src.get::<E>(0).unwrap();
-
-            // Move ahead while there are duplicates in the source.
-            let len = None; // TODO: src.index_of_any_except(index);
-            let len = len.unwrap_or(src.len()); // Rest of source is same value when None
-
-            // Fill block values in bulk.
             let value = palette[index.to_usize().unwrap()];
-            dst[..len].fill(value);
 
-            src = src.cut(len..);
-            dst = &mut dst[len..];
+            // Fill block values in bulk.
+            dst[start..(start + len)].fill(value);
         }
     }
 
-    #[inline(never)]
-    fn set_blocks_core<T: 'static + PrimInt, const BPV: u8>(
+    #[inline(always)]
+    fn set_blocks_core<T: PrimInt, const N: usize>(
         &mut self,
         offset: BlockCoord,
         size: BlockSize,
@@ -139,80 +269,58 @@ impl ChunkPalette {
         src_bounds: BlockSize,
         src: &[BlockId],
     ) {
-        let src_width = src_bounds.width;
-        let src_depth = src_bounds.depth;
-
-        let dst_width = size.width;
-        let dst_height = size.height;
-        let dst_depth = size.depth;
-
-        let stride = if dst_depth == src_depth && dst_depth == self.depth().get() {
-            dst_width * dst_depth
+        let (stride, rows) = if self.is_layer_contiguous(size, src_bounds) {
+            (size.width * size.depth, 1)
         } else {
-            dst_width
+            (size.width, size.depth)
         };
 
-        // TODO: increase buffer size preferrably to layer-wide instead of row-wide, to increase SIMD utilization
-        let mut index_buffer: ConstVec<T, BPV> = Default::default();
-        index_buffer.extend_with(stride, 0);
+        let mut index_buffer = vec![T::zero(); stride];
 
-        if dst_depth == src_depth && dst_depth == self.width().get() {
-            for y in 0..dst_height {
-                let src_idx = get_index_base(src_depth, src_width, src_offset.y + y, src_offset.z)
-                    + src_offset.x;
-                let dst_offset = self.get_offset(offset.x, offset.y + y, offset.z) + src_offset.x;
-                self.set_contiguous_blocks(
+        for y in 0..size.height {
+            let src_y = src_offset.y + y;
+            let dst_y = offset.y + y;
+
+            for z in 0..rows {
+                let src_idx =
+                    get_index_base(src_bounds.depth, src_bounds.width, src_y, src_offset.z + z)
+                        + src_offset.x;
+                let dst_idx = self.get_offset(offset.x, dst_y, offset.z + z);
+                self.set_contiguous_blocks::<T, N>(
                     src.cut(src_idx..(src_idx + stride)),
-                    index_buffer.as_span_mut().cut(dst_offset..),
+                    dst_idx,
+                    &mut index_buffer,
                 );
             }
-        } else {
-            for y in 0..dst_height {
-                let src_y = src_offset.y + y;
-                for z in 0..dst_depth {
-                    let src_idx = get_index_base(src_depth, src_width, src_y, src_offset.z + z)
-                        + src_offset.x;
-                    let dst_offset = self.get_offset(offset.x, offset.y + y, offset.z + z);
-                    self.set_contiguous_blocks(
-                        src.cut(src_idx..(src_idx + stride)),
-                        index_buffer.as_span_mut().cut(dst_offset..),
-                    );
-                }
-            }
         }
     }
 
-    fn set_contiguous_blocks<T: PrimInt, const BPV: u8>(
+    #[inline(always)]
+    fn set_contiguous_blocks<T: PrimInt, const N: usize>(
         &mut self,
-        mut src: &[BlockId],
-        mut index_buffer: PackSpanMut<ConstPackOrder<T, BPV>>,
+        src: &[BlockId],
+        dst_idx: usize,
+        index_buffer: &mut [T],
     ) {
         assert_eq!(index_buffer.len(), src.len());
 
-        // Unpack block indices in bulk.
-        self.data.copy_to(&mut index_buffer);
-
-        let mut buf_idx = 0;
-        while src.len() > 0 {
-            let value = src[0];
-            let (pal_index, _) = self.get_or_add_index(value);
-            let pal_value = T::from(*pal_index).unwrap();
-
-            // Move ahead while there are duplicates in the source.
-            let len = block_index_of_any_except::<4>(src, value);
-            let len = len.unwrap_or(src.len()); // Rest of source is same value when None
+        let src_32 = bytemuck::cast_slice::<BlockId, u32>(src);
+        for (value, start, len) in src_32.runs::<N>() {
+            let pal_index = self.core.index_or_add(BlockId(value));
+            let pal_value = T::from(pal_index).unwrap();
 
             // Copy block indices in bulk.
-            (&mut index_buffer)
-                .cut(buf_idx..(buf_idx + len))
-                .fill(pal_value);
-
-            src = &src[len..];
-            buf_idx += len;
+            index_buffer[start..(start + len)].fill(pal_value);
         }
 
-        // Pack block indices in bulk.
-        index_buffer.copy_to(&mut self.data);
+        // Pack block indices in bulk; storage may have grown while adding indices.
+        let value_bits = self.core.value_bits();
+        pack::pack(
+            self.core.data.as_slice_mut(),
+            dst_idx,
+            index_buffer,
+            value_bits,
+        );
     }
 
     #[inline(never)]
@@ -221,7 +329,7 @@ impl ChunkPalette {
         let dst_height = size.height;
         let dst_depth = size.depth;
 
-        if dst_depth == self.depth().get() {
+        if dst_width == self.width().get() && dst_depth == self.depth().get() {
             let stride = dst_width * dst_depth;
             for y in 0..dst_height {
                 let dst_idx = self.get_offset(offset.x, offset.y + y, offset.z);
@@ -240,36 +348,36 @@ impl ChunkPalette {
 
     fn fill_contiguous_blocks<T: PrimInt>(&mut self, dst_idx: usize, len: usize, palette_idx: T) {
         //nint changeCount = _storage.AsBitSpan(dstIdx, count).Fill(value, changeTracking);
-        self.data
+        self.core
+            .data
             .as_span_mut()
             .cut(dst_idx..(dst_idx + len))
             .fill(palette_idx)
     }
 }
 
-impl BlockStorage for ChunkPalette {
+impl<const W: usize, const H: usize, const D: usize> BlockView for ChunkPalette<W, H, D> {
+    #[inline]
     fn width(&self) -> NonZeroUsize {
-        Chunk::WIDTH
+        Chunk::<W, H, D>::WIDTH
     }
 
+    #[inline]
     fn height(&self) -> NonZeroUsize {
-        Chunk::HEIGHT
+        Chunk::<W, H, D>::HEIGHT
     }
 
+    #[inline]
     fn depth(&self) -> NonZeroUsize {
-        Chunk::DEPTH
+        Chunk::<W, H, D>::DEPTH
     }
 
-    fn get_at(&self, offset: usize) -> Option<&BlockId> {
-        let index = self.data.get::<PalIdx>(offset)?;
-        return self
-            .indices
-            .value(index)
-            .unwrap_or_else(|| panic!("array contains unknown index {}.", index))
-            .into();
+    #[inline]
+    fn get_at(&self, offset: usize) -> Option<BlockId> {
+        self.core.get(self.layout.slot::<W, H, D>(offset))
     }
 
-    fn get_slice(
+    fn get_slice_core(
         &self,
         offset: BlockCoord,
         size: BlockSize,
@@ -277,23 +385,45 @@ impl BlockStorage for ChunkPalette {
         dst_bounds: BlockSize,
         dst: &mut [BlockId],
     ) {
-        match self.data.order().value_bits().get() {
-            ..=08 => self.get_blocks_core::<u8, 8, 16>(offset, size, dst_offset, dst_bounds, dst),
-            ..=16 => self.get_blocks_core::<u16, 16, 8>(offset, size, dst_offset, dst_bounds, dst),
-            ..=32 => self.get_blocks_core::<u32, 32, 4>(offset, size, dst_offset, dst_bounds, dst),
+        if self.layout != BlockLayout::Linear {
+            let entries = self.entries();
+            let (data, layout) = (self.data(), self.layout);
+            layout.for_each_slot::<W, H, D>(offset, size, dst_offset, dst_bounds, |slot, i| {
+                dst[i] = entries[data.get::<PalIdx>(slot).unwrap() as usize];
+            });
+            return;
+        }
+
+        macro_rules! get_blocks {
+            ($elem:ty) => {
+                dispatch(GetBlocks::<$elem, W, H, D> {
+                    palette: self,
+                    offset,
+                    size,
+                    dst_offset,
+                    dst_bounds,
+                    dst,
+                    _elem: PhantomData,
+                })
+            };
+        }
+
+        match self.core.value_bits().get() {
+            ..=08 => get_blocks!(u8),
+            ..=16 => get_blocks!(u16),
+            ..=32 => get_blocks!(u32),
             value_bits => panic_unsupported_value_bits(value_bits),
         }
     }
+}
 
+impl<const W: usize, const H: usize, const D: usize> BlockStorage for ChunkPalette<W, H, D> {
+    #[inline]
     fn set_at(&mut self, offset: usize, value: BlockId) -> Option<bool> {
-        let index = *self.get_or_add_index(value).0;
-
-        let prev_index = self.data.set(offset, index)?;
-        // TODO: also return prev value?
-        return Some(prev_index != index);
+        self.core.set(self.layout.slot::<W, H, D>(offset), value)
     }
 
-    fn set_slice(
+    fn set_slice_core(
         &mut self,
         offset: BlockCoord,
         size: BlockSize,
@@ -306,55 +436,76 @@ impl BlockStorage for ChunkPalette {
         };
 
         // TODO: is this worthwhile?
-        // Use large N since we are checking a contiguous slice and exiting early.
-        let Some(run_length) = block_index_of_any_except::<16>(src, *first_value) else {
-            return self.fill(offset, size, *first_value);
+        let Some(run_length) = block_index_of_any_except(src, *first_value) else {
+            return self.fill_core(offset, size, *first_value);
         };
 
-        let added_count_estimate = src.len() - run_length;
+        self.core.maybe_compact(None);
+        if self.layout != BlockLayout::Linear {
+            let mut last = None;
+            let layout = self.layout;
+            layout.for_each_slot::<W, H, D>(offset, size, src_offset, src_bounds, |slot, i| {
+                let index = match last {
+                    Some((value, index)) if value == src[i] => index,
+                    _ => self.core.index_or_add(src[i]),
+                };
+                last = Some((src[i], index));
+                self.core.data.set(slot, index);
+            });
+            return;
+        }
+
+        // The first value and every value after its run may be new.
+        let added_count_estimate = 1 + src.len() - run_length;
         let bits_needed_estimate =
-            get_storage_bits_for_palette(self.indices.len() + added_count_estimate);
+            get_storage_bits_for_palette(self.entries().len() + added_count_estimate);
+
+        macro_rules! set_blocks {
+            ($elem:ty) => {
+                dispatch(SetBlocks::<$elem, W, H, D> {
+                    palette: self,
+                    offset,
+                    size,
+                    src_offset,
+                    src_bounds,
+                    src,
+                    _elem: PhantomData,
+                })
+            };
+        }
 
         match bits_needed_estimate.get() {
-            ..=08 => self.set_blocks_core::<u8, 8>(offset, size, src_offset, src_bounds, src),
-            ..=16 => self.set_blocks_core::<u16, 16>(offset, size, src_offset, src_bounds, src),
-            ..=32 => self.set_blocks_core::<u32, 32>(offset, size, src_offset, src_bounds, src),
+            ..=08 => set_blocks!(u8),
+            ..=16 => set_blocks!(u16),
+            ..=32 => set_blocks!(u32),
             bpv => panic_unsupported_value_bits(bpv),
         }
     }
 
-    fn fill(&mut self, offset: BlockCoord, size: BlockSize, value: BlockId) {
-        let palette_idx = *self.get_or_add_index(value).0;
-        match self.data.order().value_bits().get() {
+    fn fill_core(&mut self, offset: BlockCoord, size: BlockSize, value: BlockId) {
+        self.core.maybe_compact(Some(value));
+        let palette_idx = self.core.index_or_add(value);
+        if self.layout != BlockLayout::Linear {
+            let layout = self.layout;
+            layout.for_each_slot::<W, H, D>(offset, size, offset, self.size(), |slot, _| {
+                self.core.data.set(slot, palette_idx);
+            });
+            return;
+        }
+        match self.core.value_bits().get() {
             ..=08 => self.fill_block_core::<u8>(offset, size, palette_idx as u8),
             ..=16 => self.fill_block_core::<u16>(offset, size, palette_idx as u16),
-            ..=32 => self.fill_block_core::<u32>(offset, size, palette_idx as u32),
+            ..=32 => self.fill_block_core::<u32>(offset, size, palette_idx),
             value_bits => panic_unsupported_value_bits(value_bits),
         }
     }
 }
 
-impl ChunkPalette {
-    fn get_or_add_index(&mut self, value: BlockId) -> (&PalIdx, bool) {
-        let bits_needed = get_storage_bits_for_palette(self.indices.len() + 1);
-        let next_index = self.indices.get_next_index();
-        match self.indices.map.entry(value) {
-            Entry::Occupied(occupied) => (occupied.into_mut(), false),
-            Entry::Vacant(vacant) => {
-                if self.data.order().value_bits() != bits_needed {
-                    std::hint::cold_path();
-                    self.data = resize_storage(&self.data, bits_needed);
-                }
-                (vacant.insert(next_index), true)
-            }
-        }
-    }
-}
-
 // TODO: resize in-place
-fn resize_storage(data: &PackVec, value_bits: PartSize) -> PackVec {
+pub(crate) fn resize_storage(data: &PackVec, value_bits: PartSize) -> PackVec {
     let order = VarPackOrder::new(value_bits);
     let mut new_storage = PackVec::with_capacity(data.len(), order);
+    new_storage.extend_with(data.len(), 0);
 
     let src_span = data.as_span();
     let mut dst_span = new_storage.as_span_mut();
@@ -367,3 +518,6 @@ fn resize_storage(data: &PackVec, value_bits: PartSize) -> PackVec {
 fn panic_unsupported_value_bits(value_bits: usize) -> ! {
     panic!("unsupported value bit-size {}.", value_bits)
 }
+
+#[cfg(test)]
+mod tests;
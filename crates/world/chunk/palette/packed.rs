@@ -0,0 +1,195 @@
+use std::hash::Hash;
+
+use collections::IndexMap;
+use pack::{
+    order::{PackOrder, VarPackOrder},
+    part::PartSize,
+    span::{PackAccess, PackAccessMut},
+    vec::PackVec,
+};
+
+use super::{get_storage_bits_for_palette, resize_storage};
+
+pub(crate) type PalIdx = u32;
+
+/// Values stored as packed indices into a palette of the distinct values.
+///
+/// The core of [`super::ChunkPalette`] and [`crate::biome::ChunkBiomes`], which
+/// decide where each position is kept.
+#[derive(Clone, Debug)]
+pub(crate) struct PackedPalette<T> {
+    pub(super) indices: IndexMap<T, PalIdx>,
+    pub(super) data: PackVec,
+    /// Live entries found by the last compaction, or a lower bound.
+    live_hint: usize,
+}
+
+/// Writes compact first once the palette has this many times the entries
+/// that were live at the last compaction.
+pub(super) const COMPACT_RATIO: usize = 2;
+
+/// Palettes this small are never compacted automatically.
+const COMPACT_MIN_LEN: usize = 4;
+
+impl<T: Copy + Hash + Eq> PackedPalette<T> {
+    /// `len` slots set to `value`.
+    pub fn new(value: T, len: usize) -> Self {
+        let mut indices = IndexMap::default();
+        indices.index_or_add(value);
+
+        let mut data = PackVec::new_var(get_storage_bits_for_palette(1));
+        data.extend_with(len, 0);
+        return Self {
+            indices,
+            data,
+            live_hint: 1,
+        };
+    }
+
+    /// A palette of `entries` with `data` indexing them, `None` if an entry repeats.
+    ///
+    /// Every index in `data` must be in bounds of `entries`.
+    pub fn from_parts(entries: &[T], data: PackVec) -> Option<Self> {
+        let mut indices = IndexMap::default();
+        for entry in entries {
+            if !indices.index_or_add(*entry).1 {
+                return None;
+            }
+        }
+        return Some(Self {
+            indices,
+            data,
+            live_hint: entries.len(),
+        });
+    }
+
+    /// Distinct values in palette order, including ones no slot uses anymore.
+    #[inline]
+    pub fn entries(&self) -> &[T] {
+        &self.indices.list
+    }
+
+    /// The packed palette indices.
+    #[inline]
+    pub fn data(&self) -> &PackVec {
+        &self.data
+    }
+
+    /// Live entries found by the last compaction.
+    #[inline]
+    pub fn live_hint(&self) -> usize {
+        self.live_hint
+    }
+
+    /// Bits used to store each palette index.
+    #[inline]
+    pub fn value_bits(&self) -> PartSize {
+        self.data.order().value_bits()
+    }
+
+    #[inline]
+    pub fn get(&self, slot: usize) -> Option<T> {
+        let index = self.data.get::<PalIdx>(slot)?;
+        let value = self
+            .indices
+            .value(index)
+            .unwrap_or_else(|| panic!("array contains unknown index {}.", index));
+        return Some(*value);
+    }
+
+    /// Sets `slot` to `value`, returning whether it changed.
+    pub fn set(&mut self, slot: usize, value: T) -> Option<bool> {
+        self.maybe_compact(Some(value));
+        let index = self.index_or_add(value);
+        let prev_index = self.data.set(slot, index)?;
+        return Some(prev_index != index);
+    }
+
+    /// The value of every slot, if they are all the same.
+    pub fn uniform_value(&self) -> Option<T> {
+        if let [value] = self.indices.list.as_slice() {
+            return Some(*value);
+        }
+
+        let first = self.data.get::<PalIdx>(0)?;
+        if !self.data.as_span().all(|index| index == first as u64) {
+            return None;
+        }
+        return self.indices.value(first).copied();
+    }
+
+    /// Palette index of `value`, adding it and widening the data if missing.
+    pub fn index_or_add(&mut self, value: T) -> PalIdx {
+        if let Some(index) = self.indices.index(&value) {
+            return *index;
+        }
+
+        let bits_needed = get_storage_bits_for_palette(self.indices.len() + 1);
+        if self.value_bits() != bits_needed {
+            std::hint::cold_path();
+            self.data = resize_storage(&self.data, bits_needed);
+        }
+        return *self.indices.index_or_add(value).0;
+    }
+
+    /// Drops entries no slot uses, remaps the data and narrows its bit width.
+    ///
+    /// Live entries keep their relative order. Returns whether the palette or the data changed.
+    pub fn compact(&mut self) -> bool {
+        let mut counts = vec![0u32; self.indices.len()];
+        for index in self.data.as_span() {
+            counts[index as usize] += 1;
+        }
+
+        let live = counts.iter().filter(|count| **count > 0).count();
+        self.live_hint = live;
+        let value_bits = get_storage_bits_for_palette(live);
+        let dropped = self.indices.len() - live;
+        if dropped == 0 && value_bits == self.value_bits() {
+            return false;
+        }
+
+        let mut indices = IndexMap::default();
+        let mut remap = vec![PalIdx::MAX; counts.len()];
+        let entries = self.indices.list.iter().zip(&counts);
+        for (old_index, (value, count)) in entries.enumerate() {
+            if *count > 0 {
+                remap[old_index] = *indices.index_or_add(*value).0;
+            }
+        }
+
+        let mut data = PackVec::with_capacity(self.data.len(), VarPackOrder::new(value_bits));
+        for index in self.data.as_span() {
+            data.push(remap[index as usize]);
+        }
+
+        self.indices = indices;
+        self.data = data;
+        return true;
+    }
+
+    /// Compacts if the palette grew past [`COMPACT_RATIO`] times its live entries,
+    /// or if adding `value` would widen the data, which rewrites it anyway.
+    ///
+    /// Must run before a write resolves palette indices, as it remaps them.
+    pub fn maybe_compact(&mut self, value: Option<T>) {
+        let len = self.indices.len();
+        let widens = value.is_some_and(|value| {
+            self.indices.index(&value).is_none()
+                && get_storage_bits_for_palette(len + 1) > self.value_bits()
+        });
+        if widens || (len >= COMPACT_MIN_LEN && len >= self.live_hint * COMPACT_RATIO) {
+            std::hint::cold_path();
+            self.compact();
+        }
+    }
+
+    /// Bytes allocated on the heap for the data and the palette.
+    pub fn heap_size(&self) -> usize {
+        let data = size_of_val(self.data.as_slice());
+        let list = self.indices.list.capacity() * size_of::<T>();
+        // Entry plus one control byte per bucket.
+        let map = self.indices.map.capacity() * (size_of::<(T, PalIdx)>() + 1);
+        return data + list + map;
+    }
+}
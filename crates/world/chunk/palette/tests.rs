@@ -0,0 +1,399 @@
+//! Differential tests that drive `ChunkPalette` with random operations
+//! and compare every block against a plain `Vec<BlockId>` model.
+
+use iters::dispatch::{SimdLevel, dispatch_at};
+use pack::testing::check;
+use rand::{Rng, SeedableRng};
+use rand_xoshiro::Xoshiro256PlusPlus;
+
+use super::{packed::COMPACT_RATIO, *};
+use crate::{
+    block::BlockBox,
+    chunk::{Chunk16, StorageError},
+};
+
+type DefaultPalette = ChunkPalette;
+
+const SEED_COUNT: u64 = 256;
+const OP_COUNT: usize = 24;
+
+const SIZE: BlockSize = BlockSize {
+    width: Chunk16::WIDTH.get(),
+    height: Chunk16::HEIGHT.get(),
+    depth: Chunk16::DEPTH.get(),
+};
+
+#[derive(Clone, Debug)]
+enum Op {
+    SetAt(BlockCoord, BlockId),
+    Fill(BlockCoord, BlockSize, BlockId),
+    SetSlice {
+        offset: BlockCoord,
+        size: BlockSize,
+        src_offset: BlockCoord,
+        src_bounds: BlockSize,
+        src: Vec<BlockId>,
+    },
+    GetSlice {
+        offset: BlockCoord,
+        size: BlockSize,
+        dst_offset: BlockCoord,
+        dst_bounds: BlockSize,
+    },
+}
+
+struct Model {
+    blocks: Vec<BlockId>,
+}
+
+impl Model {
+    fn index(bounds: BlockSize, x: usize, y: usize, z: usize) -> usize {
+        (y * bounds.depth + z) * bounds.width + x
+    }
+
+    fn copy(
+        src: &[BlockId],
+        src_offset: BlockCoord,
+        src_bounds: BlockSize,
+        dst: &mut [BlockId],
+        dst_offset: BlockCoord,
+        dst_bounds: BlockSize,
+        size: BlockSize,
+    ) {
+        for y in 0..size.height {
+            for z in 0..size.depth {
+                for x in 0..size.width {
+                    let (sx, sy, sz) = (src_offset.x + x, src_offset.y + y, src_offset.z + z);
+                    let (dx, dy, dz) = (dst_offset.x + x, dst_offset.y + y, dst_offset.z + z);
+                    dst[Self::index(dst_bounds, dx, dy, dz)] =
+                        src[Self::index(src_bounds, sx, sy, sz)];
+                }
+            }
+        }
+    }
+}
+
+fn gen_block(rng: &mut Xoshiro256PlusPlus, distinct: u32) -> BlockId {
+    BlockId(rng.gen_range(0..distinct))
+}
+
+fn gen_box(rng: &mut Xoshiro256PlusPlus) -> (BlockCoord, BlockSize) {
+    let mut axis = |len: usize| {
+        let min = rng.gen_range(0..len);
+        (min, rng.gen_range(1..=len - min))
+    };
+    let (x, width) = axis(SIZE.width);
+    let (y, height) = axis(SIZE.height);
+    let (z, depth) = axis(SIZE.depth);
+    (
+        BlockCoord { x, y, z },
+        BlockSize {
+            width,
+            height,
+            depth,
+        },
+    )
+}
+
+/// Random bounds that fit `size`, with `size` placed at a random offset inside.
+fn gen_bounds(rng: &mut Xoshiro256PlusPlus, size: BlockSize) -> (BlockCoord, BlockSize) {
+    let mut axis = |len: usize| {
+        let bound = len + rng.gen_range(0..3);
+        (rng.gen_range(0..=bound - len), bound)
+    };
+    let (x, width) = axis(size.width);
+    let (y, height) = axis(size.height);
+    let (z, depth) = axis(size.depth);
+    (
+        BlockCoord { x, y, z },
+        BlockSize {
+            width,
+            height,
+            depth,
+        },
+    )
+}
+
+fn gen_op(rng: &mut Xoshiro256PlusPlus, distinct: u32) -> Op {
+    match rng.gen_range(0..4) {
+        0 => {
+            let x = rng.gen_range(0..SIZE.width);
+            let y = rng.gen_range(0..SIZE.height);
+            let z = rng.gen_range(0..SIZE.depth);
+            Op::SetAt(BlockCoord { x, y, z }, gen_block(rng, distinct))
+        }
+        1 => {
+            let (offset, size) = gen_box(rng);
+            Op::Fill(offset, size, gen_block(rng, distinct))
+        }
+        2 => {
+            let (offset, size) = gen_box(rng);
+            let (src_offset, src_bounds) = gen_bounds(rng, size);
+
+            // Write runs so both the run search and the palette growth get exercised.
+            let mut src = Vec::with_capacity(src_bounds.volume());
+            while src.len() < src_bounds.volume() {
+                let len = rng.gen_range(1..=24).min(src_bounds.volume() - src.len());
+                src.extend(std::iter::repeat_n(gen_block(rng, distinct), len));
+            }
+            Op::SetSlice {
+                offset,
+                size,
+                src_offset,
+                src_bounds,
+                src,
+            }
+        }
+        _ => {
+            let (offset, size) = gen_box(rng);
+            let (dst_offset, dst_bounds) = gen_bounds(rng, size);
+            Op::GetSlice {
+                offset,
+                size,
+                dst_offset,
+                dst_bounds,
+            }
+        }
+    }
+}
+
+fn run(ops: &[Op], layout: BlockLayout) {
+    let mut palette = DefaultPalette::with_layout(BlockId::default(), layout);
+    let mut model = Model {
+        blocks: vec![BlockId::default(); SIZE.volume()],
+    };
+
+    for (step, op) in ops.iter().enumerate() {
+        match op {
+            Op::SetAt(coord, value) => {
+                let offset = palette.get_coord_offset(*coord);
+                let index = Model::index(SIZE, coord.x, coord.y, coord.z);
+                let changed = std::mem::replace(&mut model.blocks[index], *value) != *value;
+                assert_eq!(palette.set_at(offset, *value), Some(changed), "step {step}");
+            }
+            Op::Fill(offset, size, value) => {
+                palette.fill(BlockBox::new(*offset, *size), *value).unwrap();
+
+                let src = vec![*value; size.volume()];
+                let origin = BlockCoord::default();
+                Model::copy(&src, origin, *size, &mut model.blocks, *offset, SIZE, *size);
+            }
+            Op::SetSlice {
+                offset,
+                size,
+                src_offset,
+                src_bounds,
+                src,
+            } => {
+                let region = BlockBox::new(*offset, *size);
+                palette
+                    .set_slice(region, *src_offset, *src_bounds, src)
+                    .unwrap();
+
+                let (blocks, src_offset, src_bounds) =
+                    (&mut model.blocks, *src_offset, *src_bounds);
+                Model::copy(src, src_offset, src_bounds, blocks, *offset, SIZE, *size);
+            }
+            Op::GetSlice {
+                offset,
+                size,
+                dst_offset,
+                dst_bounds,
+            } => {
+                let sentinel = BlockId(u32::MAX);
+                let mut dst = vec![sentinel; dst_bounds.volume()];
+                let region = BlockBox::new(*offset, *size);
+                palette
+                    .get_slice(region, *dst_offset, *dst_bounds, &mut dst)
+                    .unwrap();
+
+                let mut expected = vec![sentinel; dst_bounds.volume()];
+                let (dst_offset, dst_bounds) = (*dst_offset, *dst_bounds);
+                Model::copy(
+                    &model.blocks,
+                    *offset,
+                    SIZE,
+                    &mut expected,
+                    dst_offset,
+                    dst_bounds,
+                    *size,
+                );
+                assert_eq!(dst, expected, "step {step}");
+            }
+        }
+
+        for (i, expected) in model.blocks.iter().enumerate() {
+            assert_eq!(
+                palette.get_at(i),
+                Some(*expected),
+                "step {step}, offset {i}"
+            );
+        }
+
+        let mut all = vec![BlockId(u32::MAX); SIZE.volume()];
+        let origin = BlockCoord::default();
+        palette
+            .get_slice(palette.bounds(), origin, SIZE, &mut all)
+            .unwrap();
+        assert_eq!(all, model.blocks, "step {step}");
+    }
+}
+
+#[test]
+fn palette_model() {
+    for seed in 0..SEED_COUNT {
+        let mut rng = Xoshiro256PlusPlus::seed_from_u64(seed);
+
+        // Vary palette sizes to cross storage bit-width boundaries.
+        let distinct = [2, 3, 17, 300][rng.gen_range(0..4)];
+        let ops = (0..OP_COUNT).map(|_| gen_op(&mut rng, distinct)).collect();
+        let layout = match seed % 2 {
+            0 => BlockLayout::Linear,
+            _ => BlockLayout::Morton,
+        };
+
+        check(seed, ops, |ops| run(ops, layout));
+    }
+}
+
+#[test]
+fn palette_bounds() {
+    let mut palette = DefaultPalette::new(BlockId::default());
+    let value = BlockId(7);
+    let origin = BlockCoord::default();
+    let past_end = BlockBox::new(BlockCoord { x: 1, ..origin }, SIZE);
+
+    let out_of_bounds = Err(StorageError::OutOfBounds {
+        region: past_end,
+        bounds: SIZE,
+    });
+    assert_eq!(palette.fill(past_end, value), out_of_bounds);
+
+    let mut dst = vec![BlockId::default(); SIZE.volume() - 1];
+    assert_eq!(
+        palette.get_slice(palette.bounds(), origin, SIZE, &mut dst),
+        Err(StorageError::BufferTooSmall {
+            bounds: SIZE,
+            len: SIZE.volume() - 1
+        })
+    );
+
+    let src = vec![value; 8];
+    let region = BlockBox::new(origin, BlockSize::splat(2));
+    assert!(
+        palette
+            .set_slice(region, BlockCoord::splat(1), BlockSize::splat(2), &src)
+            .is_err()
+    );
+    assert_eq!(
+        palette.set_slice(region, origin, BlockSize::splat(2), &src),
+        Ok(())
+    );
+
+    // Empty boxes are accepted anywhere.
+    let empty = BlockBox::new(BlockCoord::splat(usize::MAX), BlockSize::default());
+    assert_eq!(palette.fill(empty, BlockId(9)), Ok(()));
+    let overflowing = BlockBox::new(empty.min, BlockSize { width: 0, ..SIZE });
+    assert!(palette.fill(overflowing, BlockId(9)).is_err());
+    assert_eq!(palette.get_at(0), Some(value));
+    assert_eq!(
+        palette.get_at(palette.get_offset(2, 0, 0)),
+        Some(BlockId::default())
+    );
+}
+
+#[test]
+fn palette_compact() {
+    let mut palette = DefaultPalette::new(BlockId::default());
+    for i in 0..300 {
+        palette.set_at(i, BlockId(i as u32 + 1)).unwrap();
+    }
+    assert_eq!(palette.value_bits(), 9);
+
+    // Overwrite everything but the first two blocks of each row.
+    let region = BlockBox::new(
+        BlockCoord { x: 2, y: 0, z: 0 },
+        BlockSize {
+            width: SIZE.width - 2,
+            ..SIZE
+        },
+    );
+    palette.fill(region, BlockId(7)).unwrap();
+    let mut expected = vec![BlockId(u32::MAX); SIZE.volume()];
+    let origin = BlockCoord::default();
+    palette
+        .get_slice(palette.bounds(), origin, SIZE, &mut expected)
+        .unwrap();
+
+    let mut live = expected.clone();
+    live.sort_by_key(|value| value.0);
+    live.dedup();
+    assert!(palette.compact());
+    let mut entries = palette.entries().to_vec();
+    entries.sort_by_key(|value| value.0);
+    assert_eq!(entries, live);
+    assert_eq!(palette.value_bits(), 6);
+    assert!(!palette.compact());
+
+    let mut blocks = vec![BlockId(u32::MAX); SIZE.volume()];
+    palette
+        .get_slice(palette.bounds(), origin, SIZE, &mut blocks)
+        .unwrap();
+    assert_eq!(blocks, expected);
+
+    // Repeatedly replacing a single block keeps the palette bounded.
+    for i in 0..10_000 {
+        palette.set_at(0, BlockId(1000 + i)).unwrap();
+    }
+    let len = palette.entries().len();
+    assert!(len <= COMPACT_RATIO * live.len(), "{len}");
+}
+
+/// Copies `src` in and out of a palette through the kernels at `level`.
+fn copy_at<E: PrimInt>(level: SimdLevel, src: &[BlockId]) -> Vec<BlockId>
+where
+    for<'a> GetBlocks<'a, E, 16, 16, 16>: LaneKernel<Output = ()>,
+{
+    let mut palette = DefaultPalette::new(BlockId::default());
+    let origin = BlockCoord::default();
+    let set = SetBlocks::<E, 16, 16, 16> {
+        palette: &mut palette,
+        offset: origin,
+        size: SIZE,
+        src_offset: origin,
+        src_bounds: SIZE,
+        src,
+        _elem: PhantomData,
+    };
+    // SAFETY: callers clamp the level to the detected one.
+    unsafe { dispatch_at(level, set) };
+
+    let mut dst = vec![BlockId(u32::MAX); SIZE.volume()];
+    let get = GetBlocks::<E, 16, 16, 16> {
+        palette: &palette,
+        offset: origin,
+        size: SIZE,
+        dst_offset: origin,
+        dst_bounds: SIZE,
+        dst: &mut dst,
+        _elem: PhantomData,
+    };
+    // SAFETY: as above.
+    unsafe { dispatch_at(level, get) };
+    return dst;
+}
+
+#[test]
+fn palette_levels() {
+    let detected = SimdLevel::detect();
+    let blocks = |distinct: usize| -> Vec<_> {
+        (0..SIZE.volume())
+            .map(|i| BlockId((i / 3 % distinct) as u32))
+            .collect()
+    };
+    let (narrow, wide) = (blocks(200), blocks(1000));
+    for level in SimdLevel::ALL.map(|level| level.min(detected)) {
+        assert_eq!(copy_at::<u8>(level, &narrow), narrow, "{level:?}");
+        assert_eq!(copy_at::<u16>(level, &wide), wide, "{level:?}");
+    }
+}
@@ -0,0 +1,702 @@
+use std::{ops::Range, sync::Arc};
+
+use pack::{order::PackOrder, span::PackAccess, vec::PackVec};
+
+use crate::{
+    block::{BlockBox, BlockCoord, BlockId, BlockSize},
+    storage::{Decode, DecodeError, Encode, Reader},
+};
+
+use super::{
+    BlockStorage, BlockView, Chunk, ChunkStorage, StorageError, check_box,
+    entity::{BlockEntity, EntityMap, decode_entity_map, encode_entities},
+    iter::{block_all_eq, block_index_of_match, block_index_of_mismatch, block_runs},
+    layout::BlockLayout,
+    snapshot::ChunkSnapshot,
+};
+
+/// A write of a single value to some blocks.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BlockEdit {
+    Block {
+        offset: usize,
+        value: BlockId,
+    },
+
+    /// `len` blocks in storage order from `offset`.
+    Run {
+        offset: usize,
+        len: usize,
+        value: BlockId,
+    },
+
+    Fill {
+        region: BlockBox,
+        value: BlockId,
+    },
+}
+
+impl BlockEdit {
+    #[inline]
+    pub fn value(&self) -> BlockId {
+        match *self {
+            BlockEdit::Block { value, .. }
+            | BlockEdit::Run { value, .. }
+            | BlockEdit::Fill { value, .. } => value,
+        }
+    }
+
+    /// Boxes of storage of `size` the edit writes to.
+    pub fn boxes(&self, size: BlockSize) -> impl Iterator<Item = BlockBox> {
+        let (offset, len, fill) = match *self {
+            BlockEdit::Block { offset, .. } => (offset, 1, None),
+            BlockEdit::Run { offset, len, .. } => (offset, len, None),
+            BlockEdit::Fill { region, .. } => (0, 0, Some(region)),
+        };
+        return fill.into_iter().chain(run_boxes(size, offset, len));
+    }
+}
+
+/// Splits the run of `len` blocks from `offset` into as few boxes as possible
+/// while following storage order.
+fn run_boxes(size: BlockSize, mut offset: usize, mut len: usize) -> impl Iterator<Item = BlockBox> {
+    let (width, depth) = (size.width, size.depth);
+    return std::iter::from_fn(move || {
+        if len == 0 {
+            return None;
+        }
+        let min = BlockCoord {
+            x: offset % width,
+            y: offset / (width * depth),
+            z: offset / width % depth,
+        };
+        let size = if min.x == 0 && min.z == 0 && len >= width * depth {
+            BlockSize {
+                width,
+                height: len / (width * depth),
+                depth,
+            }
+        } else if min.x == 0 && len >= width {
+            BlockSize {
+                width,
+                height: 1,
+                depth: (len / width).min(depth - min.z),
+            }
+        } else {
+            BlockSize {
+                width: len.min(width - min.x),
+                height: 1,
+                depth: 1,
+            }
+        };
+        offset += size.volume();
+        len -= size.volume();
+        return Some(BlockBox::new(min, size));
+    });
+}
+
+/// Edits turning one version of a chunk into another, with the edits undoing
+/// them, created by [`Chunk::diff`].
+///
+/// Carries the block entities both versions have in the box around the edits.
+#[derive(Clone, Debug, Default)]
+pub struct ChunkPatch {
+    edits: Vec<BlockEdit>,
+    undo: Vec<BlockEdit>,
+    entities: EntityMap,
+    undo_entities: EntityMap,
+}
+
+impl ChunkPatch {
+    /// Edits in the order they apply.
+    #[inline]
+    pub fn edits(&self) -> &[BlockEdit] {
+        &self.edits
+    }
+
+    /// Entities around the edits after applying them, with their offsets.
+    pub fn entities(&self) -> impl ExactSizeIterator<Item = (usize, &BlockEntity)> {
+        self.entities
+            .iter()
+            .map(|(offset, entity)| (*offset, entity))
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.edits.is_empty()
+    }
+
+    /// The patch turning the new version back into the old one.
+    pub fn invert(self) -> Self {
+        Self {
+            edits: self.undo,
+            undo: self.edits,
+            entities: self.undo_entities,
+            undo_entities: self.entities,
+        }
+    }
+
+    /// Writes the edits and entities to `chunk`, leaving it untouched if any
+    /// edit is out of bounds.
+    ///
+    /// Entities of the old version around the edits are replaced by those of
+    /// the new version, unless the block there does not accept them.
+    pub fn apply<const W: usize, const H: usize, const D: usize>(
+        &self,
+        chunk: &mut Chunk<W, H, D>,
+    ) -> Result<(), StorageError> {
+        self.apply_blocks(chunk)?;
+        for offset in self.undo_entities.keys() {
+            chunk.remove_entity(*offset);
+        }
+        for (offset, entity) in &self.entities {
+            // Like writes, blocks of another type drop the entity.
+            let _ = chunk.insert_entity(*offset, entity.clone());
+        }
+        return Ok(());
+    }
+
+    /// Writes only the edits to `storage`, leaving it untouched if any is out of bounds.
+    pub fn apply_blocks<S: BlockStorage>(&self, storage: &mut S) -> Result<(), StorageError> {
+        let size = storage.size();
+        for edit in &self.edits {
+            edit.boxes(size)
+                .try_for_each(|region| check_box(region, size))?;
+        }
+        for edit in &self.edits {
+            for region in edit.boxes(size) {
+                storage.fill(region, edit.value())?;
+            }
+        }
+        return Ok(());
+    }
+}
+
+impl<const W: usize, const H: usize, const D: usize> Chunk<W, H, D> {
+    /// Edits turning the blocks and entities of this chunk into those of `other`.
+    ///
+    /// Storage with the same palette is compared packed to skip equal layers.
+    /// Entities of blocks that did not change are not compared.
+    pub fn diff(&self, other: &Self) -> ChunkPatch {
+        diff_chunks::<W, H, D>(
+            (&self.storage, self.entity_map()),
+            (&other.storage, other.entity_map()),
+        )
+    }
+}
+
+impl<const W: usize, const H: usize, const D: usize> ChunkSnapshot<W, H, D> {
+    /// Like [`Chunk::diff`], for changes between snapshots of a chunk.
+    ///
+    /// Buffers the snapshots still share are equal without comparing them.
+    pub fn diff(&self, other: &Self) -> ChunkPatch {
+        diff_chunks::<W, H, D>(
+            (self.storage(), self.entity_map()),
+            (other.storage(), other.entity_map()),
+        )
+    }
+}
+
+fn diff_chunks<const W: usize, const H: usize, const D: usize>(
+    (old_storage, old_entities): (&ChunkStorage<W, H, D>, &EntityMap),
+    (new_storage, new_entities): (&ChunkStorage<W, H, D>, &EntityMap),
+) -> ChunkPatch {
+    let Some((bounds, edits, undo)) = diff_storage(old_storage, new_storage) else {
+        return ChunkPatch::default();
+    };
+    return ChunkPatch {
+        edits,
+        undo,
+        entities: entities_in::<W, D>(new_entities, bounds),
+        undo_entities: entities_in::<W, D>(old_entities, bounds),
+    };
+}
+
+/// Clones of the `entities` of blocks in `region`.
+fn entities_in<const W: usize, const D: usize>(
+    entities: &EntityMap,
+    region: BlockBox,
+) -> EntityMap {
+    let offset = |c: BlockCoord| (c.y * D + c.z) * W + c.x;
+    let max = region.max();
+    let last = BlockCoord {
+        x: max.x - 1,
+        y: max.y - 1,
+        z: max.z - 1,
+    };
+    return entities
+        .range(offset(region.min)..=offset(last))
+        .filter(|(offset, _)| {
+            region.contains(BlockCoord {
+                x: *offset % W,
+                y: *offset / (W * D),
+                z: *offset / W % D,
+            })
+        })
+        .map(|(offset, entity)| (*offset, entity.clone()))
+        .collect();
+}
+
+/// Box around the changed blocks with the edits and undo edits, `None` if
+/// the blocks are equal.
+fn diff_storage<const W: usize, const H: usize, const D: usize>(
+    old_storage: &ChunkStorage<W, H, D>,
+    new_storage: &ChunkStorage<W, H, D>,
+) -> Option<(BlockBox, Vec<BlockEdit>, Vec<BlockEdit>)> {
+    let range = changed_range(old_storage, new_storage)?;
+
+    // Decode whole layers covering the range.
+    let layer = W * D;
+    let layers = range.start / layer..range.end.div_ceil(layer);
+    let region = BlockBox::new(
+        BlockCoord {
+            x: 0,
+            y: layers.start,
+            z: 0,
+        },
+        BlockSize {
+            width: W,
+            height: layers.len(),
+            depth: D,
+        },
+    );
+    let mut old = vec![BlockId::default(); region.volume()];
+    let mut new = vec![BlockId::default(); region.volume()];
+    old_storage
+        .get_slice(region, BlockCoord::default(), region.size, &mut old)
+        .unwrap();
+    new_storage
+        .get_slice(region, BlockCoord::default(), region.size, &mut new)
+        .unwrap();
+
+    let spans = changed_spans(&old, &new);
+    let bounds = span_bounds(&spans, region)?;
+    let base = layers.start * layer;
+    return Some((
+        bounds,
+        encode_edits(&new, &spans, base, bounds, region),
+        encode_edits(&old, &spans, base, bounds, region),
+    ));
+}
+
+/// Offsets that may differ between both storages, `None` if they are equal.
+fn changed_range<const W: usize, const H: usize, const D: usize>(
+    a: &ChunkStorage<W, H, D>,
+    b: &ChunkStorage<W, H, D>,
+) -> Option<Range<usize>> {
+    let all = 0..Chunk::<W, H, D>::VOLUME;
+    match (a, b) {
+        (ChunkStorage::Palette(a), ChunkStorage::Palette(b)) => {
+            if Arc::ptr_eq(a, b) {
+                return None;
+            }
+            // Packed slots only follow storage order in the linear layout.
+            let linear = a.layout() == BlockLayout::Linear && b.layout() == BlockLayout::Linear;
+            if a.entries() != b.entries() || !linear {
+                return Some(all);
+            }
+            return changed_packed(a.data(), b.data());
+        }
+        (ChunkStorage::Direct(a), ChunkStorage::Direct(b)) => {
+            if Arc::ptr_eq(a, b) {
+                return None;
+            }
+            if a.layout() != BlockLayout::Linear || b.layout() != BlockLayout::Linear {
+                return Some(all);
+            }
+            return changed_packed(a.data(), b.data());
+        }
+        (ChunkStorage::Octree(a), ChunkStorage::Octree(b)) if Arc::ptr_eq(a, b) => None,
+        _ => match (a.uniform_value(), b.uniform_value()) {
+            (Some(a), Some(b)) if a == b => None,
+            _ => Some(all),
+        },
+    }
+}
+
+/// Values covered by parts that differ, `None` if none do.
+fn changed_packed(a: &PackVec, b: &PackVec) -> Option<Range<usize>> {
+    if a.order().value_bits() != b.order().value_bits() {
+        return Some(0..a.len());
+    }
+    let (a_parts, b_parts) = (&a.as_slice()[..a.part_len()], &b.as_slice()[..b.part_len()]);
+    let first = a_parts.iter().zip(b_parts).position(|(a, b)| a != b)?;
+    let last = a_parts.iter().zip(b_parts).rposition(|(a, b)| a != b)?;
+    let per_part = a.order().values_per_part().get();
+    return Some(first * per_part..((last + 1) * per_part).min(a.len()));
+}
+
+/// Ranges of indices where `old` and `new` differ.
+fn changed_spans(old: &[BlockId], new: &[BlockId]) -> Vec<Range<usize>> {
+    let mut spans = Vec::new();
+    let mut start = 0;
+    while let Some(skip) = block_index_of_mismatch(&old[start..], &new[start..]) {
+        let begin = start + skip;
+        let len = block_index_of_match(&old[begin..], &new[begin..]).unwrap_or(old.len() - begin);
+        spans.push(begin..begin + len);
+        start = begin + len;
+    }
+    return spans;
+}
+
+/// Box around every changed block, relative to the storage.
+fn span_bounds(spans: &[Range<usize>], region: BlockBox) -> Option<BlockBox> {
+    let (width, depth) = (region.size.width, region.size.depth);
+    let coord = |i: usize| BlockCoord {
+        x: i % width,
+        y: region.min.y + i / (width * depth),
+        z: i / width % depth,
+    };
+    let (mut min, mut max) = (coord(spans.first()?.start), BlockCoord::default());
+    for span in spans {
+        // Spans crossing rows cover the whole width, and layers the whole depth.
+        let (first, last) = (coord(span.start), coord(span.end - 1));
+        let (x0, x1) = match first.y == last.y && first.z == last.z {
+            true => (first.x, last.x),
+            false => (0, width - 1),
+        };
+        let (z0, z1) = match first.y == last.y {
+            true => (first.z, last.z),
+            false => (0, depth - 1),
+        };
+        min = BlockCoord {
+            x: min.x.min(x0),
+            y: min.y.min(first.y),
+            z: min.z.min(z0),
+        };
+        max = BlockCoord {
+            x: max.x.max(x1 + 1),
+            y: max.y.max(last.y + 1),
+            z: max.z.max(z1 + 1),
+        };
+    }
+    return Some(BlockBox::from_corners(min, max));
+}
+
+/// Edits writing the `values` of the changed `spans`, a single fill if the
+/// `bounds` of every change hold the same value.
+fn encode_edits(
+    values: &[BlockId],
+    spans: &[Range<usize>],
+    base: usize,
+    bounds: BlockBox,
+    region: BlockBox,
+) -> Vec<BlockEdit> {
+    let row = |y: usize, z: usize| {
+        let start = ((y - region.min.y) * region.size.depth + z) * region.size.width + bounds.min.x;
+        &values[start..start + bounds.size.width]
+    };
+    let first = row(bounds.min.y, bounds.min.z)[0];
+    let uniform = (bounds.min.y..bounds.max().y)
+        .all(|y| (bounds.min.z..bounds.max().z).all(|z| block_all_eq(row(y, z), first)));
+    if uniform && bounds.volume() > 1 {
+        return vec![BlockEdit::Fill {
+            region: bounds,
+            value: first,
+        }];
+    }
+
+    let mut edits = Vec::new();
+    for span in spans {
+        block_runs(&values[span.clone()], |value, start, len| {
+            let offset = base + span.start + start;
+            edits.push(match len {
+                1 => BlockEdit::Block { offset, value },
+                _ => BlockEdit::Run { offset, len, value },
+            });
+        });
+    }
+    return edits;
+}
+
+const TAG_BLOCK: u8 = 0;
+const TAG_RUN: u8 = 1;
+const TAG_FILL: u8 = 2;
+
+/// Writes a tag, then the offset and length as `u32`, or the box as six `u16`,
+/// then the value.
+impl Encode for BlockEdit {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match *self {
+            BlockEdit::Block { offset, .. } => {
+                out.push(TAG_BLOCK);
+                out.extend_from_slice(&(offset as u32).to_le_bytes());
+            }
+            BlockEdit::Run { offset, len, .. } => {
+                out.push(TAG_RUN);
+                out.extend_from_slice(&(offset as u32).to_le_bytes());
+                out.extend_from_slice(&(len as u32).to_le_bytes());
+            }
+            BlockEdit::Fill { region, .. } => {
+                out.push(TAG_FILL);
+                let (min, size) = (region.min, region.size);
+                for v in [min.x, min.y, min.z, size.width, size.height, size.depth] {
+                    out.extend_from_slice(&(v as u16).to_le_bytes());
+                }
+            }
+        }
+        out.extend_from_slice(&self.value().0.to_le_bytes());
+    }
+}
+
+impl Decode for BlockEdit {
+    fn decode(input: &mut Reader<'_>) -> Result<Self, DecodeError> {
+        let edit = match input.read_u8()? {
+            TAG_BLOCK => BlockEdit::Block {
+                offset: input.read_u32()? as usize,
+                value: BlockId(input.read_u32()?),
+            },
+            TAG_RUN => BlockEdit::Run {
+                offset: input.read_u32()? as usize,
+                len: input.read_u32()? as usize,
+                value: BlockId(input.read_u32()?),
+            },
+            TAG_FILL => {
+                let mut v = [0; 6];
+                for v in &mut v {
+                    *v = input.read_u16()? as usize;
+                }
+                let [x, y, z, width, height, depth] = v;
+                BlockEdit::Fill {
+                    region: BlockBox::new(
+                        BlockCoord { x, y, z },
+                        BlockSize {
+                            width,
+                            height,
+                            depth,
+                        },
+                    ),
+                    value: BlockId(input.read_u32()?),
+                }
+            }
+            tag => return Err(DecodeError::InvalidTag("block edit", tag)),
+        };
+        return Ok(edit);
+    }
+}
+
+/// Writes the edit count as `u32` then the edits, for both directions, then
+/// the entities of both directions like a chunk does.
+impl Encode for ChunkPatch {
+    fn encode(&self, out: &mut Vec<u8>) {
+        for edits in [&self.edits, &self.undo] {
+            out.extend_from_slice(&(edits.len() as u32).to_le_bytes());
+            for edit in edits {
+                edit.encode(out);
+            }
+        }
+        encode_entities(&self.entities, out);
+        encode_entities(&self.undo_entities, out);
+    }
+}
+
+impl Decode for ChunkPatch {
+    fn decode(input: &mut Reader<'_>) -> Result<Self, DecodeError> {
+        let mut decode_edits = || {
+            let count = input.read_u32()?;
+            return (0..count)
+                .map(|_| BlockEdit::decode(input))
+                .collect::<Result<Vec<_>, _>>();
+        };
+        let edits = decode_edits()?;
+        let undo = decode_edits()?;
+        return Ok(Self {
+            edits,
+            undo,
+            entities: decode_entity_map(input)?,
+            undo_entities: decode_entity_map(input)?,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        block::registry::{BlockProperties, BlockRegistry},
+        chunk::{Chunk16, entity::UndecodedData},
+        storage::{decode_from_slice, encode_to_vec},
+    };
+
+    fn terrain() -> Chunk16 {
+        let mut chunk = Chunk16::empty();
+        for (x, z) in (0..16).flat_map(|x| (0..16).map(move |z| (x, z))) {
+            let top = BlockBox::new(
+                BlockCoord { x, y: 0, z },
+                BlockSize {
+                    width: 1,
+                    height: 4 + (x + z) % 5,
+                    depth: 1,
+                },
+            );
+            chunk.fill(top, BlockId(1 + (x % 3) as u32)).unwrap();
+        }
+        return chunk;
+    }
+
+    fn round_trip(old: &Chunk16, new: &Chunk16) -> ChunkPatch {
+        let patch = old.diff(new);
+        let bytes = encode_to_vec(&patch);
+        let decoded: ChunkPatch = decode_from_slice(&bytes).unwrap();
+        assert_eq!(decoded.edits(), patch.edits());
+        assert_eq!(encode_to_vec(&decoded), bytes);
+
+        let mut chunk = Chunk16::from_blocks(&old.to_blocks()).unwrap();
+        patch.apply(&mut chunk).unwrap();
+        assert_eq!(chunk.to_blocks(), new.to_blocks());
+        patch.clone().invert().apply(&mut chunk).unwrap();
+        assert_eq!(chunk.to_blocks(), old.to_blocks());
+        return patch;
+    }
+
+    #[test]
+    fn diff_apply_invert() {
+        let old = terrain();
+        assert!(
+            old.diff(&Chunk16::from_blocks(&old.to_blocks()).unwrap())
+                .is_empty()
+        );
+
+        // A box of a single value fills over whatever was there.
+        let mut new = Chunk16::from_blocks(&old.to_blocks()).unwrap();
+        let wall = BlockBox::new(
+            BlockCoord { x: 2, y: 1, z: 3 },
+            BlockSize {
+                width: 5,
+                height: 9,
+                depth: 2,
+            },
+        );
+        new.fill(wall, BlockId(9)).unwrap();
+        let patch = round_trip(&old, &new);
+        assert_eq!(
+            patch.edits(),
+            [BlockEdit::Fill {
+                region: wall,
+                value: BlockId(9)
+            }]
+        );
+
+        // Mixed writes become blocks and runs.
+        new.set_at(new.get_offset(15, 15, 15), BlockId(4));
+        new.set_at(new.get_offset(0, 12, 7), BlockId(5));
+        let row = BlockBox::new(
+            BlockCoord { x: 0, y: 14, z: 0 },
+            BlockSize {
+                width: 16,
+                height: 1,
+                depth: 3,
+            },
+        );
+        new.fill(row, BlockId(6)).unwrap();
+        let patch = round_trip(&old, &new);
+        assert!(patch.edits().contains(&BlockEdit::Run {
+            offset: new.get_offset(0, 14, 0),
+            len: 48,
+            value: BlockId(6)
+        }));
+        assert!(patch.edits().contains(&BlockEdit::Block {
+            offset: new.get_offset(15, 15, 15),
+            value: BlockId(4)
+        }));
+
+        // Out of bounds edits leave storage untouched.
+        let mut small = Chunk::<4, 4, 4>::empty();
+        assert!(patch.apply(&mut small).is_err());
+        assert_eq!(small.uniform_value(), Some(BlockId::AIR));
+
+        // Snapshots compare what they still share without decoding.
+        let snapshot = new.snapshot();
+        assert!(snapshot.diff(&new.snapshot()).is_empty());
+        new.set_at(0, BlockId(7));
+        let since = snapshot.diff(&new.snapshot());
+        let undo = BlockEdit::Block {
+            offset: 0,
+            value: BlockId(1),
+        };
+        assert_eq!(since.invert().edits(), [undo]);
+
+        // Morton storage is compared in storage order, not slot order.
+        let mut old = Chunk16::from_blocks(&terrain().to_blocks()).unwrap();
+        old.set_layout(BlockLayout::Morton).unwrap();
+        let mut new = Chunk16::from_blocks(&old.to_blocks()).unwrap();
+        new.set_layout(BlockLayout::Morton).unwrap();
+        assert!(old.diff(&new).is_empty());
+        new.set_at(new.get_offset(15, 0, 0), BlockId(2));
+        let patch = round_trip(&old, &new);
+        assert_eq!(
+            patch.edits(),
+            [BlockEdit::Block {
+                offset: 15,
+                value: BlockId(2)
+            }]
+        );
+    }
+
+    #[test]
+    fn diff_entities() {
+        let mut registry = BlockRegistry::new();
+        let data = BlockProperties {
+            has_data: true,
+            ..BlockProperties::SOLID
+        };
+        let chest = registry.register("core:chest", data).unwrap();
+        let stone = registry
+            .register("core:stone", BlockProperties::SOLID)
+            .unwrap();
+        let new_entity = |bytes: &[u8]| {
+            let data = UndecodedData {
+                type_path: "core::Chest".into(),
+                bytes: bytes.to_vec(),
+            };
+            return BlockEntity::new(registry.state(chest).unwrap(), data).unwrap();
+        };
+        let bytes = |chunk: &Chunk16, offset: usize| {
+            let data = chunk.entity(offset)?.downcast_ref::<UndecodedData>()?;
+            return Some(data.bytes.clone());
+        };
+
+        let mut old = Chunk16::empty();
+        let (at, kept) = (old.get_offset(3, 4, 5), old.get_offset(4, 4, 5));
+        old.set_at(at, chest);
+        old.set_at(kept, chest);
+        old.insert_entity(at, new_entity(b"old")).unwrap();
+        old.insert_entity(kept, new_entity(b"kept")).unwrap();
+
+        // Replacing the chest drops its entity, undoing the edit restores it.
+        let mut new = Chunk16::from_blocks(&old.to_blocks()).unwrap();
+        new.insert_entity(kept, new_entity(b"kept")).unwrap();
+        new.set_at(at, stone);
+        let patch = old.diff(&new);
+        let decoded: ChunkPatch = decode_from_slice(&encode_to_vec(&patch)).unwrap();
+        let mut chunk = Chunk16::from_blocks(&new.to_blocks()).unwrap();
+        chunk.insert_entity(kept, new_entity(b"kept")).unwrap();
+        decoded.clone().invert().apply(&mut chunk).unwrap();
+        assert_eq!(chunk.to_blocks(), old.to_blocks());
+        assert_eq!(bytes(&chunk, at).as_deref(), Some(&b"old"[..]));
+        assert_eq!(bytes(&chunk, kept).as_deref(), Some(&b"kept"[..]));
+        decoded.apply(&mut chunk).unwrap();
+        assert!(chunk.entity(at).is_none());
+        assert_eq!(chunk.entities().len(), 1);
+
+        // A new chest comes with its entity, and the chest between the edits
+        // keeps its own.
+        let added = new.get_offset(5, 4, 5);
+        new.set_at(added, chest);
+        new.insert_entity(added, new_entity(b"new")).unwrap();
+        let patch = old.snapshot().diff(&new.snapshot());
+        assert_eq!(patch.entities().len(), 2);
+        patch.apply(&mut chunk).unwrap();
+        assert_eq!(bytes(&chunk, added).as_deref(), Some(&b"new"[..]));
+        assert_eq!(bytes(&chunk, kept).as_deref(), Some(&b"kept"[..]));
+        patch.invert().apply(&mut chunk).unwrap();
+        assert_eq!(bytes(&chunk, at).as_deref(), Some(&b"old"[..]));
+        assert!(chunk.entity(added).is_none());
+        assert_eq!(chunk.entities().len(), 2);
+
+        // Storages without entities only take the blocks.
+        let mut storage = ChunkStorage::<16, 16, 16>::Empty;
+        old.diff(&new).apply_blocks(&mut storage).unwrap();
+        assert_eq!(storage.get_at(added), Some(chest));
+    }
+}
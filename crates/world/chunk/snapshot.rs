@@ -0,0 +1,172 @@
+use std::{num::NonZeroUsize, sync::Arc};
+
+use crate::{
+    biome::ChunkBiomes,
+    block::{BlockCoord, BlockId, BlockSize},
+    storage::Encode,
+};
+
+use super::{
+    BlockView, Chunk, ChunkStorage, encode_storage,
+    entity::{BlockEntity, EntityMap, NO_ENTITIES, encode_entities},
+};
+
+/// Blocks, biomes and block entities of a chunk at the time of [`Chunk::snapshot`],
+/// for meshing, saving and sending off the main thread.
+///
+/// Shares block, biome and entity buffers with the chunk until it writes to them.
+/// Snapshots are read-only, see [`BlockView`], and encode like the chunk they
+/// were taken of.
+#[derive(Clone, Debug)]
+pub struct ChunkSnapshot<const W: usize = 16, const H: usize = 16, const D: usize = 16> {
+    storage: ChunkStorage<W, H, D>,
+    biomes: ChunkBiomes<W, H, D>,
+    entities: Option<Arc<EntityMap>>,
+}
+
+impl<const W: usize, const H: usize, const D: usize> ChunkSnapshot<W, H, D> {
+    #[inline]
+    pub fn storage(&self) -> &ChunkStorage<W, H, D> {
+        &self.storage
+    }
+
+    #[inline]
+    pub fn biomes(&self) -> &ChunkBiomes<W, H, D> {
+        &self.biomes
+    }
+
+    #[inline]
+    pub(super) fn entity_map(&self) -> &EntityMap {
+        self.entities.as_deref().unwrap_or(&NO_ENTITIES)
+    }
+
+    /// Entities with their offsets, in storage order.
+    pub fn entities(&self) -> impl ExactSizeIterator<Item = (usize, &BlockEntity)> {
+        self.entity_map()
+            .iter()
+            .map(|(offset, entity)| (*offset, entity))
+    }
+}
+
+impl<const W: usize, const H: usize, const D: usize> Chunk<W, H, D> {
+    /// A view of the current blocks, biomes and entities, sharing buffers with the chunk.
+    ///
+    /// The chunk copies a shared buffer on its next write to it.
+    pub fn snapshot(&self) -> ChunkSnapshot<W, H, D> {
+        ChunkSnapshot {
+            storage: self.storage.clone(),
+            biomes: self.biomes.clone(),
+            entities: self.entities.clone(),
+        }
+    }
+}
+
+/// Writes the same format as [`Chunk`], decoding back into a chunk.
+impl<const W: usize, const H: usize, const D: usize> Encode for ChunkSnapshot<W, H, D> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        encode_storage(&self.storage, out);
+        self.biomes.encode(out);
+        encode_entities(self.entity_map(), out);
+    }
+}
+
+impl<const W: usize, const H: usize, const D: usize> BlockView for ChunkSnapshot<W, H, D> {
+    #[inline]
+    fn width(&self) -> NonZeroUsize {
+        Chunk::<W, H, D>::WIDTH
+    }
+
+    #[inline]
+    fn height(&self) -> NonZeroUsize {
+        Chunk::<W, H, D>::HEIGHT
+    }
+
+    #[inline]
+    fn depth(&self) -> NonZeroUsize {
+        Chunk::<W, H, D>::DEPTH
+    }
+
+    #[inline]
+    fn get_at(&self, offset: usize) -> Option<BlockId> {
+        self.storage.get_at(offset)
+    }
+
+    #[inline]
+    fn uniform_value(&self) -> Option<BlockId> {
+        self.storage.uniform_value()
+    }
+
+    #[inline]
+    fn get_slice_core(
+        &self,
+        offset: BlockCoord,
+        size: BlockSize,
+        dst_offset: BlockCoord,
+        dst_bounds: BlockSize,
+        dst: &mut [BlockId],
+    ) {
+        self.storage
+            .get_slice_core(offset, size, dst_offset, dst_bounds, dst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        block::BlockBox,
+        chunk::{BlockStorage, Chunk16},
+        storage::{decode_from_slice, encode_to_vec},
+    };
+
+    fn shares(chunk: &Chunk16, snapshot: &ChunkSnapshot) -> bool {
+        match (chunk.storage(), snapshot.storage()) {
+            (ChunkStorage::Palette(a), ChunkStorage::Palette(b)) => Arc::ptr_eq(a, b),
+            (ChunkStorage::Direct(a), ChunkStorage::Direct(b)) => Arc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+
+    fn assert_send<T: Send + Sync>(_: &T) {}
+
+    #[test]
+    fn copy_on_write() {
+        let mut chunk = Chunk16::empty();
+        let floor = BlockBox::from_size(BlockSize {
+            width: 16,
+            height: 2,
+            depth: 16,
+        });
+        chunk.fill(floor, BlockId(3)).unwrap();
+        chunk.set_at(chunk.get_offset(4, 5, 6), BlockId(7));
+        let before = chunk.to_blocks();
+
+        let snapshot = chunk.snapshot();
+        assert_send(&snapshot);
+        assert!(shares(&chunk, &snapshot));
+        assert!(snapshot.runs().eq(chunk.runs()));
+
+        // Reads and writes that change nothing keep sharing.
+        assert_eq!(
+            chunk.set_at(chunk.get_offset(4, 5, 6), BlockId(7)),
+            Some(false)
+        );
+        chunk.optimize();
+        assert!(shares(&chunk, &snapshot));
+
+        chunk.set_at(chunk.get_offset(0, 15, 0), BlockId(9));
+        assert!(!shares(&chunk, &snapshot));
+        assert_eq!(chunk.get_at(chunk.get_offset(0, 15, 0)), Some(BlockId(9)));
+        let mut blocks = vec![BlockId::default(); Chunk16::VOLUME];
+        let size = snapshot.size();
+        snapshot
+            .get_slice(snapshot.bounds(), BlockCoord::default(), size, &mut blocks)
+            .unwrap();
+        assert_eq!(blocks, before);
+
+        let decoded: Chunk16 = decode_from_slice(&encode_to_vec(&snapshot)).unwrap();
+        assert_eq!(decoded.to_blocks(), before);
+    }
+}
@@ -0,0 +1,271 @@
+use std::collections::BTreeSet;
+
+use collections::CoordMap;
+
+use crate::{
+    block::{BlockBox, BlockCoord, BlockId, registry::BlockRegistry},
+    chunk::BlockView,
+    pos::{BlockPos, ChunkPos},
+};
+
+use super::{CHUNK_DEPTH, CHUNK_HEIGHT, CHUNK_WIDTH, ChunkMap, LoadedChunk, chunk_origin, split};
+
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq)]
+pub enum HeightmapKind {
+    /// Any block other than air.
+    NonAir,
+
+    /// Blocks entities collide with, see [`crate::block::registry::BlockProperties::solid`].
+    MotionBlocking,
+
+    /// Blocks that stop light, see [`crate::block::registry::BlockProperties::opaque`].
+    Opaque,
+}
+
+impl HeightmapKind {
+    pub const ALL: [HeightmapKind; 3] = [
+        HeightmapKind::NonAir,
+        HeightmapKind::MotionBlocking,
+        HeightmapKind::Opaque,
+    ];
+
+    #[inline]
+    const fn bit(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+/// Heightmap kinds every registered block counts for, indexed by id.
+#[derive(Clone, Debug)]
+pub struct HeightmapTable {
+    kinds: Vec<u8>,
+}
+
+impl HeightmapTable {
+    pub fn new(registry: &BlockRegistry) -> Self {
+        let ids = (0..registry.len() as u32).map(BlockId);
+        let kinds = ids.map(|id| {
+            let properties = registry.properties(id).unwrap();
+            let mut kinds = 0;
+            if id != BlockId::AIR {
+                kinds |= HeightmapKind::NonAir.bit();
+            }
+            if properties.solid {
+                kinds |= HeightmapKind::MotionBlocking.bit();
+            }
+            if properties.opaque {
+                kinds |= HeightmapKind::Opaque.bit();
+            }
+            return kinds;
+        });
+        return Self {
+            kinds: kinds.collect(),
+        };
+    }
+
+    /// Whether `block` counts for `kind`. Unknown blocks count for every kind but air.
+    #[inline]
+    pub fn matches(&self, kind: HeightmapKind, block: BlockId) -> bool {
+        match self.kinds.get(block.0 as usize) {
+            Some(kinds) => kinds & kind.bit() != 0,
+            None => block != BlockId::AIR,
+        }
+    }
+}
+
+/// Loaded chunks stacked at one chunk X and Z, with the heights of their blocks.
+#[derive(Debug)]
+pub(super) struct Column {
+    /// Chunk Y of every loaded chunk.
+    pub(super) chunks: BTreeSet<i32>,
+    /// Highest block of each kind per block X and Z, in `x + z * width` order.
+    heights: [Vec<Option<i32>>; 3],
+}
+
+impl Column {
+    pub(super) fn new() -> Self {
+        let heights = vec![None; CHUNK_WIDTH * CHUNK_DEPTH];
+        return Self {
+            chunks: BTreeSet::new(),
+            heights: [heights.clone(), heights.clone(), heights],
+        };
+    }
+
+    #[inline]
+    fn height(&self, kind: HeightmapKind, x: usize, z: usize) -> Option<i32> {
+        self.heights[kind as usize][x + z * CHUNK_WIDTH]
+    }
+}
+
+impl ChunkMap {
+    /// Y of the highest loaded block of `kind` at world X and Z, `None` if there is none.
+    pub fn height(&self, kind: HeightmapKind, x: i32, z: i32) -> Option<i32> {
+        let (chunk, local) = split(BlockPos::new(x, 0, z));
+        let column = self.columns.get(&(chunk.x, chunk.z))?;
+        return column.height(kind, local.x, local.z);
+    }
+
+    /// Updates the heights of the column crossing `region` of the chunk at
+    /// `chunk`, after its blocks changed.
+    ///
+    /// Only scans down from the top of the region where the highest block was
+    /// not above it, so at most once through the loaded height of the column.
+    pub(super) fn update_heights(&mut self, chunk: ChunkPos, region: BlockBox) {
+        if region.is_empty() {
+            return;
+        }
+        let Some(column) = self.columns.get_mut(&(chunk.x, chunk.z)) else {
+            return;
+        };
+
+        let top = chunk_origin(chunk).y + (region.max().y - 1) as i32;
+        for z in region.min.z..region.max().z {
+            for x in region.min.x..region.max().x {
+                for kind in HeightmapKind::ALL {
+                    let height = column.heights[kind as usize][x + z * CHUNK_WIDTH];
+                    if height.is_some_and(|height| height > top) {
+                        continue;
+                    }
+                    let scan = Scan {
+                        chunks: &self.chunks,
+                        table: &self.table,
+                        chunk_ys: &column.chunks,
+                    };
+                    let height = scan.down(kind, chunk, x, z, top);
+                    column.heights[kind as usize][x + z * CHUNK_WIDTH] = height;
+                }
+            }
+        }
+    }
+}
+
+/// Blocks of one column, borrowed apart from its heights.
+struct Scan<'a> {
+    chunks: &'a CoordMap<ChunkPos, LoadedChunk>,
+    table: &'a HeightmapTable,
+    chunk_ys: &'a BTreeSet<i32>,
+}
+
+impl Scan<'_> {
+    /// Y of the highest block of `kind` at or below world Y `from`, at `x` and
+    /// `z` within the column of `chunk`.
+    fn down(
+        &self,
+        kind: HeightmapKind,
+        chunk: ChunkPos,
+        x: usize,
+        z: usize,
+        from: i32,
+    ) -> Option<i32> {
+        let chunk_height = CHUNK_HEIGHT as i32;
+        let from_chunk = from.div_euclid(chunk_height);
+        for chunk_y in self.chunk_ys.range(..=from_chunk).rev() {
+            let Some(loaded) = self.chunks.get(ChunkPos::new(chunk.x, *chunk_y, chunk.z)) else {
+                continue;
+            };
+            let blocks = &loaded.blocks;
+            if blocks
+                .uniform_value()
+                .is_some_and(|block| !self.table.matches(kind, block))
+            {
+                continue;
+            }
+            let start = match *chunk_y == from_chunk {
+                true => from.rem_euclid(chunk_height) as usize,
+                false => CHUNK_HEIGHT - 1,
+            };
+            for y in (0..=start).rev() {
+                let block = blocks.get_at(blocks.get_coord_offset(BlockCoord { x, y, z }));
+                if block.is_some_and(|block| self.table.matches(kind, block)) {
+                    return Some(chunk_y * chunk_height + y as i32);
+                }
+            }
+        }
+        return None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        block::{BlockSize, registry::BlockProperties},
+        chunk::{BlockStorage, Chunk16},
+    };
+
+    #[test]
+    fn heights() {
+        let mut registry = BlockRegistry::new();
+        let stone = registry
+            .register("core:stone", BlockProperties::SOLID)
+            .unwrap();
+        let glass = BlockProperties {
+            opaque: false,
+            ..BlockProperties::SOLID
+        };
+        let glass = registry.register("core:glass", glass).unwrap();
+        let flower = registry
+            .register("core:flower", BlockProperties::AIR)
+            .unwrap();
+
+        let mut map = ChunkMap::new(&registry);
+        let mut ground = Chunk16::empty();
+        let bottom = BlockBox::from_size(BlockSize {
+            width: 16,
+            height: 4,
+            depth: 16,
+        });
+        ground.fill(bottom, stone).unwrap();
+        map.insert(ChunkPos::new(0, 0, 0), ground);
+        map.insert(ChunkPos::new(0, 1, 0), Chunk16::empty());
+        let heights = |map: &ChunkMap, x, z| HeightmapKind::ALL.map(|kind| map.height(kind, x, z));
+        assert_eq!(heights(&map, 3, 3), [Some(3); 3]);
+        assert_eq!(heights(&map, -1, 3), [None; 3]);
+
+        map.set_block(BlockPos::new(3, 20, 3), glass);
+        map.set_block(BlockPos::new(3, 21, 3), flower);
+        assert_eq!(heights(&map, 3, 3), [Some(21), Some(20), Some(3)]);
+        map.set_block(BlockPos::new(3, 21, 3), BlockId::AIR);
+        map.set_block(BlockPos::new(3, 20, 3), BlockId::AIR);
+        assert_eq!(heights(&map, 3, 3), [Some(3); 3]);
+
+        // Blocks outside loaded chunks are skipped.
+        map.fill(BlockPos::new(-2, 30, 0), BlockSize::splat(4), stone);
+        assert_eq!(heights(&map, -1, 0), [None; 3]);
+        assert_eq!(heights(&map, 1, 3), [Some(31); 3]);
+        assert_eq!(heights(&map, 2, 3), [Some(3); 3]);
+
+        map.remove(ChunkPos::new(0, 1, 0));
+        assert_eq!(heights(&map, 1, 3), [Some(3); 3]);
+        map.insert(ChunkPos::new(0, -1, 0), Chunk16::filled(stone));
+        map.remove(ChunkPos::new(0, 0, 0));
+        assert_eq!(heights(&map, 1, 3), [Some(-1); 3]);
+
+        // Writes through a borrowed chunk update the heights once it is dropped.
+        let below = ChunkPos::new(0, -1, 0);
+        let mut chunk = map.get_mut(below).unwrap();
+        let top = BlockBox::new(
+            BlockCoord { x: 0, y: 8, z: 0 },
+            BlockSize {
+                width: 16,
+                height: 8,
+                depth: 16,
+            },
+        );
+        chunk.blocks_mut().fill(top, BlockId::AIR).unwrap();
+        drop(chunk);
+        assert_eq!(heights(&map, 1, 3), [Some(-9); 3]);
+        let mut chunk = map.get_mut(below).unwrap();
+        let at = chunk.blocks.get_offset(0, 12, 0);
+        chunk.blocks_mut().set_at(at, glass);
+        drop(chunk);
+        assert_eq!(heights(&map, 0, 0), [Some(-4), Some(-4), Some(-9)]);
+        assert!(map.get(below).unwrap().blocks.changes().is_none());
+        map.get_mut(below)
+            .unwrap()
+            .blocks_mut()
+            .fill(BlockBox::from_size(BlockSize::splat(16)), BlockId::AIR)
+            .unwrap();
+        assert_eq!(heights(&map, 1, 3), [None; 3]);
+    }
+}
@@ -1,4 +1,336 @@
+use std::{collections::HashMap, ops::Deref};
+
 use bevy::prelude::Component;
+use collections::CoordMap;
+use pack::part::PartSize;
+
+use crate::{
+    block::{BlockBox, BlockCoord, BlockId, BlockSize, registry::BlockRegistry},
+    chunk::{BlockStorage, BlockView, Chunk16, StorageError, check_box, check_buffer},
+    light::{ChunkLight, LightKind},
+    pos::{BlockPos, ChunkPos},
+};
+
+use self::heightmap::{Column, HeightmapTable};
+
+pub mod heightmap;
+
+#[derive(Component, Debug)]
+pub struct Dimension {
+    pub chunks: ChunkMap,
+}
+
+impl Dimension {
+    pub fn new(registry: &BlockRegistry) -> Self {
+        Self {
+            chunks: ChunkMap::new(registry),
+        }
+    }
+}
+
+/// Chunks loaded in a [`ChunkMap`].
+pub type MapChunk = Chunk16;
+
+pub(crate) const CHUNK_WIDTH: usize = MapChunk::WIDTH.get();
+pub(crate) const CHUNK_HEIGHT: usize = MapChunk::HEIGHT.get();
+pub(crate) const CHUNK_DEPTH: usize = MapChunk::DEPTH.get();
+
+/// The chunk holding `pos` and its coordinates within, for [`MapChunk`].
+#[inline]
+fn split(pos: BlockPos) -> (ChunkPos, BlockCoord) {
+    pos.split_in::<CHUNK_WIDTH, CHUNK_HEIGHT, CHUNK_DEPTH>()
+}
+
+/// The lowest corner of the chunk at `pos`, for [`MapChunk`].
+#[inline]
+fn chunk_origin(pos: ChunkPos) -> BlockPos {
+    pos.origin_in::<CHUNK_WIDTH, CHUNK_HEIGHT, CHUNK_DEPTH>()
+}
+
+/// Blocks and light of a loaded chunk.
+#[derive(Debug, Default)]
+pub struct LoadedChunk {
+    pub blocks: MapChunk,
+    pub light: ChunkLight,
+}
+
+impl LoadedChunk {
+    pub fn new(blocks: MapChunk) -> Self {
+        Self {
+            blocks,
+            light: ChunkLight::new(),
+        }
+    }
+}
+
+/// Loaded chunks of a dimension, keyed by position.
+///
+/// Block positions outside loaded chunks read as `None` and ignore writes.
+/// Heightmaps are kept up to date by every write, see [`ChunkMut`].
+#[derive(Debug)]
+pub struct ChunkMap {
+    chunks: CoordMap<ChunkPos, LoadedChunk>,
+    table: HeightmapTable,
+    /// Heightmaps by chunk X and Z.
+    columns: HashMap<(i32, i32), Column>,
+    /// Bit width of direct storage of inserted chunks, see [`Chunk16::set_id_bits`].
+    id_bits: PartSize,
+}
+
+impl ChunkMap {
+    /// An empty map for blocks of the frozen `registry`.
+    pub fn new(registry: &BlockRegistry) -> Self {
+        Self {
+            chunks: CoordMap::default(),
+            table: HeightmapTable::new(registry),
+            columns: HashMap::new(),
+            id_bits: registry.id_bits(),
+        }
+    }
+
+    #[inline]
+    pub fn table(&self) -> &HeightmapTable {
+        &self.table
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.chunks.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    #[inline]
+    pub fn contains(&self, pos: ChunkPos) -> bool {
+        self.chunks.contains_key(pos)
+    }
+
+    #[inline]
+    pub fn get(&self, pos: ChunkPos) -> Option<&LoadedChunk> {
+        self.chunks.get(pos)
+    }
+
+    /// The chunk at `pos`, updating the heightmaps for writes to its blocks once dropped.
+    #[inline]
+    pub fn get_mut(&mut self, pos: ChunkPos) -> Option<ChunkMut<'_>> {
+        if !self.chunks.contains_key(pos) {
+            return None;
+        }
+        return Some(ChunkMut {
+            map: self,
+            pos,
+            blocks_borrowed: false,
+            tracking: false,
+        });
+    }
+
+    /// Loads `blocks` at `pos` without light, returning the chunk it replaced.
+    pub fn insert(&mut self, pos: ChunkPos, mut blocks: MapChunk) -> Option<LoadedChunk> {
+        blocks.set_id_bits(self.id_bits);
+        let bounds = blocks.bounds();
+        let prev = self.chunks.insert(pos, LoadedChunk::new(blocks));
+        let column = self
+            .columns
+            .entry((pos.x, pos.z))
+            .or_insert_with(Column::new);
+        column.chunks.insert(pos.y);
+        self.update_heights(pos, bounds);
+        return prev;
+    }
+
+    pub fn remove(&mut self, pos: ChunkPos) -> Option<LoadedChunk> {
+        let prev = self.chunks.remove(pos)?;
+        let column = self.columns.get_mut(&(pos.x, pos.z)).unwrap();
+        column.chunks.remove(&pos.y);
+        if column.chunks.is_empty() {
+            self.columns.remove(&(pos.x, pos.z));
+        } else {
+            self.update_heights(pos, prev.blocks.bounds());
+        }
+        return Some(prev);
+    }
+
+    pub fn iter(&self) -> impl ExactSizeIterator<Item = (ChunkPos, &LoadedChunk)> {
+        self.chunks.iter()
+    }
+
+    pub fn block(&self, pos: BlockPos) -> Option<BlockId> {
+        let (chunk, local) = split(pos);
+        let chunk = &self.chunks.get(chunk)?.blocks;
+        return chunk.get_at(chunk.get_coord_offset(local));
+    }
+
+    /// Sets the block at `pos` without updating light, returning the previous block.
+    ///
+    /// See [`crate::light::engine::LightEngine::set_block`] to keep light up to date.
+    pub fn set_block(&mut self, pos: BlockPos, value: BlockId) -> Option<BlockId> {
+        let (chunk_pos, local) = split(pos);
+        let chunk = &mut self.chunks.get_mut(chunk_pos)?.blocks;
+        let offset = chunk.get_coord_offset(local);
+        let prev = chunk.get_at(offset)?;
+        if chunk.set_at(offset, value)? {
+            self.update_heights(chunk_pos, BlockBox::new(local, BlockSize::splat(1)));
+        }
+        return Some(prev);
+    }
+
+    /// Fills the world-space box from `min` spanning `size` with `value`,
+    /// skipping blocks outside loaded chunks.
+    pub fn fill(&mut self, min: BlockPos, size: BlockSize, value: BlockId) {
+        for (pos, region, _) in chunk_regions(min, size) {
+            let Some(chunk) = self.chunks.get_mut(pos) else {
+                continue;
+            };
+            chunk.blocks.fill(region, value).unwrap();
+            self.update_heights(pos, region);
+        }
+    }
+
+    /// Copies `src`, blocks of the world-space box from `min` spanning `size`
+    /// in storage order, skipping blocks outside loaded chunks.
+    ///
+    /// Nothing is written unless every part of the box can be.
+    pub fn set_slice(
+        &mut self,
+        min: BlockPos,
+        size: BlockSize,
+        src: &[BlockId],
+    ) -> Result<(), StorageError> {
+        check_buffer(BlockBox::from_size(size), size, src.len())?;
+        let regions: Vec<_> = chunk_regions(min, size)
+            .filter(|(pos, _, _)| self.chunks.contains_key(*pos))
+            .collect();
+        for (pos, region, src_offset) in &regions {
+            check_box(*region, self.chunks.get(*pos).unwrap().blocks.size())?;
+            check_buffer(BlockBox::new(*src_offset, region.size), size, src.len())?;
+        }
+        for (pos, region, src_offset) in regions {
+            let chunk = self.chunks.get_mut(pos).unwrap();
+            chunk.blocks.set_slice(region, src_offset, size, src)?;
+            self.update_heights(pos, region);
+        }
+        return Ok(());
+    }
+
+    pub fn light(&self, kind: LightKind, pos: BlockPos) -> Option<u8> {
+        let (chunk, local) = split(pos);
+        let light = &self.chunks.get(chunk)?.light;
+        return light.get_at(kind, light.get_offset(local.x, local.y, local.z));
+    }
+
+    /// Sets the light level at `pos`, returning whether it changed.
+    pub fn set_light(&mut self, kind: LightKind, pos: BlockPos, level: u8) -> Option<bool> {
+        let (chunk, local) = split(pos);
+        let light = &mut self.chunks.get_mut(chunk)?.light;
+        let offset = light.get_offset(local.x, local.y, local.z);
+        return light.set_at(kind, offset, level);
+    }
+}
+
+/// A loaded chunk borrowed from a [`ChunkMap`], see [`ChunkMap::get_mut`].
+///
+/// Writes to the blocks are tracked while borrowed, and the heights of the
+/// blocks they changed updated once dropped.
+pub struct ChunkMut<'a> {
+    map: &'a mut ChunkMap,
+    pos: ChunkPos,
+    /// Whether the blocks were borrowed mutably.
+    blocks_borrowed: bool,
+    /// Whether tracking changes started with the borrow, to stop once dropped.
+    tracking: bool,
+}
+
+impl ChunkMut<'_> {
+    #[inline]
+    pub fn pos(&self) -> ChunkPos {
+        self.pos
+    }
+
+    pub fn blocks_mut(&mut self) -> &mut MapChunk {
+        let blocks = &mut self.map.chunks.get_mut(self.pos).unwrap().blocks;
+        if !self.blocks_borrowed {
+            self.blocks_borrowed = true;
+            self.tracking = blocks.changes().is_none();
+            blocks.track_changes();
+        }
+        return blocks;
+    }
+
+    #[inline]
+    pub fn light_mut(&mut self) -> &mut ChunkLight {
+        &mut self.map.chunks.get_mut(self.pos).unwrap().light
+    }
+}
+
+impl Deref for ChunkMut<'_> {
+    type Target = LoadedChunk;
+
+    #[inline]
+    fn deref(&self) -> &LoadedChunk {
+        self.map.chunks.get(self.pos).unwrap()
+    }
+}
+
+impl Drop for ChunkMut<'_> {
+    fn drop(&mut self) {
+        if !self.blocks_borrowed {
+            return;
+        }
+        let blocks = &mut self.map.chunks.get_mut(self.pos).unwrap().blocks;
+        // Changes tracked before the borrow are included, which only costs a rescan.
+        let changed = match self.tracking {
+            true => blocks.drain_changes().map(|changes| changes.bounds()),
+            false => blocks.changes().map(|changes| changes.bounds()),
+        };
+        let changed = changed.unwrap_or(blocks.bounds());
+        if self.tracking {
+            blocks.untrack_changes();
+        }
+        self.map.update_heights(self.pos, changed);
+    }
+}
 
-#[derive(Component)]
-pub struct Dimension {}
+/// Splits the world-space box from `min` spanning `size` by chunk, yielding the
+/// chunk, the part of the box within it and where that part starts in the box.
+fn chunk_regions(
+    min: BlockPos,
+    size: BlockSize,
+) -> impl Iterator<Item = (ChunkPos, BlockBox, BlockCoord)> {
+    let max = BlockPos::new(
+        min.x + size.width as i32,
+        min.y + size.height as i32,
+        min.z + size.depth as i32,
+    );
+    let (first, last) = match size.volume() {
+        0 => (ChunkPos::new(0, 0, 0), ChunkPos::new(-1, -1, -1)),
+        _ => (split(min).0, split(max - BlockPos::new(1, 1, 1)).0),
+    };
+    let ys = first.y..=last.y;
+    let zs = move |y| (first.z..=last.z).map(move |z| (y, z));
+    let xs = move |(y, z)| (first.x..=last.x).map(move |x| ChunkPos::new(x, y, z));
+    return ys.flat_map(zs).flat_map(xs).map(move |pos| {
+        let origin = chunk_origin(pos);
+        let lo = BlockPos::new(
+            min.x.max(origin.x),
+            min.y.max(origin.y),
+            min.z.max(origin.z),
+        );
+        let end = chunk_origin(pos + ChunkPos::new(1, 1, 1));
+        let hi = BlockPos::new(max.x.min(end.x), max.y.min(end.y), max.z.min(end.z));
+        let local = |p: BlockPos| BlockCoord {
+            x: (p.x - origin.x) as usize,
+            y: (p.y - origin.y) as usize,
+            z: (p.z - origin.z) as usize,
+        };
+        let region = BlockBox::from_corners(local(lo), local(hi));
+        let src_offset = BlockCoord {
+            x: (lo.x - min.x) as usize,
+            y: (lo.y - min.y) as usize,
+            z: (lo.z - min.z) as usize,
+        };
+        return (pos, region, src_offset);
+    });
+}
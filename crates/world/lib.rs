@@ -1,8 +1,11 @@
 #![feature(portable_simd)]
 #![feature(cold_path)]
 
+pub mod biome;
 pub mod block;
 pub mod chunk;
 pub mod dimension;
+pub mod light;
+pub mod pos;
 pub mod region;
 pub mod storage;
@@ -0,0 +1,467 @@
+use std::collections::{HashSet, VecDeque};
+
+use crate::{
+    block::{BlockId, registry::BlockRegistry},
+    chunk::BlockView,
+    dimension::ChunkMap,
+    pos::{BlockPos, ChunkPos, Face},
+};
+
+use super::{LightKind, MAX_LIGHT};
+
+/// Light emission and opacity of every registered block, indexed by id.
+#[derive(Clone, Debug, Default)]
+pub struct LightTable {
+    emission: Vec<u8>,
+    opacity: Vec<u8>,
+}
+
+impl LightTable {
+    pub fn new(registry: &BlockRegistry) -> Self {
+        // Every state of a block shares its properties.
+        let ids = (0..registry.len() as u32).map(BlockId);
+        let properties = ids.map(|id| registry.properties(id).unwrap());
+        return Self {
+            emission: properties.clone().map(|p| p.light_emission).collect(),
+            opacity: properties.map(|p| p.light_opacity).collect(),
+        };
+    }
+
+    #[inline]
+    pub fn emission(&self, id: BlockId) -> u8 {
+        self.emission.get(id.0 as usize).copied().unwrap_or(0)
+    }
+
+    /// Unknown blocks are fully opaque.
+    #[inline]
+    pub fn opacity(&self, id: BlockId) -> u8 {
+        self.opacity
+            .get(id.0 as usize)
+            .copied()
+            .unwrap_or(MAX_LIGHT)
+    }
+}
+
+/// Breadth-first light propagation over the chunks of a [`ChunkMap`].
+///
+/// Light crosses chunk borders freely but stops at unloaded chunks. Blocks at
+/// the top of a column of loaded chunks receive full sky light from above, and
+/// sky light at full strength travels down through transparent blocks without
+/// fading.
+#[derive(Debug)]
+pub struct LightEngine {
+    table: LightTable,
+    /// Blocks whose level rose, to spread to their neighbors.
+    queue: VecDeque<(BlockPos, u8)>,
+    /// Blocks cleared with the level they had, to clear their dependents.
+    removals: VecDeque<(BlockPos, u8)>,
+}
+
+impl LightEngine {
+    pub fn new(table: LightTable) -> Self {
+        Self {
+            table,
+            queue: VecDeque::new(),
+            removals: VecDeque::new(),
+        }
+    }
+
+    #[inline]
+    pub fn table(&self) -> &LightTable {
+        &self.table
+    }
+
+    /// Sets the block at `pos` and updates the light it affects, returning the
+    /// previous block.
+    ///
+    /// Returns `None` without changes if the chunk of `pos` is not loaded.
+    pub fn set_block(
+        &mut self,
+        map: &mut ChunkMap,
+        pos: BlockPos,
+        value: BlockId,
+    ) -> Option<BlockId> {
+        let prev = map.set_block(pos, value)?;
+        if prev != value {
+            self.relight_block(map, pos);
+        }
+        return Some(prev);
+    }
+
+    /// Updates light after the block at `pos` changed, given that the light
+    /// was up to date before the change.
+    pub fn relight_block(&mut self, map: &mut ChunkMap, pos: BlockPos) {
+        let Some(block) = map.block(pos) else {
+            return;
+        };
+
+        for kind in LightKind::ALL {
+            let level = map.light(kind, pos).unwrap();
+            if level > 0 {
+                map.set_light(kind, pos, 0);
+                self.removals.push_back((pos, level));
+                self.unpropagate(map, kind);
+            }
+
+            self.seed(map, kind, pos, block);
+            for next in pos.neighbors_6() {
+                if let Some(level @ 1..) = map.light(kind, next) {
+                    self.queue.push_back((next, level));
+                }
+            }
+            self.propagate(map, kind);
+        }
+    }
+
+    /// Recomputes all light of the loaded chunks at `chunks` from scratch,
+    /// e.g. after they were generated.
+    ///
+    /// Light already in neighboring chunks outside the batch flows in, and
+    /// light from the batch flows out into them.
+    pub fn relight_chunks(&mut self, map: &mut ChunkMap, chunks: &[ChunkPos]) {
+        let batch: HashSet<ChunkPos> = chunks
+            .iter()
+            .copied()
+            .filter(|pos| map.contains(*pos))
+            .collect();
+        // Faces of the batch next to loaded chunks outside of it.
+        let mut borders = Vec::new();
+        for &chunk_pos in &batch {
+            let bounds = map.get(chunk_pos).unwrap().blocks.bounds();
+            for (face, layer) in bounds.faces() {
+                let next = chunk_pos.offset(face);
+                if map.contains(next) && !batch.contains(&next) {
+                    borders.push((chunk_pos, face, layer));
+                }
+            }
+        }
+
+        for kind in LightKind::ALL {
+            // Clear light that spread out of the batch before resetting it.
+            for &(chunk_pos, _, layer) in &borders {
+                for local in layer {
+                    let pos = chunk_pos.block(local);
+                    if let Some(level @ 1..) = map.light(kind, pos) {
+                        self.removals.push_back((pos, level));
+                    }
+                }
+            }
+            for &chunk_pos in &batch {
+                let mut chunk = map.get_mut(chunk_pos).unwrap();
+                let light = chunk.light_mut();
+                light.fill(kind, light.bounds(), 0).unwrap();
+            }
+            self.unpropagate(map, kind);
+
+            for &chunk_pos in &batch {
+                let chunk = &map.get(chunk_pos).unwrap().blocks;
+                let sources: Vec<(BlockPos, BlockId)> = match kind {
+                    LightKind::Sky if !map.contains(chunk_pos.offset(Face::PosY)) => {
+                        let top = chunk.bounds().face(Face::PosY).unwrap();
+                        chunk
+                            .iter_box(top)
+                            .unwrap()
+                            .map(|(local, block)| (chunk_pos.block(local), block))
+                            .collect()
+                    }
+                    LightKind::Sky => Vec::new(),
+                    LightKind::Block => chunk
+                        .iter_non_default()
+                        .filter(|(_, block)| self.table.emission(*block) > 0)
+                        .map(|(local, block)| (chunk_pos.block(local), block))
+                        .collect(),
+                };
+                for (pos, block) in sources {
+                    self.seed(map, kind, pos, block);
+                }
+            }
+
+            // Light entering from outside the batch.
+            for &(chunk_pos, face, layer) in &borders {
+                for local in layer {
+                    let next = chunk_pos.block(local).offset(face);
+                    if let Some(level @ 1..) = map.light(kind, next) {
+                        self.queue.push_back((next, level));
+                    }
+                }
+            }
+            self.propagate(map, kind);
+        }
+    }
+
+    /// Light `pos` receives by itself, from emission or open sky above.
+    fn source_level(&self, map: &ChunkMap, kind: LightKind, pos: BlockPos, block: BlockId) -> u8 {
+        match kind {
+            LightKind::Block => self.table.emission(block),
+            LightKind::Sky => {
+                let above = pos.offset(Face::PosY);
+                match map.contains(above.chunk()) {
+                    true => 0,
+                    false => self.spread(kind, Face::NegY, MAX_LIGHT, block),
+                }
+            }
+        }
+    }
+
+    /// Raises `pos` to its source level and queues it, if it has one.
+    fn seed(&mut self, map: &mut ChunkMap, kind: LightKind, pos: BlockPos, block: BlockId) {
+        let level = self.source_level(map, kind, pos, block);
+        if level > 0 && map.light(kind, pos).is_some_and(|current| current < level) {
+            map.set_light(kind, pos, level);
+            self.queue.push_back((pos, level));
+        }
+    }
+
+    /// Level that light at `level` has after moving towards `face` into `block`.
+    #[inline]
+    fn spread(&self, kind: LightKind, face: Face, level: u8, block: BlockId) -> u8 {
+        let opacity = self.table.opacity(block);
+        if kind == LightKind::Sky && face == Face::NegY && level == MAX_LIGHT && opacity == 0 {
+            return MAX_LIGHT;
+        }
+        return level.saturating_sub(opacity.max(1));
+    }
+
+    fn propagate(&mut self, map: &mut ChunkMap, kind: LightKind) {
+        while let Some((pos, level)) = self.queue.pop_front() {
+            // Superseded by a later update of the same block.
+            if map.light(kind, pos) != Some(level) {
+                continue;
+            }
+
+            for face in Face::ALL {
+                let next = pos.offset(face);
+                let Some(block) = map.block(next) else {
+                    continue;
+                };
+                let new = self.spread(kind, face, level, block);
+                if new > map.light(kind, next).unwrap() {
+                    map.set_light(kind, next, new);
+                    self.queue.push_back((next, new));
+                }
+            }
+        }
+    }
+
+    /// Clears light that depended on the queued removals, and queues the
+    /// remaining light around the cleared area for [`Self::propagate`].
+    fn unpropagate(&mut self, map: &mut ChunkMap, kind: LightKind) {
+        while let Some((pos, level)) = self.removals.pop_front() {
+            for face in Face::ALL {
+                let next = pos.offset(face);
+                let Some(current @ 1..) = map.light(kind, next) else {
+                    continue;
+                };
+                let block = map.block(next).unwrap();
+
+                if current <= self.spread(kind, face, level, block) {
+                    map.set_light(kind, next, 0);
+                    self.removals.push_back((next, current));
+                    self.seed(map, kind, next, block);
+                } else {
+                    self.queue.push_back((next, current));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        block::{BlockBox, BlockCoord, BlockSize, registry::BlockProperties},
+        chunk::{BlockStorage, Chunk16},
+    };
+
+    /// Light levels of the blocks in `region` of the chunk at `chunk`, in storage order.
+    fn levels(map: &ChunkMap, kind: LightKind, chunk: ChunkPos, region: BlockBox) -> Vec<u8> {
+        region
+            .iter()
+            .map(|local| map.light(kind, chunk.block(local)).unwrap())
+            .collect()
+    }
+
+    struct Blocks {
+        table: LightTable,
+        /// An empty map for the blocks.
+        map: ChunkMap,
+        stone: BlockId,
+        torch: BlockId,
+    }
+
+    fn blocks() -> Blocks {
+        let mut registry = BlockRegistry::new();
+        let stone = registry
+            .register("core:stone", BlockProperties::SOLID)
+            .unwrap();
+        let torch = BlockProperties {
+            light_emission: 14,
+            ..BlockProperties::AIR
+        };
+        let torch = registry.register("core:torch", torch).unwrap();
+        return Blocks {
+            table: LightTable::new(&registry),
+            map: ChunkMap::new(&registry),
+            stone,
+            torch,
+        };
+    }
+
+    #[test]
+    fn torch_in_cave() {
+        let Blocks {
+            table,
+            mut map,
+            stone,
+            torch,
+        } = blocks();
+        let mut engine = LightEngine::new(table);
+
+        // Two chunks of stone side by side under a stone chunk, with a tunnel
+        // along X through both.
+        for x in [0, 1] {
+            let mut chunk = Chunk16::filled(stone);
+            let tunnel = BlockBox::new(
+                BlockCoord { x: 0, y: 4, z: 4 },
+                BlockSize {
+                    width: 16,
+                    height: 1,
+                    depth: 1,
+                },
+            );
+            chunk.fill(tunnel, BlockId::AIR).unwrap();
+            map.insert(ChunkPos::new(x, 0, 0), chunk);
+            map.insert(ChunkPos::new(x, 1, 0), Chunk16::filled(stone));
+        }
+        let chunks: Vec<ChunkPos> = map.iter().map(|(pos, _)| pos).collect();
+        engine.relight_chunks(&mut map, &chunks);
+        assert_eq!(map.light(LightKind::Sky, BlockPos::new(3, 4, 4)), Some(0));
+        assert_eq!(map.light(LightKind::Sky, BlockPos::new(3, 31, 4)), Some(0));
+
+        let at = BlockPos::new(10, 4, 4);
+        assert_eq!(engine.set_block(&mut map, at, torch), Some(BlockId::AIR));
+        for x in 0..32i32 {
+            let expected = 14u8.saturating_sub(x.abs_diff(10) as u8);
+            let pos = BlockPos::new(x, 4, 4);
+            assert_eq!(map.light(LightKind::Block, pos), Some(expected), "{pos:?}");
+            assert_eq!(map.light(LightKind::Sky, pos), Some(0));
+        }
+        for wall in at.neighbors_26() {
+            if wall.y != 4 || wall.z != 4 {
+                assert_eq!(map.light(LightKind::Block, wall), Some(0), "{wall:?}");
+            }
+        }
+
+        // Relighting from scratch gives the same light, also across the border.
+        let all = BlockBox::from_size(BlockSize::splat(16));
+        let before = levels(&map, LightKind::Block, ChunkPos::new(1, 0, 0), all);
+        engine.relight_chunks(&mut map, &chunks);
+        assert_eq!(
+            levels(&map, LightKind::Block, ChunkPos::new(1, 0, 0), all),
+            before
+        );
+        assert_eq!(
+            map.light(LightKind::Block, BlockPos::new(16, 4, 4)),
+            Some(8)
+        );
+
+        // A second torch across the border, then removing the first.
+        engine.set_block(&mut map, BlockPos::new(20, 4, 4), torch);
+        assert_eq!(
+            map.light(LightKind::Block, BlockPos::new(15, 4, 4)),
+            Some(9)
+        );
+        engine.set_block(&mut map, at, BlockId::AIR);
+        for x in 0..32i32 {
+            let expected = 14u8.saturating_sub(x.abs_diff(20) as u8);
+            let pos = BlockPos::new(x, 4, 4);
+            assert_eq!(map.light(LightKind::Block, pos), Some(expected), "{pos:?}");
+        }
+
+        // Walling the torch in leaves it as the only lit block.
+        engine.set_block(&mut map, BlockPos::new(19, 4, 4), stone);
+        engine.set_block(&mut map, BlockPos::new(21, 4, 4), stone);
+        assert_eq!(
+            map.light(LightKind::Block, BlockPos::new(20, 4, 4)),
+            Some(14)
+        );
+        assert_eq!(
+            map.light(LightKind::Block, BlockPos::new(18, 4, 4)),
+            Some(0)
+        );
+        assert_eq!(
+            map.light(LightKind::Block, BlockPos::new(22, 4, 4)),
+            Some(0)
+        );
+        engine.set_block(&mut map, BlockPos::new(20, 4, 4), BlockId::AIR);
+        assert_eq!(
+            map.light(LightKind::Block, BlockPos::new(20, 4, 4)),
+            Some(0)
+        );
+    }
+
+    #[test]
+    fn tunnel_under_overhang() {
+        let Blocks {
+            table,
+            mut map,
+            stone,
+            ..
+        } = blocks();
+        let mut engine = LightEngine::new(table);
+
+        // A stone slab at y = 8 covering x < 24 across two chunks, over open air.
+        for x in [0, 1] {
+            let mut chunk = Chunk16::filled(BlockId::AIR);
+            let width = if x == 0 { 16 } else { 8 };
+            let slab = BlockBox::new(
+                BlockCoord { x: 0, y: 8, z: 0 },
+                BlockSize {
+                    width,
+                    height: 1,
+                    depth: 16,
+                },
+            );
+            chunk.fill(slab, stone).unwrap();
+            map.insert(ChunkPos::new(x, 0, 0), chunk);
+        }
+        engine.relight_chunks(&mut map, &[ChunkPos::new(0, 0, 0), ChunkPos::new(1, 0, 0)]);
+
+        let sky = |map: &ChunkMap, x, y| map.light(LightKind::Sky, BlockPos::new(x, y, 5)).unwrap();
+        assert_eq!(sky(&map, 3, 15), MAX_LIGHT);
+        assert_eq!(sky(&map, 3, 8), 0);
+        // Light under the slab fades with the distance to its edge.
+        let check_under_slab = |map: &ChunkMap| {
+            for x in 0..32i32 {
+                let expected = match x {
+                    24.. => MAX_LIGHT,
+                    _ => MAX_LIGHT.saturating_sub(24 - x as u8),
+                };
+                assert_eq!(sky(map, x, 4), expected, "x = {x}");
+            }
+        };
+        check_under_slab(&map);
+
+        // A hole in the slab lets a full column of sky light through.
+        engine.set_block(&mut map, BlockPos::new(4, 8, 5), BlockId::AIR);
+        for y in 0..16 {
+            assert_eq!(sky(&map, 4, y), MAX_LIGHT, "y = {y}");
+        }
+        assert_eq!(sky(&map, 5, 0), MAX_LIGHT - 1);
+
+        // Covering it again restores the previous light.
+        engine.set_block(&mut map, BlockPos::new(4, 8, 5), stone);
+        check_under_slab(&map);
+
+        // A chunk loaded above the open part casts its shadow once relit.
+        map.insert(ChunkPos::new(1, 1, 0), Chunk16::filled(stone));
+        engine.relight_chunks(&mut map, &[ChunkPos::new(1, 0, 0), ChunkPos::new(1, 1, 0)]);
+        assert_eq!(sky(&map, 28, 4), 0);
+        assert_eq!(sky(&map, 3, 4), 0);
+        assert_eq!(sky(&map, 3, 12), MAX_LIGHT);
+        assert_eq!(sky(&map, 16, 4), 0);
+        assert_eq!(sky(&map, 15, 9), MAX_LIGHT);
+        assert_eq!(sky(&map, 16, 9), MAX_LIGHT - 1);
+    }
+}
@@ -0,0 +1,442 @@
+pub mod engine;
+
+use bevy::prelude::Component;
+use collections::OwnedCut;
+use pack::{
+    part::PartSize,
+    span::{PackAccess, PackAccessMut},
+    vec::PackVec,
+};
+
+use crate::{
+    block::{BlockBox, BlockCoord, BlockSize},
+    chunk::{Chunk, StorageError, check_box, check_buffer, fill, get_index_base},
+    storage::{Decode, DecodeError, Encode, Reader},
+};
+
+/// Brightest light level.
+pub const MAX_LIGHT: u8 = 15;
+
+const LIGHT_BITS: PartSize = PartSize::new(4).unwrap();
+
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq)]
+pub enum LightKind {
+    /// Light from the sky, full strength above the terrain.
+    Sky,
+
+    /// Light emitted by blocks.
+    Block,
+}
+
+impl LightKind {
+    pub const ALL: [LightKind; 2] = [LightKind::Sky, LightKind::Block];
+}
+
+/// Light levels of one kind for every block of a chunk.
+#[derive(Debug, Default)]
+pub enum LightLayer {
+    /// No light anywhere.
+    #[default]
+    Empty,
+
+    /// A single level represents the entire layer.
+    Value(u8),
+
+    /// One 4-bit level per block, in storage order.
+    Packed(PackVec),
+}
+
+impl LightLayer {
+    /// The level of every block, if the layer is not packed.
+    #[inline]
+    pub fn uniform_value(&self) -> Option<u8> {
+        match self {
+            LightLayer::Empty => Some(0),
+            LightLayer::Value(level) => Some(*level),
+            LightLayer::Packed(_) => None,
+        }
+    }
+
+    /// Switches to [`LightLayer::Packed`] seeded with the current level, for writes.
+    fn promote(&mut self, volume: usize) -> &mut PackVec {
+        if let Some(level) = self.uniform_value() {
+            let mut data = PackVec::new_var(LIGHT_BITS);
+            data.extend_with(volume, level.into());
+            *self = LightLayer::Packed(data);
+        }
+        match self {
+            LightLayer::Packed(data) => data,
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// Sky and block light of a `W`x`H`x`D` chunk, see [`Chunk`].
+///
+/// Offsets and boxes follow the block storage order of the chunk.
+#[derive(Component, Debug, Default)]
+pub struct ChunkLight<const W: usize = 16, const H: usize = 16, const D: usize = 16> {
+    sky: LightLayer,
+    block: LightLayer,
+}
+
+impl<const W: usize, const H: usize, const D: usize> ChunkLight<W, H, D> {
+    pub const VOLUME: usize = Chunk::<W, H, D>::VOLUME;
+
+    const SIZE: BlockSize = BlockSize {
+        width: W,
+        height: H,
+        depth: D,
+    };
+
+    /// Every level at zero.
+    pub const fn new() -> Self {
+        Self {
+            sky: LightLayer::Empty,
+            block: LightLayer::Empty,
+        }
+    }
+
+    #[inline]
+    pub fn size(&self) -> BlockSize {
+        Self::SIZE
+    }
+
+    #[inline]
+    pub fn bounds(&self) -> BlockBox {
+        BlockBox::from_size(Self::SIZE)
+    }
+
+    #[inline]
+    pub fn get_offset(&self, x: usize, y: usize, z: usize) -> usize {
+        get_index_base(D, W, y, z) + x
+    }
+
+    #[inline]
+    pub fn layer(&self, kind: LightKind) -> &LightLayer {
+        match kind {
+            LightKind::Sky => &self.sky,
+            LightKind::Block => &self.block,
+        }
+    }
+
+    #[inline]
+    fn layer_mut(&mut self, kind: LightKind) -> &mut LightLayer {
+        match kind {
+            LightKind::Sky => &mut self.sky,
+            LightKind::Block => &mut self.block,
+        }
+    }
+
+    pub fn get_at(&self, kind: LightKind, offset: usize) -> Option<u8> {
+        match self.layer(kind) {
+            LightLayer::Packed(data) => data.get(offset),
+            layer => (offset < Self::VOLUME).then(|| layer.uniform_value().unwrap()),
+        }
+    }
+
+    /// Sets the level at `offset`, returning whether it changed.
+    pub fn set_at(&mut self, kind: LightKind, offset: usize, level: u8) -> Option<bool> {
+        debug_assert!(level <= MAX_LIGHT, "light level {level} is out of range");
+        if offset >= Self::VOLUME {
+            return None;
+        }
+
+        let layer = self.layer_mut(kind);
+        if layer.uniform_value() == Some(level) {
+            return Some(false);
+        }
+        let prev = layer.promote(Self::VOLUME).set(offset, level)?;
+        return Some(prev != level);
+    }
+
+    /// Copies levels of `region` into `dst`, like [`crate::chunk::BlockView::get_slice`].
+    pub fn get_slice(
+        &self,
+        kind: LightKind,
+        region: BlockBox,
+        dst_offset: BlockCoord,
+        dst_bounds: BlockSize,
+        dst: &mut [u8],
+    ) -> Result<(), StorageError> {
+        check_box(region, Self::SIZE)?;
+        check_buffer(
+            BlockBox::new(dst_offset, region.size),
+            dst_bounds,
+            dst.len(),
+        )?;
+        if region.is_empty() {
+            return Ok(());
+        }
+
+        let data = match self.layer(kind) {
+            LightLayer::Packed(data) => data,
+            layer => {
+                let level = layer.uniform_value().unwrap();
+                fill(dst_offset, region.size, level, dst_bounds, dst);
+                return Ok(());
+            }
+        };
+
+        let (offset, size) = (region.min, region.size);
+        for y in 0..size.height {
+            for z in 0..size.depth {
+                let src_idx = self.get_offset(offset.x, offset.y + y, offset.z + z);
+                let dst_y = dst_offset.y + y;
+                let dst_idx =
+                    get_index_base(dst_bounds.depth, dst_bounds.width, dst_y, dst_offset.z + z)
+                        + dst_offset.x;
+                let dst_slice = dst.cut(dst_idx..(dst_idx + size.width));
+                pack::unpack(dst_slice, data.as_slice(), src_idx, LIGHT_BITS);
+            }
+        }
+        return Ok(());
+    }
+
+    /// Copies levels into `region` from `src`, like [`crate::chunk::BlockStorage::set_slice`].
+    pub fn set_slice(
+        &mut self,
+        kind: LightKind,
+        region: BlockBox,
+        src_offset: BlockCoord,
+        src_bounds: BlockSize,
+        src: &[u8],
+    ) -> Result<(), StorageError> {
+        check_box(region, Self::SIZE)?;
+        check_buffer(
+            BlockBox::new(src_offset, region.size),
+            src_bounds,
+            src.len(),
+        )?;
+        debug_assert!(src.iter().all(|level| *level <= MAX_LIGHT));
+        if region.is_empty() {
+            return Ok(());
+        }
+
+        let (offset, size) = (region.min, region.size);
+        let data = self.layer_mut(kind).promote(Self::VOLUME);
+        for y in 0..size.height {
+            for z in 0..size.depth {
+                let src_y = src_offset.y + y;
+                let src_idx =
+                    get_index_base(src_bounds.depth, src_bounds.width, src_y, src_offset.z + z)
+                        + src_offset.x;
+                let src_slice = src.cut(src_idx..(src_idx + size.width));
+                let dst_idx = get_index_base(D, W, offset.y + y, offset.z + z) + offset.x;
+                pack::pack(data.as_slice_mut(), dst_idx, src_slice, LIGHT_BITS);
+            }
+        }
+        return Ok(());
+    }
+
+    pub fn fill(
+        &mut self,
+        kind: LightKind,
+        region: BlockBox,
+        level: u8,
+    ) -> Result<(), StorageError> {
+        debug_assert!(level <= MAX_LIGHT, "light level {level} is out of range");
+        check_box(region, Self::SIZE)?;
+        if region.is_empty() {
+            return Ok(());
+        }
+
+        let layer = self.layer_mut(kind);
+        if region.size == Self::SIZE {
+            *layer = LightLayer::Value(level);
+            return Ok(());
+        }
+        if layer.uniform_value() == Some(level) {
+            return Ok(());
+        }
+
+        let (offset, size) = (region.min, region.size);
+        let data = layer.promote(Self::VOLUME);
+        for y in 0..size.height {
+            for z in 0..size.depth {
+                let dst_idx = get_index_base(D, W, offset.y + y, offset.z + z) + offset.x;
+                data.as_span_mut()
+                    .cut(dst_idx..(dst_idx + size.width))
+                    .fill(level);
+            }
+        }
+        return Ok(());
+    }
+
+    /// Demotes packed layers with a single level to [`LightLayer::Value`].
+    ///
+    /// Returns whether any layer changed.
+    pub fn optimize(&mut self) -> bool {
+        let mut changed = false;
+        for kind in LightKind::ALL {
+            let layer = self.layer_mut(kind);
+            let LightLayer::Packed(data) = layer else {
+                continue;
+            };
+            let Some(first) = data.get::<u8>(0) else {
+                continue;
+            };
+            if data.as_span().all(|level| level == first as u64) {
+                *layer = LightLayer::Value(first);
+                changed = true;
+            }
+        }
+        return changed;
+    }
+
+    /// Bytes used by the light, including heap allocations.
+    pub fn memory_footprint(&self) -> usize {
+        let heap = |layer: &LightLayer| match layer {
+            LightLayer::Packed(data) => size_of_val(data.as_slice()),
+            _ => 0,
+        };
+        return size_of::<Self>() + heap(&self.sky) + heap(&self.block);
+    }
+}
+
+const TAG_EMPTY: u8 = 0;
+const TAG_VALUE: u8 = 1;
+const TAG_PACKED: u8 = 2;
+
+/// Writes each layer as a tag, followed by its level or by two levels per byte,
+/// low nibble first.
+impl<const W: usize, const H: usize, const D: usize> Encode for ChunkLight<W, H, D> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        for kind in LightKind::ALL {
+            match self.layer(kind) {
+                LightLayer::Empty => out.push(TAG_EMPTY),
+                LightLayer::Value(level) => out.extend_from_slice(&[TAG_VALUE, *level]),
+                LightLayer::Packed(data) => {
+                    let mut levels = vec![0u8; Self::VOLUME];
+                    pack::unpack(&mut levels, data.as_slice(), 0, LIGHT_BITS);
+
+                    out.push(TAG_PACKED);
+                    let pairs = levels.chunks(2);
+                    out.extend(pairs.map(|pair| pair[0] | pair.get(1).unwrap_or(&0) << 4));
+                }
+            }
+        }
+    }
+}
+
+impl<const W: usize, const H: usize, const D: usize> Decode for ChunkLight<W, H, D> {
+    fn decode(input: &mut Reader<'_>) -> Result<Self, DecodeError> {
+        let mut light = Self::new();
+        for kind in LightKind::ALL {
+            let layer = match input.read_u8()? {
+                TAG_EMPTY => LightLayer::Empty,
+                TAG_VALUE => match input.read_u8()? {
+                    level @ 0..=MAX_LIGHT => LightLayer::Value(level),
+                    _ => return Err(DecodeError::InvalidValue("light level")),
+                },
+                TAG_PACKED => {
+                    let bytes = input.read_bytes(Self::VOLUME.div_ceil(2))?;
+                    let mut levels: Vec<u8> =
+                        bytes.iter().flat_map(|b| [b & 0xF, b >> 4]).collect();
+                    levels.truncate(Self::VOLUME);
+
+                    let mut data = PackVec::new_var(LIGHT_BITS);
+                    data.extend_with(Self::VOLUME, 0);
+                    pack::pack(data.as_slice_mut(), 0, &levels, LIGHT_BITS);
+                    LightLayer::Packed(data)
+                }
+                tag => return Err(DecodeError::InvalidTag("light layer", tag)),
+            };
+            *light.layer_mut(kind) = layer;
+        }
+        return Ok(light);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{decode_from_slice, encode_to_vec};
+
+    #[test]
+    fn layers() {
+        let mut light = ChunkLight::<16, 16, 16>::new();
+        assert_eq!(light.get_at(LightKind::Sky, 0), Some(0));
+        assert_eq!(
+            light.get_at(LightKind::Sky, ChunkLight::<16, 16, 16>::VOLUME),
+            None
+        );
+        assert_eq!(light.set_at(LightKind::Sky, 0, 0), Some(false));
+        assert!(matches!(light.layer(LightKind::Sky), LightLayer::Empty));
+
+        light
+            .fill(LightKind::Sky, light.bounds(), MAX_LIGHT)
+            .unwrap();
+        assert!(matches!(
+            light.layer(LightKind::Sky),
+            LightLayer::Value(MAX_LIGHT)
+        ));
+        let offset = light.get_offset(1, 2, 3);
+        assert_eq!(light.set_at(LightKind::Sky, offset, 4), Some(true));
+        assert_eq!(light.get_at(LightKind::Sky, offset), Some(4));
+        assert_eq!(light.get_at(LightKind::Sky, offset + 1), Some(MAX_LIGHT));
+        assert!(matches!(light.layer(LightKind::Block), LightLayer::Empty));
+
+        let region = BlockBox::new(BlockCoord { x: 3, y: 1, z: 2 }, BlockSize::splat(3));
+        let src: Vec<u8> = (0..27).map(|i| i % 16).collect();
+        light
+            .set_slice(
+                LightKind::Block,
+                region,
+                BlockCoord::default(),
+                region.size,
+                &src,
+            )
+            .unwrap();
+        let mut dst = vec![0xFF; 27];
+        light
+            .get_slice(
+                LightKind::Block,
+                region,
+                BlockCoord::default(),
+                region.size,
+                &mut dst,
+            )
+            .unwrap();
+        assert_eq!(dst, src);
+        let past_end = BlockBox::new(BlockCoord::splat(15), BlockSize::splat(2));
+        assert!(light.fill(LightKind::Block, past_end, 1).is_err());
+
+        let bytes = encode_to_vec(&light);
+        assert_eq!(bytes.len(), 2 * (1 + ChunkLight::<16, 16, 16>::VOLUME / 2));
+        let decoded: ChunkLight = decode_from_slice(&bytes).unwrap();
+        for kind in LightKind::ALL {
+            let all = |light: &ChunkLight| {
+                let mut levels = vec![0; ChunkLight::<16, 16, 16>::VOLUME];
+                light
+                    .get_slice(
+                        kind,
+                        light.bounds(),
+                        BlockCoord::default(),
+                        light.size(),
+                        &mut levels,
+                    )
+                    .unwrap();
+                levels
+            };
+            assert_eq!(all(&decoded), all(&light));
+        }
+        assert_eq!(
+            decode_from_slice::<ChunkLight>(&bytes[..bytes.len() - 1]).unwrap_err(),
+            DecodeError::UnexpectedEnd
+        );
+        assert_eq!(
+            decode_from_slice::<ChunkLight>(&[TAG_VALUE, 16, TAG_EMPTY]).unwrap_err(),
+            DecodeError::InvalidValue("light level")
+        );
+
+        light.fill(LightKind::Sky, light.bounds(), 0).unwrap();
+        light.fill(LightKind::Block, light.bounds(), 0).unwrap();
+        assert_eq!(encode_to_vec(&light), [TAG_VALUE, 0, TAG_VALUE, 0]);
+        assert!(!light.optimize());
+        light.set_at(LightKind::Block, 0, 3);
+        light.set_at(LightKind::Block, 0, 0);
+        assert!(light.optimize());
+        assert_eq!(light.memory_footprint(), size_of::<ChunkLight>());
+    }
+}
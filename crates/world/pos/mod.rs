@@ -0,0 +1,437 @@
+use std::ops::{Add, AddAssign, Mul, Neg, Sub, SubAssign};
+
+use bevy::math::{IVec3, UVec3, Vec3};
+use collections::{Coord3, NEIGHBORS_26};
+
+use crate::{block::BlockCoord, chunk::Chunk, region::ChunkRegion};
+
+/// One of the six axis-aligned neighbor directions.
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq)]
+pub enum Face {
+    NegX,
+    PosX,
+    NegY,
+    PosY,
+    NegZ,
+    PosZ,
+}
+
+impl Face {
+    pub const ALL: [Face; 6] = [
+        Face::NegX,
+        Face::PosX,
+        Face::NegY,
+        Face::PosY,
+        Face::NegZ,
+        Face::PosZ,
+    ];
+
+    #[inline]
+    pub const fn offset(self) -> IVec3 {
+        match self {
+            Face::NegX => IVec3::NEG_X,
+            Face::PosX => IVec3::X,
+            Face::NegY => IVec3::NEG_Y,
+            Face::PosY => IVec3::Y,
+            Face::NegZ => IVec3::NEG_Z,
+            Face::PosZ => IVec3::Z,
+        }
+    }
+
+    #[inline]
+    pub const fn opposite(self) -> Face {
+        match self {
+            Face::NegX => Face::PosX,
+            Face::PosX => Face::NegX,
+            Face::NegY => Face::PosY,
+            Face::PosY => Face::NegY,
+            Face::NegZ => Face::PosZ,
+            Face::PosZ => Face::NegZ,
+        }
+    }
+
+    /// Axis index, `0` for X, `1` for Y and `2` for Z.
+    #[inline]
+    pub const fn axis(self) -> usize {
+        self as usize / 2
+    }
+}
+
+// Shared by the block, chunk and region grids, which only differ in scale.
+macro_rules! impl_grid_pos {
+    ($name:ident) => {
+        impl $name {
+            pub const ZERO: Self = Self::new(0, 0, 0);
+
+            #[inline]
+            pub const fn new(x: i32, y: i32, z: i32) -> Self {
+                Self { x, y, z }
+            }
+
+            #[inline]
+            pub const fn splat(value: i32) -> Self {
+                Self::new(value, value, value)
+            }
+
+            #[inline]
+            pub fn offset(self, face: Face) -> Self {
+                self + Self::from(face.offset())
+            }
+
+            /// The six face neighbors, ordered like [`Face::ALL`].
+            pub fn neighbors_6(self) -> [Self; 6] {
+                Face::ALL.map(|face| self.offset(face))
+            }
+
+            /// The 26 surrounding positions, ordered like [`NEIGHBORS_26`].
+            pub fn neighbors_26(self) -> [Self; 26] {
+                NEIGHBORS_26.map(|[x, y, z]| self + Self::new(x, y, z))
+            }
+
+            #[inline]
+            pub fn manhattan_distance(self, other: Self) -> u32 {
+                self.x.abs_diff(other.x) + self.y.abs_diff(other.y) + self.z.abs_diff(other.z)
+            }
+
+            #[inline]
+            pub fn chebyshev_distance(self, other: Self) -> u32 {
+                let dx = self.x.abs_diff(other.x);
+                let dy = self.y.abs_diff(other.y);
+                let dz = self.z.abs_diff(other.z);
+                dx.max(dy).max(dz)
+            }
+
+            #[inline]
+            pub fn min(self, other: Self) -> Self {
+                Self::new(
+                    self.x.min(other.x),
+                    self.y.min(other.y),
+                    self.z.min(other.z),
+                )
+            }
+
+            #[inline]
+            pub fn max(self, other: Self) -> Self {
+                Self::new(
+                    self.x.max(other.x),
+                    self.y.max(other.y),
+                    self.z.max(other.z),
+                )
+            }
+        }
+
+        impl Add for $name {
+            type Output = Self;
+
+            #[inline]
+            fn add(self, rhs: Self) -> Self {
+                Self::new(self.x + rhs.x, self.y + rhs.y, self.z + rhs.z)
+            }
+        }
+
+        impl Sub for $name {
+            type Output = Self;
+
+            #[inline]
+            fn sub(self, rhs: Self) -> Self {
+                Self::new(self.x - rhs.x, self.y - rhs.y, self.z - rhs.z)
+            }
+        }
+
+        impl Mul<i32> for $name {
+            type Output = Self;
+
+            #[inline]
+            fn mul(self, rhs: i32) -> Self {
+                Self::new(self.x * rhs, self.y * rhs, self.z * rhs)
+            }
+        }
+
+        impl Neg for $name {
+            type Output = Self;
+
+            #[inline]
+            fn neg(self) -> Self {
+                Self::new(-self.x, -self.y, -self.z)
+            }
+        }
+
+        impl AddAssign for $name {
+            #[inline]
+            fn add_assign(&mut self, rhs: Self) {
+                *self = *self + rhs;
+            }
+        }
+
+        impl SubAssign for $name {
+            #[inline]
+            fn sub_assign(&mut self, rhs: Self) {
+                *self = *self - rhs;
+            }
+        }
+
+        impl From<IVec3> for $name {
+            #[inline]
+            fn from(v: IVec3) -> Self {
+                Self::new(v.x, v.y, v.z)
+            }
+        }
+
+        impl From<$name> for IVec3 {
+            #[inline]
+            fn from(p: $name) -> Self {
+                IVec3::new(p.x, p.y, p.z)
+            }
+        }
+
+        impl Coord3 for $name {
+            #[inline]
+            fn to_xyz(self) -> [i32; 3] {
+                [self.x, self.y, self.z]
+            }
+
+            #[inline]
+            fn from_xyz([x, y, z]: [i32; 3]) -> Self {
+                Self::new(x, y, z)
+            }
+        }
+    };
+}
+
+/// Signed world-space block position.
+#[derive(Clone, Copy, Debug, Default, Hash, Eq, PartialEq, PartialOrd, Ord)]
+pub struct BlockPos {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+/// Signed position of a chunk, in chunks.
+#[derive(Clone, Copy, Debug, Default, Hash, Eq, PartialEq, PartialOrd, Ord)]
+pub struct ChunkPos {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+/// Signed position of a chunk region, in regions.
+#[derive(Clone, Copy, Debug, Default, Hash, Eq, PartialEq, PartialOrd, Ord)]
+pub struct RegionPos {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+impl_grid_pos!(BlockPos);
+impl_grid_pos!(ChunkPos);
+impl_grid_pos!(RegionPos);
+
+/// Size of a `Chunk<W, H, D>` in blocks.
+const fn chunk_size<const W: usize, const H: usize, const D: usize>() -> IVec3 {
+    IVec3::new(
+        Chunk::<W, H, D>::WIDTH.get() as i32,
+        Chunk::<W, H, D>::HEIGHT.get() as i32,
+        Chunk::<W, H, D>::DEPTH.get() as i32,
+    )
+}
+
+const REGION_SIZE: IVec3 = IVec3::new(
+    ChunkRegion::WIDTH.get() as i32,
+    ChunkRegion::HEIGHT.get() as i32,
+    ChunkRegion::DEPTH.get() as i32,
+);
+
+impl BlockPos {
+    /// The block containing the world-space point `v`.
+    #[inline]
+    pub fn containing(v: Vec3) -> Self {
+        Self::from(v.floor().as_ivec3())
+    }
+
+    /// World-space center of the block.
+    #[inline]
+    pub fn center(self) -> Vec3 {
+        Vec3::from(self) + Vec3::splat(0.5)
+    }
+
+    /// The 16³ chunk containing the block, see [`BlockPos::chunk_in`] for other sizes.
+    #[inline]
+    pub fn chunk(self) -> ChunkPos {
+        self.chunk_in::<16, 16, 16>()
+    }
+
+    /// Offset of the block within its 16³ chunk.
+    #[inline]
+    pub fn local(self) -> BlockCoord {
+        self.local_in::<16, 16, 16>()
+    }
+
+    #[inline]
+    pub fn split(self) -> (ChunkPos, BlockCoord) {
+        self.split_in::<16, 16, 16>()
+    }
+
+    /// The `Chunk<W, H, D>` containing the block.
+    #[inline]
+    pub fn chunk_in<const W: usize, const H: usize, const D: usize>(self) -> ChunkPos {
+        ChunkPos::from(IVec3::from(self).div_euclid(chunk_size::<W, H, D>()))
+    }
+
+    /// Offset of the block within its `Chunk<W, H, D>`.
+    #[inline]
+    pub fn local_in<const W: usize, const H: usize, const D: usize>(self) -> BlockCoord {
+        let local = IVec3::from(self)
+            .rem_euclid(chunk_size::<W, H, D>())
+            .as_uvec3();
+        BlockCoord {
+            x: local.x as usize,
+            y: local.y as usize,
+            z: local.z as usize,
+        }
+    }
+
+    #[inline]
+    pub fn split_in<const W: usize, const H: usize, const D: usize>(
+        self,
+    ) -> (ChunkPos, BlockCoord) {
+        (self.chunk_in::<W, H, D>(), self.local_in::<W, H, D>())
+    }
+
+    #[inline]
+    pub fn region(self) -> RegionPos {
+        self.chunk().region()
+    }
+}
+
+impl From<BlockPos> for Vec3 {
+    /// The minimum corner of the block.
+    #[inline]
+    fn from(p: BlockPos) -> Self {
+        IVec3::from(p).as_vec3()
+    }
+}
+
+impl ChunkPos {
+    /// The chunk containing the world-space point `v`.
+    #[inline]
+    pub fn containing(v: Vec3) -> Self {
+        BlockPos::containing(v).chunk()
+    }
+
+    /// The minimum block of the chunk, for 16³ chunks.
+    #[inline]
+    pub fn origin(self) -> BlockPos {
+        self.origin_in::<16, 16, 16>()
+    }
+
+    /// World position of the block at `local` within the chunk, for 16³ chunks.
+    #[inline]
+    pub fn block(self, local: BlockCoord) -> BlockPos {
+        self.block_in::<16, 16, 16>(local)
+    }
+
+    /// The minimum block of the chunk, for `Chunk<W, H, D>`.
+    #[inline]
+    pub fn origin_in<const W: usize, const H: usize, const D: usize>(self) -> BlockPos {
+        BlockPos::from(IVec3::from(self) * chunk_size::<W, H, D>())
+    }
+
+    /// World position of the block at `local` within the chunk, for `Chunk<W, H, D>`.
+    #[inline]
+    pub fn block_in<const W: usize, const H: usize, const D: usize>(
+        self,
+        local: BlockCoord,
+    ) -> BlockPos {
+        let local = IVec3::new(local.x as i32, local.y as i32, local.z as i32);
+        self.origin_in::<W, H, D>() + BlockPos::from(local)
+    }
+
+    #[inline]
+    pub fn region(self) -> RegionPos {
+        RegionPos::from(IVec3::from(self).div_euclid(REGION_SIZE))
+    }
+
+    /// Offset of the chunk within its region.
+    #[inline]
+    pub fn region_local(self) -> UVec3 {
+        IVec3::from(self).rem_euclid(REGION_SIZE).as_uvec3()
+    }
+}
+
+impl RegionPos {
+    /// The minimum chunk of the region.
+    #[inline]
+    pub fn origin(self) -> ChunkPos {
+        ChunkPos::from(IVec3::from(self) * REGION_SIZE)
+    }
+
+    #[inline]
+    pub fn chunk(self, local: UVec3) -> ChunkPos {
+        self.origin() + ChunkPos::from(local.as_ivec3())
+    }
+
+    #[inline]
+    pub fn contains(self, chunk: ChunkPos) -> bool {
+        chunk.region() == self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk16;
+
+    #[test]
+    fn chunk_split() {
+        let w = Chunk16::WIDTH.get() as i32;
+        for x in [-2 * w - 1, -w - 1, -w, -1, 0, 1, w - 1, w, 3 * w + 5] {
+            let pos = BlockPos::new(x, -x, x / 2);
+            let (chunk, local) = pos.split();
+            assert_eq!(chunk.block(local), pos, "{pos:?}");
+            assert!(local.x < Chunk16::WIDTH.get() && local.y < Chunk16::HEIGHT.get());
+        }
+
+        assert_eq!(BlockPos::new(-1, 0, 0).chunk(), ChunkPos::new(-1, 0, 0));
+        assert_eq!(BlockPos::new(-1, 0, 0).local().x, Chunk16::WIDTH.get() - 1);
+        assert_eq!(BlockPos::new(-w, 0, 0).chunk(), ChunkPos::new(-1, 0, 0));
+
+        let pos = BlockPos::new(-1, 300, 17);
+        let (chunk, local) = pos.split_in::<16, 256, 16>();
+        assert_eq!(chunk, ChunkPos::new(-1, 1, 1));
+        assert_eq!(local, BlockCoord { x: 15, y: 44, z: 1 });
+        assert_eq!(chunk.block_in::<16, 256, 16>(local), pos);
+        assert_eq!(pos.chunk_in::<32, 32, 32>(), ChunkPos::new(-1, 9, 0));
+        assert_eq!(
+            BlockPos::containing(Vec3::new(-0.5, 0.5, 1.0)),
+            BlockPos::new(-1, 0, 1)
+        );
+    }
+
+    #[test]
+    fn regions() {
+        let r = ChunkRegion::WIDTH.get() as i32;
+        let chunk = ChunkPos::new(-1, r, 2 * r + 3);
+        assert_eq!(chunk.region(), RegionPos::new(-1, 1, 2));
+        assert_eq!(chunk.region_local(), UVec3::new(r as u32 - 1, 0, 3));
+        assert_eq!(chunk.region().chunk(chunk.region_local()), chunk);
+        assert!(chunk.region().contains(chunk));
+        assert_eq!(chunk.origin().region(), chunk.region());
+    }
+
+    #[test]
+    fn offsets() {
+        let pos = BlockPos::new(1, -2, 3);
+        for face in Face::ALL {
+            assert_eq!(pos.offset(face).offset(face.opposite()), pos);
+            assert_eq!(pos.offset(face).manhattan_distance(pos), 1);
+        }
+        assert!(
+            pos.neighbors_26()
+                .iter()
+                .all(|n| n.chebyshev_distance(pos) == 1)
+        );
+        assert_eq!(pos.manhattan_distance(-pos), 12);
+        assert_eq!(pos.chebyshev_distance(-pos), 6);
+        assert_eq!(IVec3::from(pos * 2 - pos), IVec3::new(1, -2, 3));
+    }
+}
@@ -1,10 +1,30 @@
+use std::num::NonZeroUsize;
+
 use bevy::prelude::Component;
 
+use crate::pos::RegionPos;
+
 #[derive(Component, Debug, Default)]
 pub struct ChunkRegionLocation {
-    pub x: u32,
-    pub y: u32,
-    pub z: u32,
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+impl From<RegionPos> for ChunkRegionLocation {
+    fn from(pos: RegionPos) -> Self {
+        Self {
+            x: pos.x,
+            y: pos.y,
+            z: pos.z,
+        }
+    }
+}
+
+impl From<&ChunkRegionLocation> for RegionPos {
+    fn from(location: &ChunkRegionLocation) -> Self {
+        RegionPos::new(location.x, location.y, location.z)
+    }
 }
 
 #[derive(Component)]
@@ -12,3 +32,10 @@ pub struct ChunkRegionLocation {
 pub struct ChunkRegion {
     
 }
+
+impl ChunkRegion {
+    // Size in chunks.
+    pub const WIDTH: NonZeroUsize = NonZeroUsize::new(32).unwrap();
+    pub const HEIGHT: NonZeroUsize = NonZeroUsize::new(32).unwrap();
+    pub const DEPTH: NonZeroUsize = NonZeroUsize::new(32).unwrap();
+}
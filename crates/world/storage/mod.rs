@@ -1 +1,110 @@
+//! Compact binary encoding of world data, for saving and networking.
+//!
+//! Integers are little-endian. Formats assume both sides agree on chunk dimensions.
 
+use std::{error::Error, fmt};
+
+pub trait Encode {
+    fn encode(&self, out: &mut Vec<u8>);
+}
+
+pub trait Decode: Sized {
+    fn decode(input: &mut Reader<'_>) -> Result<Self, DecodeError>;
+}
+
+/// Encodes `value` into a new buffer.
+pub fn encode_to_vec<T: Encode + ?Sized>(value: &T) -> Vec<u8> {
+    let mut out = Vec::new();
+    value.encode(&mut out);
+    return out;
+}
+
+/// Decodes a `T` spanning all of `bytes`.
+pub fn decode_from_slice<T: Decode>(bytes: &[u8]) -> Result<T, DecodeError> {
+    let mut input = Reader::new(bytes);
+    let value = T::decode(&mut input)?;
+    if !input.is_empty() {
+        return Err(DecodeError::TrailingBytes(input.remaining()));
+    }
+    return Ok(value);
+}
+
+/// Cursor over encoded bytes.
+#[derive(Clone, Debug)]
+pub struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    #[inline]
+    pub const fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    #[inline]
+    pub const fn remaining(&self) -> usize {
+        self.bytes.len()
+    }
+
+    #[inline]
+    pub const fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        let Some((bytes, rest)) = self.bytes.split_at_checked(len) else {
+            return Err(DecodeError::UnexpectedEnd);
+        };
+        self.bytes = rest;
+        return Ok(bytes);
+    }
+
+    #[inline]
+    pub fn read_array<const N: usize>(&mut self) -> Result<[u8; N], DecodeError> {
+        let bytes = self.read_bytes(N)?;
+        return Ok(bytes.try_into().unwrap());
+    }
+
+    #[inline]
+    pub fn read_u8(&mut self) -> Result<u8, DecodeError> {
+        return Ok(self.read_array::<1>()?[0]);
+    }
+
+    #[inline]
+    pub fn read_u16(&mut self) -> Result<u16, DecodeError> {
+        return Ok(u16::from_le_bytes(self.read_array()?));
+    }
+
+    #[inline]
+    pub fn read_u32(&mut self) -> Result<u32, DecodeError> {
+        return Ok(u32::from_le_bytes(self.read_array()?));
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum DecodeError {
+    /// The input ended in the middle of a value.
+    UnexpectedEnd,
+
+    /// Unknown variant tag of the named type.
+    InvalidTag(&'static str, u8),
+
+    /// A value is out of range for the named type.
+    InvalidValue(&'static str),
+
+    /// Bytes were left after the value.
+    TrailingBytes(usize),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::UnexpectedEnd => f.write_str("unexpected end of input"),
+            DecodeError::InvalidTag(name, tag) => write!(f, "invalid {name} tag {tag}"),
+            DecodeError::InvalidValue(name) => write!(f, "invalid {name}"),
+            DecodeError::TrailingBytes(len) => write!(f, "{len} trailing bytes"),
+        }
+    }
+}
+
+impl Error for DecodeError {}